@@ -69,15 +69,10 @@ function DeploymentModal({ isOpen, onClose }) {
       setHostFileCount(masterHostFileList.length);
       setStepStatus(prev => [prev[0], prev[1], 'success', ...prev.slice(3)]);
 
-      // Step 4: Backup current host file
+      // Steps 4 and 5: save_hosts_file backs up the current host file, then saves the new one
       setCurrentStep(3);
-      await backupCurrentHostsFile();
-      setStepStatus(prev => [prev[0], prev[1], prev[2], 'success', ...prev.slice(4)]);
-
-      // Step 5: Save new host file
-      setCurrentStep(4);
       await saveNewHostsFile(masterHostFileList);
-      setStepStatus(prev => [prev[0], prev[1], prev[2], prev[3], 'success', ...prev.slice(5)]);
+      setStepStatus(prev => [prev[0], prev[1], prev[2], 'success', 'success', ...prev.slice(5)]);
 
       // Step 6: Done
       setCurrentStep(5);
@@ -341,16 +336,6 @@ function DeploymentModal({ isOpen, onClose }) {
     }
   };
 
-  // Helper function to backup current hosts file
-  const backupCurrentHostsFile = async () => {
-    try {
-      await invoke('backup_current_hosts_file');
-      console.log('Hosts file backed up successfully');
-    } catch (error) {
-      throw new Error(`Failed to backup hosts file: ${error}`);
-    }
-  };
-
   // Helper function to save new hosts file
   const saveNewHostsFile = async (masterHostFileList) => {
     try {
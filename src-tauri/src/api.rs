@@ -1,14 +1,20 @@
 use actix_web::{web, HttpRequest, HttpResponse, Responder};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
 use chrono::{DateTime, Utc};
 use crate::auth;
-use std::fs::{File, OpenOptions};
-use std::io::{BufRead, BufReader, Write};
-use regex::Regex;
-use std::path::Path;
-use std::time::{SystemTime, UNIX_EPOCH, Duration};
+use crate::backups::{self, Backup, BackupDiff, BackupError, BackupFile, BackupLabel, BackupOrigin, PruneReport, RestoreResult};
+use crate::compiler::CompileStats;
+use crate::config::{self, Config};
+use crate::deploy::{self, HostsPreview};
+use crate::hosts::{self, HostsFile};
+use crate::paths;
+use crate::privilege::{self, PrivilegedAction};
+use crate::domains;
+use crate::formats::{self, ListFormat};
+use crate::guard::{self, GuardReport};
+use crate::sources::{self, RefreshOutcome, RefreshResult, Source, SourceKind};
+use crate::store::{self, ListKind, ListQuery};
 
 // Data structures
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -16,6 +22,8 @@ pub struct HostEntry {
     pub id: String,
     pub ip: String,
     pub hostname: String,
+    #[serde(default)]
+    pub aliases: Vec<String>,
     pub comment: Option<String>,
     pub enabled: bool,
     pub created_at: DateTime<Utc>,
@@ -26,45 +34,220 @@ pub struct HostEntry {
 pub struct CreateHostEntryRequest {
     pub ip: String,
     pub hostname: String,
+    #[serde(default)]
+    pub aliases: Vec<String>,
     pub comment: Option<String>,
     pub enabled: Option<bool>,
 }
 
+impl CreateHostEntryRequest {
+    fn to_entry(&self) -> hosts::Entry {
+        hosts::Entry {
+            ip: self.ip.clone(),
+            hostname: self.hostname.clone(),
+            aliases: self.aliases.clone(),
+            comment: self.comment.clone(),
+            enabled: self.enabled.unwrap_or(true),
+        }
+    }
+}
+
+// Partial update; an empty comment removes the existing one
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateHostEntryRequest {
+    pub ip: Option<String>,
+    pub hostname: Option<String>,
+    pub aliases: Option<Vec<String>>,
+    pub comment: Option<String>,
+    pub enabled: Option<bool>,
+}
+
+impl UpdateHostEntryRequest {
+    fn apply_to(&self, entry: &mut hosts::Entry) {
+        if let Some(ref ip) = self.ip {
+            entry.ip = ip.clone();
+        }
+        if let Some(ref hostname) = self.hostname {
+            entry.hostname = hostname.clone();
+        }
+        if let Some(ref aliases) = self.aliases {
+            entry.aliases = aliases.clone();
+        }
+        if let Some(ref comment) = self.comment {
+            entry.comment = if comment.is_empty() { None } else { Some(comment.clone()) };
+        }
+        if let Some(enabled) = self.enabled {
+            entry.enabled = enabled;
+        }
+    }
+}
 
+// A single operation in a bulk request, tagged by "op"
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum HostOperation {
+    Create {
+        #[serde(flatten)]
+        entry: CreateHostEntryRequest,
+    },
+    Replace {
+        id: String,
+        #[serde(flatten)]
+        entry: CreateHostEntryRequest,
+    },
+    Update {
+        id: String,
+        #[serde(flatten)]
+        changes: UpdateHostEntryRequest,
+    },
+    Delete {
+        id: String,
+    },
+    Toggle {
+        id: String,
+    },
+}
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BulkHostOperationsRequest {
+    pub operations: Vec<HostOperation>,
+}
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HostOperationsResult {
+    // Created or modified entries, with their IDs after the write
+    pub entries: Vec<HostEntry>,
+    // Removed entries, as they were before the write
+    pub deleted: Vec<HostEntry>,
+    // Set when the write preflight re-injected protected entries
+    pub guard: Option<GuardReport>,
+}
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct BackupConfig {
-    pub id: String,
-    pub name: String,
-    pub description: Option<String>,
-    pub created_at: DateTime<Utc>,
-    pub entries_count: usize,
+// A single written entry; `guard` is set when the write preflight re-injected protected entries
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GuardedHostEntry {
+    #[serde(flatten)]
+    pub entry: HostEntry,
+    pub guard: Option<GuardReport>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CreateBackupRequest {
-    pub name: String,
+    pub name: Option<String>,
     pub description: Option<String>,
+    // Defaults to manual; schedulers calling the API pass `scheduled`
+    #[serde(default)]
+    pub origin: BackupOrigin,
+    // Pinned backups are never pruned
+    #[serde(default)]
+    pub pinned: bool,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct AllowListEntry {
-    pub id: String,
+pub type AllowListEntry = store::ListEntry;
+pub type BlockListEntry = store::ListEntry;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateListEntryRequest {
     pub pattern: String,
     pub description: Option<String>,
-    pub enabled: bool,
-    pub created_at: DateTime<Utc>,
+    pub enabled: Option<bool>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct BlockListEntry {
-    pub id: String,
-    pub pattern: String,
+// Partial update; an empty description removes the existing one
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct UpdateListEntryRequest {
+    pub pattern: Option<String>,
     pub description: Option<String>,
-    pub enabled: bool,
-    pub created_at: DateTime<Utc>,
+    pub enabled: Option<bool>,
+}
+
+impl From<CreateListEntryRequest> for UpdateListEntryRequest {
+    // A full replacement: unset fields go back to their defaults
+    fn from(request: CreateListEntryRequest) -> Self {
+        UpdateListEntryRequest {
+            pattern: Some(request.pattern),
+            description: Some(request.description.unwrap_or_default()),
+            enabled: Some(request.enabled.unwrap_or(true)),
+        }
+    }
+}
+
+// Bulk import: a pasted list in `text` and/or a `patterns` array
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImportListEntriesRequest {
+    pub text: Option<String>,
+    // Format of `text`. Block lists detect it when omitted; allow lists then
+    // read `text` as one pattern per line so wildcards and regexes survive.
+    pub format: Option<ListFormat>,
+    #[serde(default)]
+    pub patterns: Vec<String>,
+    // Applied to every imported entry
+    pub description: Option<String>,
+    pub enabled: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SkippedPattern {
+    pub pattern: String,
+    pub reason: String,
+    // Line in `text`, when the list parser skipped it
+    pub line: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImportListEntriesResult {
+    pub imported: Vec<store::ListEntry>,
+    pub skipped: Vec<SkippedPattern>,
+    // Format `text` was parsed as; None when it was read as one pattern per line
+    pub format: Option<ListFormat>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct BuildHostsRequest {
+    // Overrides deploy.sink_ip
+    pub sink_ip: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BuildHostsResult {
+    pub stats: CompileStats,
+    pub size_bytes: usize,
+    pub guard: Option<GuardReport>,
+    // Backup of the replaced hosts file; None when there was no hosts file
+    pub snapshot: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateSourceRequest {
+    pub name: String,
+    pub url: String,
+    pub kind: SourceKind,
+    // Detected from the content when omitted
+    pub format: Option<ListFormat>,
+    pub enabled: Option<bool>,
+}
+
+// Partial update of a source subscription
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct UpdateSourceRequest {
+    pub name: Option<String>,
+    pub url: Option<String>,
+    pub kind: Option<SourceKind>,
+    pub format: Option<ListFormat>,
+    pub enabled: Option<bool>,
+}
+
+impl From<CreateSourceRequest> for UpdateSourceRequest {
+    // A full replacement: unset fields go back to their defaults
+    fn from(request: CreateSourceRequest) -> Self {
+        UpdateSourceRequest {
+            name: Some(request.name),
+            url: Some(request.url),
+            kind: Some(request.kind),
+            format: Some(request.format.unwrap_or_default()),
+            enabled: Some(request.enabled.unwrap_or(true)),
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -86,14 +269,8 @@ pub struct PaginatedResponse<T> {
     pub has_prev: bool,
 }
 
-// Global storage for other data (not hosts entries)
-lazy_static::lazy_static! {
-    static ref BACKUPS: Arc<Mutex<HashMap<String, BackupConfig>>> = Arc::new(Mutex::new(HashMap::new()));
-    static ref ALLOW_LISTS: Arc<Mutex<HashMap<String, AllowListEntry>>> = Arc::new(Mutex::new(HashMap::new()));
-    static ref BLOCK_LISTS: Arc<Mutex<HashMap<String, BlockListEntry>>> = Arc::new(Mutex::new(HashMap::new()));
-}
 
-// Helper function to generate UUID
+// Helper function to generate UUID (for records that are not derived from file content)
 fn generate_id() -> String {
     uuid::Uuid::new_v4().to_string()
 }
@@ -103,153 +280,43 @@ fn now() -> DateTime<Utc> {
     Utc::now()
 }
 
-// Parse hosts file content into structured entries
-fn parse_hosts_content(content: &str) -> Vec<HostEntry> {
-    let lines = content.split('\n');
-    let mut entries = Vec::new();
-    let mut _entry_order = 0;
-    
-    // Regex patterns for validation
-    let ipv4_pattern = Regex::new(r"^(?:(?:25[0-5]|2[0-4][0-9]|[01]?[0-9][0-9]?)\.){3}(?:25[0-5]|2[0-4][0-9]|[01]?[0-9][0-9]?)$").unwrap();
-    let ipv6_pattern = Regex::new(r"^(?:(?:[0-9a-fA-F]{1,4}:){7}[0-9a-fA-F]{1,4}|(?:[0-9a-fA-F]{1,4}:){1,7}:|(?:[0-9a-fA-F]{1,4}:){1,6}:[0-9a-fA-F]{1,4}|(?:[0-9a-fA-F]{1,4}:){1,5}(?::[0-9a-fA-F]{1,4}){1,2}|(?:[0-9a-fA-F]{1,4}:){1,4}(?::[0-9a-fA-F]{1,4}){1,3}|(?:[0-9a-fA-F]{1,4}:){1,3}(?::[0-9a-fA-F]{1,4}){1,4}|(?:[0-9a-fA-F]{1,4}:){1,2}(?::[0-9a-fA-F]{1,4}){1,5}|[0-9a-fA-F]{1,4}:(?:(?::[0-9a-fA-F]{1,4}){1,6})|:(?:(?::[0-9a-fA-F]{1,4}){1,7}|:)|fe80:(?::[0-9a-fA-F]{0,4}){0,4}%[0-9a-zA-Z]{1,}|::(?:ffff(?::0{1,4}){0,1}:){0,1}(?:(?:25[0-5]|(?:2[0-4]|1{0,1}[0-9]){0,1}[0-9])\.){3,3}(?:25[0-5]|(?:2[0-4]|1{0,1}[0-9]){0,1}[0-9])|(?:[0-9a-fA-F]{1,4}:){1,4}:(?:(?:25[0-5]|(?:2[0-4]|1{0,1}[0-9]){0,1}[0-9])\.){3,3}(?:25[0-5]|(?:2[0-4]|1{0,1}[0-9]){0,1}[0-9]))$").unwrap();
-    
-    for (index, line) in lines.enumerate() {
-        let trimmed_line = line.trim();
-        
-        // Skip empty lines
-        if trimmed_line.is_empty() {
-            continue;
-        }
-        
-        // Check if line is commented out (disabled)
-        let is_enabled = !trimmed_line.starts_with('#');
-        let working_line = if is_enabled { trimmed_line } else { &trimmed_line[1..].trim() };
-        
-        // Skip pure comments (lines that start with # and don't have valid IP/hostname data)
-        if working_line.is_empty() || working_line.starts_with('#') {
-            continue;
-        }
-        
-        // Parse IP and hostname
-        let parts: Vec<&str> = working_line.split_whitespace().collect();
-        if parts.len() >= 2 {
-            let ip = parts[0];
-            let hostname = parts[1];
-            
-            // Validate IP using regex
-            let is_valid_ip = ipv4_pattern.is_match(ip) || ipv6_pattern.is_match(ip);
-            
-            // More permissive hostname validation
-            let is_valid_hostname = !hostname.is_empty() && 
-                                   !hostname.starts_with('#') && 
-                                   !hostname.contains(char::is_whitespace) &&
-                                   hostname.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '.' || c == '%');
-            
-            // Only add entry if both IP and hostname are valid
-            if is_valid_ip && is_valid_hostname {
-                // Extract comment if present
-                let comment = if parts.len() > 2 {
-                    Some(parts[2..].join(" "))
-                } else {
-                    None
-                };
-                
-                entries.push(HostEntry {
-                    id: format!("entry_{}", index),
-                    ip: ip.to_string(),
-                    hostname: hostname.to_string(),
-                    comment,
-                    enabled: is_enabled,
-                    created_at: now(),
-                    updated_at: now(),
-                });
-                _entry_order += 1;
-            }
-        }
+// Convert a parsed hosts entry into its API representation
+fn to_host_entry(id: String, entry: &hosts::Entry) -> HostEntry {
+    HostEntry {
+        id,
+        ip: entry.ip.clone(),
+        hostname: entry.hostname.clone(),
+        aliases: entry.aliases.clone(),
+        comment: entry.comment.clone(),
+        enabled: entry.enabled,
+        created_at: now(),
+        updated_at: now(),
     }
-    
-    entries
 }
 
-// Read hosts file with streaming support
-fn read_hosts_file_streaming(page: usize, page_size: usize) -> Result<PaginatedResponse<HostEntry>, String> {
-    let hosts_path = "/etc/hosts";
-    let backup_path = "/etc/hosts.backup";
+// Load and parse the hosts file, refusing to operate while it is disabled
+fn load_hosts_file() -> Result<HostsFile, String> {
+    let paths = paths::hosts_paths();
     
-    // Check if hosts file is disabled (backup exists, original doesn't)
-    if Path::new(backup_path).exists() && !Path::new(hosts_path).exists() {
+    if paths.is_disabled() {
         return Err("Hosts file is currently disabled".to_string());
     }
     
-    // Check if file exists
-    if !Path::new(hosts_path).exists() {
-        return Err("Hosts file not found".to_string());
-    }
-    
-    // Regex patterns for validation
-    let ipv4_pattern = Regex::new(r"^(?:(?:25[0-5]|2[0-4][0-9]|[01]?[0-9][0-9]?)\.){3}(?:25[0-5]|2[0-4][0-9]|[01]?[0-9][0-9]?)$").unwrap();
-    let ipv6_pattern = Regex::new(r"^(?:(?:[0-9a-fA-F]{1,4}:){7}[0-9a-fA-F]{1,4}|(?:[0-9a-fA-F]{1,4}:){1,7}:|(?:[0-9a-fA-F]{1,4}:){1,6}:[0-9a-fA-F]{1,4}|(?:[0-9a-fA-F]{1,4}:){1,5}(?::[0-9a-fA-F]{1,4}){1,2}|(?:[0-9a-fA-F]{1,4}:){1,4}(?::[0-9a-fA-F]{1,4}){1,3}|(?:[0-9a-fA-F]{1,4}:){1,3}(?::[0-9a-fA-F]{1,4}){1,4}|(?:[0-9a-fA-F]{1,4}:){1,2}(?::[0-9a-fA-F]{1,4}){1,5}|[0-9a-fA-F]{1,4}:(?:(?::[0-9a-fA-F]{1,4}){1,6})|:(?:(?::[0-9a-fA-F]{1,4}){1,7}|:)|fe80:(?::[0-9a-fA-F]{0,4}){0,4}%[0-9a-zA-Z]{1,}|::(?:ffff(?::0{1,4}){0,1}:){0,1}(?:(?:25[0-5]|(?:2[0-4]|1{0,1}[0-9]){0,1}[0-9])\.){3,3}(?:25[0-5]|(?:2[0-4]|1{0,1}[0-9]){0,1}[0-9])|(?:[0-9a-fA-F]{1,4}:){1,4}:(?:(?:25[0-5]|(?:2[0-4]|1{0,1}[0-9]){0,1}[0-9])\.){3,3}(?:25[0-5]|(?:2[0-4]|1{0,1}[0-9]){0,1}[0-9]))$").unwrap();
-    
-    let file = File::open(hosts_path)
-        .map_err(|_| "Failed to open hosts file".to_string())?;
-    
-    let reader = BufReader::new(file);
-    let mut all_entries = Vec::new();
-    let mut entry_order = 0;
+    hosts::read_hosts_file(&paths.hosts)
+}
+
+// Read a page of host entries
+fn read_hosts_file_streaming(page: usize, page_size: usize) -> Result<PaginatedResponse<HostEntry>, String> {
+    let hosts_file = load_hosts_file()?;
     
-    // Read all entries first to preserve order
-    for (_line_index, line) in reader.lines().enumerate() {
-        if let Ok(line) = line {
-            let trimmed_line = line.trim();
-            
-            if trimmed_line.is_empty() {
-                continue;
-            }
-            
-            let is_enabled = !trimmed_line.starts_with('#');
-            let working_line = if is_enabled { trimmed_line } else { &trimmed_line[1..].trim() };
-            
-            if working_line.is_empty() || working_line.starts_with('#') {
-                continue;
-            }
-            
-            let parts: Vec<&str> = working_line.split_whitespace().collect();
-            if parts.len() >= 2 {
-                let ip = parts[0];
-                let hostname = parts[1];
-                
-                // Validate IP and hostname
-                let is_valid_ip = ipv4_pattern.is_match(ip) || ipv6_pattern.is_match(ip);
-                let is_valid_hostname = !hostname.is_empty() && 
-                                       !hostname.contains(char::is_whitespace) &&
-                                       hostname.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '.' || c == '%');
-                
-                // Only process if both IP and hostname are valid
-                if is_valid_ip && is_valid_hostname {
-                    let comment = if parts.len() > 2 {
-                        Some(parts[2..].join(" "))
-                    } else {
-                        None
-                    };
-                    
-                    all_entries.push(HostEntry {
-                        id: format!("entry_{}", entry_order),
-                        ip: ip.to_string(),
-                        hostname: hostname.to_string(),
-                        comment,
-                        enabled: is_enabled,
-                        created_at: now(),
-                        updated_at: now(),
-                    });
-                    entry_order += 1;
-                }
-            }
-        }
-    }
+    let all_entries: Vec<HostEntry> = hosts_file.entries_with_ids()
+        .into_iter()
+        .map(|(id, entry)| to_host_entry(id, entry))
+        .collect();
     
     let total_entries = all_entries.len();
-    let start_index = page * page_size;
-    let end_index = (start_index + page_size).min(total_entries);
+    let start_index = page.saturating_mul(page_size);
+    let end_index = start_index.saturating_add(page_size).min(total_entries);
     
     // Extract the requested page
     let entries = if start_index < total_entries {
@@ -258,7 +325,7 @@ fn read_hosts_file_streaming(page: usize, page_size: usize) -> Result<PaginatedR
         Vec::new()
     };
     
-    let total_pages = (total_entries + page_size - 1) / page_size;
+    let total_pages = total_entries.div_ceil(page_size.max(1));
     
     Ok(PaginatedResponse {
         data: entries,
@@ -266,146 +333,1259 @@ fn read_hosts_file_streaming(page: usize, page_size: usize) -> Result<PaginatedR
         page,
         page_size,
         total_pages,
-        has_next: page < total_pages - 1,
+        has_next: page + 1 < total_pages,
         has_prev: page > 0,
     })
 }
 
-// Write hosts file with streaming support
-fn write_hosts_file_streaming(entries: Vec<HostEntry>) -> Result<(), String> {
-    let hosts_path = "/etc/hosts";
-    let backup_path = "/etc/hosts.backup";
-    
-    // Check if hosts file is disabled (backup exists, original doesn't)
-    if Path::new(backup_path).exists() && !Path::new(hosts_path).exists() {
-        return Err("Cannot write to hosts file while it is disabled".to_string());
-    }
-    
-    // Create backup first
-    if Path::new(hosts_path).exists() {
-        std::fs::copy(hosts_path, backup_path)
-            .map_err(|_| "Failed to create backup".to_string())?;
-    }
-    
-    // Write new content
-    let mut file = OpenOptions::new()
-        .write(true)
-        .truncate(true)
-        .open(hosts_path)
-        .map_err(|_| "Failed to open hosts file for writing".to_string())?;
-    
-    // Write header
-    writeln!(file, "# HostDNI managed hosts file")
-        .map_err(|_| "Failed to write header".to_string())?;
-    writeln!(file, "# Generated on: {}", now().to_rfc3339())
-        .map_err(|_| "Failed to write timestamp".to_string())?;
-    writeln!(file, "")
-        .map_err(|_| "Failed to write empty line".to_string())?;
-    
-    // Write entries in chunks to avoid memory issues
-    let chunk_size = 1000;
-    for chunk in entries.chunks(chunk_size) {
-        for entry in chunk {
-            let line = if entry.enabled {
-                if let Some(ref comment) = entry.comment {
-                    format!("{} {}\t# {}", entry.ip, entry.hostname, comment)
-                } else {
-                    format!("{} {}", entry.ip, entry.hostname)
-                }
-            } else {
-                if let Some(ref comment) = entry.comment {
-                    format!("# {} {}\t# {}", entry.ip, entry.hostname, comment)
-                } else {
-                    format!("# {} {}", entry.ip, entry.hostname)
-                }
-            };
-            
-            writeln!(file, "{}", line)
-                .map_err(|_| "Failed to write entry".to_string())?;
-        }
-        
-        // Flush after each chunk to ensure data is written
-        file.flush()
-            .map_err(|_| "Failed to flush file".to_string())?;
+// Write a parsed hosts file back atomically,
+// keeping the previous version at the rollback path
+// Runs the write preflight first (see guard.rs); a refusal leaves the file untouched
+fn write_hosts_file(hosts_file: &mut HostsFile) -> Result<Option<GuardReport>, HostOperationError> {
+    let report = guard::preflight(hosts_file, &config::current().protected)
+        .map_err(|report| HostOperationError::Conflict(report.message()))?;
+    deploy::write(&hosts_file.to_string()).map_err(HostOperationError::Failed)?;
+    Ok(report)
+}
+
+// Success message, followed by the guard report when a guard fired
+fn guarded_message(message: &str, guard: &Option<GuardReport>) -> String {
+    match guard {
+        Some(report) => format!("{}. {}", message, report.message()),
+        None => message.to_string(),
+    }
+}
+
+// Get total count of hosts entries
+fn get_hosts_file_count() -> Result<usize, String> {
+    let paths = paths::hosts_paths();
+    
+    // A missing (but not disabled) hosts file simply has no entries
+    if !paths.hosts.exists() && !paths.disabled.exists() {
+        return Ok(0);
+    }
+    
+    Ok(load_hosts_file()?.entry_count())
+}
+
+fn validate_entry(entry: &hosts::Entry) -> Result<(), String> {
+    if !hosts::is_valid_ip(&entry.ip) {
+        return Err(format!("Invalid IP address: {}", entry.ip));
+    }
+    if !hosts::is_valid_hostname(&entry.hostname) {
+        return Err(format!("Invalid hostname: {}", entry.hostname));
+    }
+    if let Some(alias) = entry.aliases.iter().find(|alias| !hosts::is_valid_hostname(alias)) {
+        return Err(format!("Invalid alias: {}", alias));
+    }
+    // The comment is written verbatim after the entry; a line break would add unvalidated lines
+    if entry.comment.as_deref().is_some_and(|comment| comment.chars().any(char::is_control)) {
+        return Err("Invalid comment: line breaks and control characters are not allowed".to_string());
+    }
+    Ok(())
+}
+
+// Failure of a handler's operation; into_response maps it to the HTTP status.
+// Shared by the hosts, backup, list and source handlers.
+enum HostOperationError {
+    NotFound(String),
+    Invalid(String),
+    // A write guard refused the resulting file, or the change clashes with existing data
+    Conflict(String),
+    Failed(String),
+}
+
+impl HostOperationError {
+    fn into_response<T: Serialize>(self) -> HttpResponse {
+        let (mut builder, error) = match self {
+            HostOperationError::NotFound(e) => (HttpResponse::NotFound(), e),
+            HostOperationError::Invalid(e) => (HttpResponse::BadRequest(), e),
+            HostOperationError::Conflict(e) => (HttpResponse::Conflict(), e),
+            HostOperationError::Failed(e) => (HttpResponse::InternalServerError(), e),
+        };
+        builder.json(ApiResponse::<T> {
+            success: false,
+            data: None,
+            message: None,
+            error: Some(error),
+        })
+    }
+}
+
+// Apply a batch of operations to the hosts file and write it back once.
+// Either every operation applies or the file is left untouched.
+fn run_host_operations(operations: Vec<HostOperation>) -> Result<HostOperationsResult, HostOperationError> {
+    let mut hosts_file = load_hosts_file().map_err(HostOperationError::Failed)?;
+    
+    // Resolve IDs against the file as read, so earlier operations in the
+    // batch cannot shift the targets of later ones
+    let ids = hosts_file.entry_ids_by_line();
+    let mut deleted_lines: Vec<usize> = Vec::new();
+    let mut touched_lines: Vec<usize> = Vec::new();
+    let mut deleted = Vec::new();
+    
+    let resolve = |id: &str, deleted_lines: &[usize]| -> Result<usize, HostOperationError> {
+        let line = ids.iter()
+            .find(|(_, entry_id)| entry_id == id)
+            .map(|(line, _)| *line)
+            .ok_or_else(|| HostOperationError::NotFound(format!("Host entry not found: {}", id)))?;
+        if deleted_lines.contains(&line) {
+            return Err(HostOperationError::Invalid(format!("Host entry already deleted in this batch: {}", id)));
+        }
+        Ok(line)
+    };
+    
+    for operation in operations {
+        match operation {
+            HostOperation::Create { entry } => {
+                let entry = entry.to_entry();
+                validate_entry(&entry).map_err(HostOperationError::Invalid)?;
+                hosts_file.push_entry(entry);
+                touched_lines.push(hosts_file.lines.len() - 1);
+            }
+            HostOperation::Replace { id, entry } => {
+                let line = resolve(&id, &deleted_lines)?;
+                let entry = entry.to_entry();
+                validate_entry(&entry).map_err(HostOperationError::Invalid)?;
+                if let Some(existing) = hosts_file.lines[line].as_entry_mut() {
+                    *existing = entry;
+                }
+                touched_lines.push(line);
+            }
+            HostOperation::Update { id, changes } => {
+                let line = resolve(&id, &deleted_lines)?;
+                if let Some(existing) = hosts_file.lines[line].as_entry_mut() {
+                    let mut entry = existing.clone();
+                    changes.apply_to(&mut entry);
+                    validate_entry(&entry).map_err(HostOperationError::Invalid)?;
+                    *existing = entry;
+                }
+                touched_lines.push(line);
+            }
+            HostOperation::Toggle { id } => {
+                let line = resolve(&id, &deleted_lines)?;
+                if let Some(existing) = hosts_file.lines[line].as_entry_mut() {
+                    existing.enabled = !existing.enabled;
+                }
+                touched_lines.push(line);
+            }
+            HostOperation::Delete { id } => {
+                let line = resolve(&id, &deleted_lines)?;
+                if let Some(existing) = hosts_file.lines[line].as_entry() {
+                    deleted.push(to_host_entry(id, existing));
+                }
+                deleted_lines.push(line);
+            }
+        }
+    }
+    
+    // Drop deleted lines, remembering where surviving touched lines end up
+    let mut new_index = vec![None; hosts_file.lines.len()];
+    let mut kept = 0;
+    for (index, slot) in new_index.iter_mut().enumerate() {
+        if !deleted_lines.contains(&index) {
+            *slot = Some(kept);
+            kept += 1;
+        }
+    }
+    hosts_file.lines = std::mem::take(&mut hosts_file.lines)
+        .into_iter()
+        .enumerate()
+        .filter(|(index, _)| new_index[*index].is_some())
+        .map(|(_, line)| line)
+        .collect();
+    
+    let guard = write_hosts_file(&mut hosts_file)?;
+    
+    let ids = hosts_file.entry_ids_by_line();
+    let mut entries: Vec<HostEntry> = Vec::new();
+    let mut seen: Vec<usize> = Vec::new();
+    for line in touched_lines.into_iter().filter_map(|line| new_index[line]) {
+        if seen.contains(&line) {
+            continue;
+        }
+        seen.push(line);
+        if let (Some((_, id)), Some(entry)) = (ids.iter().find(|(index, _)| *index == line), hosts_file.lines[line].as_entry()) {
+            entries.push(to_host_entry(id.clone(), entry));
+        }
+    }
+    
+    Ok(HostOperationsResult { entries, deleted, guard })
+}
+
+// ===== HOST ENTRIES ENDPOINTS =====
+
+// GET /api/etc/hosts - Get paginated host entries from the hosts file
+pub async fn get_host_entries(req: HttpRequest) -> impl Responder {
+    if !auth::validate_token(&req) {
+        return HttpResponse::Unauthorized().json(ApiResponse::<PaginatedResponse<HostEntry>> {
+            success: false,
+            data: None,
+            message: None,
+            error: Some("Invalid or missing API token".to_string()),
+        });
+    }
+    
+    // ?page=&page_size=
+    let params = query_params(&req);
+    let page = params.get("page").and_then(|value| value.parse::<usize>().ok()).unwrap_or(0);
+    let page_size = config::current().page_size(params.get("page_size").and_then(|value| value.parse().ok())); // Clamped to limits.min/max_page_size
+    
+    match read_hosts_file_streaming(page, page_size) {
+        Ok(paginated_data) => {
+            HttpResponse::Ok().json(ApiResponse {
+                success: true,
+                data: Some(paginated_data),
+                message: None,
+                error: None,
+            })
+        }
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse::<PaginatedResponse<HostEntry>> {
+            success: false,
+            data: None,
+            message: None,
+            error: Some(e),
+        }),
+    }
+}
+
+// POST /api/etc/hosts - Add a new host entry to the hosts file
+pub async fn create_host_entry(
+    req: HttpRequest,
+    entry_req: web::Json<CreateHostEntryRequest>,
+) -> impl Responder {
+    if !auth::validate_token(&req) {
+        return HttpResponse::Unauthorized().json(ApiResponse::<GuardedHostEntry> {
+            success: false,
+            data: None,
+            message: None,
+            error: Some("Invalid or missing API token".to_string()),
+        });
+    }
+    
+    match run_host_operations(vec![HostOperation::Create { entry: entry_req.into_inner() }]) {
+        Ok(mut result) => {
+            HttpResponse::Created().json(ApiResponse {
+                success: true,
+                data: result.entries.pop().map(|entry| GuardedHostEntry { entry, guard: result.guard.clone() }),
+                message: Some(guarded_message("Host entry created successfully", &result.guard)),
+                error: None,
+            })
+        }
+        Err(e) => e.into_response::<GuardedHostEntry>(),
+    }
+}
+
+// PUT /api/etc/hosts/{id} - Replace a host entry
+pub async fn replace_host_entry(
+    req: HttpRequest,
+    path: web::Path<String>,
+    entry_req: web::Json<CreateHostEntryRequest>,
+) -> impl Responder {
+    if !auth::validate_token(&req) {
+        return HttpResponse::Unauthorized().json(ApiResponse::<GuardedHostEntry> {
+            success: false,
+            data: None,
+            message: None,
+            error: Some("Invalid or missing API token".to_string()),
+        });
+    }
+    
+    let operation = HostOperation::Replace { id: path.into_inner(), entry: entry_req.into_inner() };
+    match run_host_operations(vec![operation]) {
+        Ok(mut result) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            data: result.entries.pop().map(|entry| GuardedHostEntry { entry, guard: result.guard.clone() }),
+            message: Some(guarded_message("Host entry updated successfully", &result.guard)),
+            error: None,
+        }),
+        Err(e) => e.into_response::<GuardedHostEntry>(),
+    }
+}
+
+// PATCH /api/etc/hosts/{id} - Update selected fields of a host entry
+pub async fn update_host_entry(
+    req: HttpRequest,
+    path: web::Path<String>,
+    update_req: web::Json<UpdateHostEntryRequest>,
+) -> impl Responder {
+    if !auth::validate_token(&req) {
+        return HttpResponse::Unauthorized().json(ApiResponse::<GuardedHostEntry> {
+            success: false,
+            data: None,
+            message: None,
+            error: Some("Invalid or missing API token".to_string()),
+        });
+    }
+    
+    let operation = HostOperation::Update { id: path.into_inner(), changes: update_req.into_inner() };
+    match run_host_operations(vec![operation]) {
+        Ok(mut result) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            data: result.entries.pop().map(|entry| GuardedHostEntry { entry, guard: result.guard.clone() }),
+            message: Some(guarded_message("Host entry updated successfully", &result.guard)),
+            error: None,
+        }),
+        Err(e) => e.into_response::<GuardedHostEntry>(),
+    }
+}
+
+// DELETE /api/etc/hosts/{id} - Remove a host entry
+pub async fn delete_host_entry(req: HttpRequest, path: web::Path<String>) -> impl Responder {
+    if !auth::validate_token(&req) {
+        return HttpResponse::Unauthorized().json(ApiResponse::<GuardedHostEntry> {
+            success: false,
+            data: None,
+            message: None,
+            error: Some("Invalid or missing API token".to_string()),
+        });
+    }
+    
+    match run_host_operations(vec![HostOperation::Delete { id: path.into_inner() }]) {
+        Ok(mut result) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            data: result.deleted.pop().map(|entry| GuardedHostEntry { entry, guard: result.guard.clone() }),
+            message: Some(guarded_message("Host entry deleted successfully", &result.guard)),
+            error: None,
+        }),
+        Err(e) => e.into_response::<GuardedHostEntry>(),
+    }
+}
+
+// POST /api/etc/hosts/{id}/toggle - Enable or disable (comment out) a host entry
+pub async fn toggle_host_entry(req: HttpRequest, path: web::Path<String>) -> impl Responder {
+    if !auth::validate_token(&req) {
+        return HttpResponse::Unauthorized().json(ApiResponse::<GuardedHostEntry> {
+            success: false,
+            data: None,
+            message: None,
+            error: Some("Invalid or missing API token".to_string()),
+        });
+    }
+    
+    match run_host_operations(vec![HostOperation::Toggle { id: path.into_inner() }]) {
+        Ok(mut result) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            data: result.entries.pop().map(|entry| GuardedHostEntry { entry, guard: result.guard.clone() }),
+            message: Some(guarded_message("Host entry toggled successfully", &result.guard)),
+            error: None,
+        }),
+        Err(e) => e.into_response::<GuardedHostEntry>(),
+    }
+}
+
+// POST /api/etc/hosts/bulk - Apply a batch of operations atomically
+pub async fn bulk_host_operations(
+    req: HttpRequest,
+    bulk_req: web::Json<BulkHostOperationsRequest>,
+) -> impl Responder {
+    if !auth::validate_token(&req) {
+        return HttpResponse::Unauthorized().json(ApiResponse::<HostOperationsResult> {
+            success: false,
+            data: None,
+            message: None,
+            error: Some("Invalid or missing API token".to_string()),
+        });
+    }
+    
+    let operations_count = bulk_req.operations.len();
+    match run_host_operations(bulk_req.into_inner().operations) {
+        Ok(result) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            message: Some(guarded_message(&format!("Applied {} operations", operations_count), &result.guard)),
+            data: Some(result),
+            error: None,
+        }),
+        Err(e) => e.into_response::<HostOperationsResult>(),
+    }
+}
+
+// ===== BACKUPS ENDPOINTS =====
+
+// GET /api/backups/folder-status - Check if the backup repository folder exists and create if needed
+pub async fn get_backup_folder_status(req: HttpRequest) -> impl Responder {
+    if !auth::validate_token(&req) {
+        return HttpResponse::Unauthorized().json(ApiResponse::<serde_json::Value> {
+            success: false,
+            data: None,
+            message: None,
+            error: Some("Invalid or missing API token".to_string()),
+        });
+    }
+    
+    // The backup repository lives at backups.dir
+    let repository = backups::repository();
+    let folder_created = !repository.root().exists();
+    let error_message = repository.ensure_root().err();
+    
+    HttpResponse::Ok().json(ApiResponse {
+        success: error_message.is_none(),
+        data: Some(serde_json::json!({
+            "hosts_backups_exists": repository.root().exists(),
+            "hosts_backups_path": repository.root(),
+            "folder_created": folder_created && error_message.is_none(),
+            "timestamp": now().to_rfc3339()
+        })),
+        message: if folder_created && error_message.is_none() { Some("Hosts backups folder created successfully".to_string()) } else { None },
+        error: error_message,
+    })
+}
+
+// GET /api/backups/files - Get list of backup files from the backup repository
+pub async fn get_backup_files(req: HttpRequest) -> impl Responder {
+    if !auth::validate_token(&req) {
+        return HttpResponse::Unauthorized().json(ApiResponse::<Vec<BackupFile>> {
+            success: false,
+            data: None,
+            message: None,
+            error: Some("Invalid or missing API token".to_string()),
+        });
+    }
+    
+    // Newest first
+    match backups::repository().list() {
+        Ok(backups) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            data: Some(backups.iter().map(BackupFile::from).collect::<Vec<_>>()),
+            message: None,
+            error: None,
+        }),
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse::<Vec<BackupFile>> {
+            success: false,
+            data: None,
+            message: None,
+            error: Some(format!("Failed to read hosts backups directory: {}", e)),
+        }),
+    }
+}
+
+// GET /api/backups - Get all backups in the backup repository with their metadata
+pub async fn get_backups(req: HttpRequest) -> impl Responder {
+    if !auth::validate_token(&req) {
+        return HttpResponse::Unauthorized().json(ApiResponse::<Vec<Backup>> {
+            success: false,
+            data: None,
+            message: None,
+            error: Some("Invalid or missing API token".to_string()),
+        });
+    }
+    
+    // Newest first
+    match backups::repository().list() {
+        Ok(backups_vec) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            data: Some(backups_vec),
+            message: None,
+            error: None,
+        }),
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse::<Vec<Backup>> {
+            success: false,
+            data: None,
+            message: None,
+            error: Some(e),
+        }),
+    }
+}
+
+// POST /api/backups - Back up the current hosts file into the backup repository
+pub async fn create_backup(
+    req: HttpRequest,
+    backup_req: web::Json<CreateBackupRequest>,
+) -> impl Responder {
+    if !auth::validate_token(&req) {
+        return HttpResponse::Unauthorized().json(ApiResponse::<Backup> {
+            success: false,
+            data: None,
+            message: None,
+            error: Some("Invalid or missing API token".to_string()),
+        });
+    }
+    
+    let request = backup_req.into_inner();
+    let label = BackupLabel {
+        pinned: request.pinned,
+        ..BackupLabel::new(request.origin, request.name, request.description)
+    };
+    match backups::repository().create_from_hosts(label) {
+        Ok(backup) => HttpResponse::Created().json(ApiResponse {
+            success: true,
+            message: Some(format!("Backup {} created with {} entries", backup.id, backup.meta.entries_count)),
+            data: Some(backup),
+            error: None,
+        }),
+        Err(e) => backup_error_response::<Backup>(e),
+    }
+}
+
+// POST /api/backups/{id}/restore - Restore a backup file from the backup repository
+pub async fn restore_backup(req: HttpRequest, path: web::Path<String>) -> impl Responder {
+    if !auth::validate_token(&req) {
+        return HttpResponse::Unauthorized().json(ApiResponse::<RestoreResult> {
+            success: false,
+            data: None,
+            message: None,
+            error: Some("Invalid or missing API token".to_string()),
+        });
+    }
+    
+    match backups::repository().restore(&path.into_inner()) {
+        Ok(result) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            message: Some(guarded_message(
+                &format!(
+                    "Backup {} restored: {} added, {} removed, {} changed",
+                    result.backup, result.changes.added, result.changes.removed, result.changes.changed
+                ),
+                &result.guard,
+            )),
+            data: Some(result),
+            error: None,
+        }),
+        Err(e) => backup_error_response::<RestoreResult>(e),
+    }
+}
+
+// GET /api/backups/{a}/diff/{b} - Compare two backups; `live` stands for the current hosts file
+pub async fn diff_backups(req: HttpRequest, path: web::Path<(String, String)>) -> impl Responder {
+    if !auth::validate_token(&req) {
+        return HttpResponse::Unauthorized().json(ApiResponse::<BackupDiff> {
+            success: false,
+            data: None,
+            message: None,
+            error: Some("Invalid or missing API token".to_string()),
+        });
+    }
+    
+    let (from, to) = path.into_inner();
+    match backups::repository().diff(&from, &to) {
+        Ok(result) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            message: Some(format!(
+                "{} to {}: {} added, {} removed, {} toggled, {} IP changed",
+                result.from,
+                result.to,
+                result.entries.added.len(),
+                result.entries.removed.len(),
+                result.entries.toggled.len(),
+                result.entries.ip_changed.len()
+            )),
+            data: Some(result),
+            error: None,
+        }),
+        Err(e) => backup_error_response::<BackupDiff>(e),
+    }
+}
+
+// POST /api/backups/prune?dry_run=true - Delete the backups the retention policy does not keep
+pub async fn prune_backups(req: HttpRequest) -> impl Responder {
+    if !auth::validate_token(&req) {
+        return HttpResponse::Unauthorized().json(ApiResponse::<PruneReport> {
+            success: false,
+            data: None,
+            message: None,
+            error: Some("Invalid or missing API token".to_string()),
+        });
+    }
+    
+    let dry_run = query_params(&req).get("dry_run").is_some_and(|value| value == "true");
+    match backups::repository().prune(&config::current().backups.retention, dry_run) {
+        Ok(report) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            message: Some(if dry_run {
+                format!("{} backups would be removed, {} kept", report.removed.len(), report.kept.len())
+            } else {
+                format!("{} backups removed, {} kept", report.removed.len(), report.kept.len())
+            }),
+            data: Some(report),
+            error: None,
+        }),
+        Err(e) => HostOperationError::Failed(e).into_response::<PruneReport>(),
+    }
+}
+
+// POST /api/backups/{id}/pin - Exempt a backup from pruning
+pub async fn pin_backup(req: HttpRequest, path: web::Path<String>) -> impl Responder {
+    set_backup_pinned(req, path.into_inner(), true)
+}
+
+// POST /api/backups/{id}/unpin - Let pruning remove a backup again
+pub async fn unpin_backup(req: HttpRequest, path: web::Path<String>) -> impl Responder {
+    set_backup_pinned(req, path.into_inner(), false)
+}
+
+fn set_backup_pinned(req: HttpRequest, id: String, pinned: bool) -> HttpResponse {
+    if !auth::validate_token(&req) {
+        return HttpResponse::Unauthorized().json(ApiResponse::<Backup> {
+            success: false,
+            data: None,
+            message: None,
+            error: Some("Invalid or missing API token".to_string()),
+        });
+    }
+    
+    match backups::repository().set_pinned(&id, pinned) {
+        Ok(backup) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            message: Some(format!("Backup {} {}", backup.id, if pinned { "pinned" } else { "unpinned" })),
+            data: Some(backup),
+            error: None,
+        }),
+        Err(e) => backup_error_response::<Backup>(e),
+    }
+}
+
+fn backup_error_response<T: Serialize>(error: BackupError) -> HttpResponse {
+    match error {
+        BackupError::NotFound(e) => HostOperationError::NotFound(e).into_response::<T>(),
+        BackupError::Invalid(e) => HostOperationError::Invalid(e).into_response::<T>(),
+        BackupError::Refused(e) => HostOperationError::Conflict(e).into_response::<T>(),
+        BackupError::Failed(e) => HostOperationError::Failed(e).into_response::<T>(),
+    }
+}
+
+// ===== LIST ENTRY HELPERS (allow and block lists) =====
+
+fn list_name(kind: ListKind) -> &'static str {
+    match kind {
+        ListKind::Allow => "allow list",
+        ListKind::Block => "block list",
+    }
+}
+
+// Validate a pattern for the given list and return its normalized form
+fn normalize_list_pattern(kind: ListKind, pattern: &str) -> Result<String, String> {
+    match kind {
+        ListKind::Allow => domains::normalize_allow_pattern(pattern).map(|(pattern, _)| pattern),
+        ListKind::Block => domains::normalize_block_domain(pattern),
+    }
+}
+
+// Decoded query string parameters
+fn query_params(req: &HttpRequest) -> HashMap<String, String> {
+    web::Query::<HashMap<String, String>>::from_query(req.query_string())
+        .map(|query| query.into_inner())
+        .unwrap_or_default()
+}
+
+// GET handler body: ?page=&page_size=&search=&enabled=
+fn query_list_entries(kind: ListKind, req: &HttpRequest) -> Result<PaginatedResponse<store::ListEntry>, String> {
+    let params = query_params(req);
+    let page = params.get("page").and_then(|value| value.parse::<usize>().ok()).unwrap_or(0);
+    let page_size = config::current().page_size(params.get("page_size").and_then(|value| value.parse().ok()));
+    let query = ListQuery {
+        search: params.get("search").map(|search| search.trim().to_string()),
+        enabled: params.get("enabled").and_then(|value| value.parse().ok()),
+        offset: page.saturating_mul(page_size),
+        limit: page_size,
+    };
+    
+    let (entries, total) = store::store().query_entries(kind, &query)?;
+    let total_pages = total.div_ceil(page_size.max(1));
+    Ok(PaginatedResponse {
+        data: entries,
+        total,
+        page,
+        page_size,
+        total_pages,
+        has_next: page + 1 < total_pages,
+        has_prev: page > 0,
+    })
+}
+
+fn find_list_entry(kind: ListKind, id: &str) -> Result<store::ListEntry, HostOperationError> {
+    store::store()
+        .get_entry(kind, id)
+        .map_err(HostOperationError::Failed)?
+        .ok_or_else(|| HostOperationError::NotFound(format!("No {} entry with id {}", list_name(kind), id)))
+}
+
+fn ensure_unique_pattern(kind: ListKind, pattern: &str, except_id: Option<&str>) -> Result<(), HostOperationError> {
+    if store::store().pattern_exists(kind, pattern, except_id).map_err(HostOperationError::Failed)? {
+        return Err(HostOperationError::Conflict(format!("{} is already in the {}", pattern, list_name(kind))));
+    }
+    Ok(())
+}
+
+fn create_list_entry(kind: ListKind, request: CreateListEntryRequest) -> Result<store::ListEntry, HostOperationError> {
+    let pattern = normalize_list_pattern(kind, &request.pattern).map_err(HostOperationError::Invalid)?;
+    ensure_unique_pattern(kind, &pattern, None)?;
+    
+    let entry = store::ListEntry {
+        id: generate_id(),
+        pattern,
+        description: request.description.filter(|description| !description.is_empty()),
+        enabled: request.enabled.unwrap_or(true),
+        created_at: now(),
+    };
+    store::store()
+        .insert_entries(kind, std::slice::from_ref(&entry))
+        .map_err(HostOperationError::Failed)?;
+    Ok(entry)
+}
+
+fn update_list_entry(kind: ListKind, id: &str, changes: UpdateListEntryRequest) -> Result<store::ListEntry, HostOperationError> {
+    let mut entry = find_list_entry(kind, id)?;
+    if let Some(pattern) = changes.pattern {
+        entry.pattern = normalize_list_pattern(kind, &pattern).map_err(HostOperationError::Invalid)?;
+        ensure_unique_pattern(kind, &entry.pattern, Some(id))?;
+    }
+    if let Some(description) = changes.description {
+        entry.description = if description.is_empty() { None } else { Some(description) };
+    }
+    if let Some(enabled) = changes.enabled {
+        entry.enabled = enabled;
+    }
+    
+    if !store::store().update_entry(kind, &entry).map_err(HostOperationError::Failed)? {
+        return Err(HostOperationError::NotFound(format!("No {} entry with id {}", list_name(kind), id)));
+    }
+    Ok(entry)
+}
+
+fn delete_list_entry(kind: ListKind, id: &str) -> Result<store::ListEntry, HostOperationError> {
+    let entry = find_list_entry(kind, id)?;
+    store::store().delete_entry(kind, id).map_err(HostOperationError::Failed)?;
+    Ok(entry)
+}
+
+// Validate every pattern, skip invalid ones and duplicates, insert the rest at once
+fn import_list_entries(kind: ListKind, request: ImportListEntriesRequest) -> Result<ImportListEntriesResult, HostOperationError> {
+    let mut skipped = Vec::new();
+    let mut candidates: Vec<String> = request.patterns.iter().map(|pattern| pattern.trim().to_string()).collect();
+    
+    let text = request.text.as_deref().unwrap_or("");
+    let format = match (kind, request.format) {
+        (ListKind::Allow, None) => {
+            // Skip blanks and comment lines
+            let lines = text.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#') && !line.starts_with('!'));
+            candidates.extend(lines.map(str::to_string));
+            None
+        }
+        (_, format) => {
+            let parsed = formats::parse(text, format.unwrap_or_default());
+            skipped.extend(parsed.skipped.into_iter().map(|line| SkippedPattern {
+                pattern: line.content,
+                reason: line.reason,
+                line: Some(line.line),
+            }));
+            candidates.extend(parsed.domains);
+            Some(parsed.format)
+        }
+    };
+    
+    let description = request.description.filter(|description| !description.is_empty());
+    let enabled = request.enabled.unwrap_or(true);
+    let created_at = now();
+    let mut seen = std::collections::HashSet::new();
+    let mut imported = Vec::new();
+    
+    for candidate in candidates.iter().filter(|candidate| !candidate.is_empty()) {
+        let pattern = match normalize_list_pattern(kind, candidate) {
+            Ok(pattern) => pattern,
+            Err(reason) => {
+                skipped.push(SkippedPattern { pattern: candidate.to_string(), reason, line: None });
+                continue;
+            }
+        };
+        if !seen.insert(pattern.clone()) {
+            skipped.push(SkippedPattern {
+                pattern: candidate.to_string(),
+                reason: "Duplicate in import".to_string(),
+                line: None,
+            });
+            continue;
+        }
+        if store::store().pattern_exists(kind, &pattern, None).map_err(HostOperationError::Failed)? {
+            skipped.push(SkippedPattern {
+                pattern: candidate.to_string(),
+                reason: format!("Already in the {}", list_name(kind)),
+                line: None,
+            });
+            continue;
+        }
+        imported.push(store::ListEntry {
+            id: generate_id(),
+            pattern,
+            description: description.clone(),
+            enabled,
+            created_at,
+        });
+    }
+    
+    store::store().insert_entries(kind, &imported).map_err(HostOperationError::Failed)?;
+    Ok(ImportListEntriesResult { imported, skipped, format })
+}
+
+// ===== ALLOW LISTS ENDPOINTS =====
+
+// GET /api/allow-lists - Get paginated allow list entries (?page=&page_size=&search=&enabled=)
+pub async fn get_allow_lists(req: HttpRequest) -> impl Responder {
+    if !auth::validate_token(&req) {
+        return HttpResponse::Unauthorized().json(ApiResponse::<PaginatedResponse<AllowListEntry>> {
+            success: false,
+            data: None,
+            message: None,
+            error: Some("Invalid or missing API token".to_string()),
+        });
+    }
+    
+    match query_list_entries(ListKind::Allow, &req) {
+        Ok(paginated_data) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            data: Some(paginated_data),
+            message: None,
+            error: None,
+        }),
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse::<PaginatedResponse<AllowListEntry>> {
+            success: false,
+            data: None,
+            message: None,
+            error: Some(e),
+        }),
+    }
+}
+
+// GET /api/allow-lists/{id} - Get a single allow list entry
+pub async fn get_allow_list_entry(req: HttpRequest, path: web::Path<String>) -> impl Responder {
+    if !auth::validate_token(&req) {
+        return HttpResponse::Unauthorized().json(ApiResponse::<AllowListEntry> {
+            success: false,
+            data: None,
+            message: None,
+            error: Some("Invalid or missing API token".to_string()),
+        });
+    }
+    
+    match find_list_entry(ListKind::Allow, &path.into_inner()) {
+        Ok(entry) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            data: Some(entry),
+            message: None,
+            error: None,
+        }),
+        Err(e) => e.into_response::<AllowListEntry>(),
+    }
+}
+
+// POST /api/allow-lists - Add an allow list entry
+pub async fn create_allow_list_entry(
+    req: HttpRequest,
+    entry_req: web::Json<CreateListEntryRequest>,
+) -> impl Responder {
+    if !auth::validate_token(&req) {
+        return HttpResponse::Unauthorized().json(ApiResponse::<AllowListEntry> {
+            success: false,
+            data: None,
+            message: None,
+            error: Some("Invalid or missing API token".to_string()),
+        });
+    }
+    
+    match create_list_entry(ListKind::Allow, entry_req.into_inner()) {
+        Ok(entry) => HttpResponse::Created().json(ApiResponse {
+            success: true,
+            data: Some(entry),
+            message: Some("Allow list entry created successfully".to_string()),
+            error: None,
+        }),
+        Err(e) => e.into_response::<AllowListEntry>(),
+    }
+}
+
+// PUT /api/allow-lists/{id} - Replace an allow list entry
+pub async fn replace_allow_list_entry(
+    req: HttpRequest,
+    path: web::Path<String>,
+    entry_req: web::Json<CreateListEntryRequest>,
+) -> impl Responder {
+    if !auth::validate_token(&req) {
+        return HttpResponse::Unauthorized().json(ApiResponse::<AllowListEntry> {
+            success: false,
+            data: None,
+            message: None,
+            error: Some("Invalid or missing API token".to_string()),
+        });
+    }
+    
+    match update_list_entry(ListKind::Allow, &path.into_inner(), entry_req.into_inner().into()) {
+        Ok(entry) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            data: Some(entry),
+            message: Some("Allow list entry replaced successfully".to_string()),
+            error: None,
+        }),
+        Err(e) => e.into_response::<AllowListEntry>(),
+    }
+}
+
+// PATCH /api/allow-lists/{id} - Update selected fields of an allow list entry
+pub async fn update_allow_list_entry(
+    req: HttpRequest,
+    path: web::Path<String>,
+    update_req: web::Json<UpdateListEntryRequest>,
+) -> impl Responder {
+    if !auth::validate_token(&req) {
+        return HttpResponse::Unauthorized().json(ApiResponse::<AllowListEntry> {
+            success: false,
+            data: None,
+            message: None,
+            error: Some("Invalid or missing API token".to_string()),
+        });
+    }
+    
+    match update_list_entry(ListKind::Allow, &path.into_inner(), update_req.into_inner()) {
+        Ok(entry) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            data: Some(entry),
+            message: Some("Allow list entry updated successfully".to_string()),
+            error: None,
+        }),
+        Err(e) => e.into_response::<AllowListEntry>(),
+    }
+}
+
+// DELETE /api/allow-lists/{id} - Remove an allow list entry
+pub async fn delete_allow_list_entry(req: HttpRequest, path: web::Path<String>) -> impl Responder {
+    if !auth::validate_token(&req) {
+        return HttpResponse::Unauthorized().json(ApiResponse::<AllowListEntry> {
+            success: false,
+            data: None,
+            message: None,
+            error: Some("Invalid or missing API token".to_string()),
+        });
+    }
+    
+    match delete_list_entry(ListKind::Allow, &path.into_inner()) {
+        Ok(entry) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            data: Some(entry),
+            message: Some("Allow list entry deleted successfully".to_string()),
+            error: None,
+        }),
+        Err(e) => e.into_response::<AllowListEntry>(),
+    }
+}
+
+// POST /api/allow-lists/{id}/enable - Enable an allow list entry
+pub async fn enable_allow_list_entry(req: HttpRequest, path: web::Path<String>) -> impl Responder {
+    set_allow_list_entry_enabled(req, path.into_inner(), true)
+}
+
+// POST /api/allow-lists/{id}/disable - Disable an allow list entry
+pub async fn disable_allow_list_entry(req: HttpRequest, path: web::Path<String>) -> impl Responder {
+    set_allow_list_entry_enabled(req, path.into_inner(), false)
+}
+
+fn set_allow_list_entry_enabled(req: HttpRequest, id: String, enabled: bool) -> HttpResponse {
+    if !auth::validate_token(&req) {
+        return HttpResponse::Unauthorized().json(ApiResponse::<AllowListEntry> {
+            success: false,
+            data: None,
+            message: None,
+            error: Some("Invalid or missing API token".to_string()),
+        });
+    }
+    
+    let changes = UpdateListEntryRequest { enabled: Some(enabled), ..Default::default() };
+    match update_list_entry(ListKind::Allow, &id, changes) {
+        Ok(entry) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            data: Some(entry),
+            message: Some(if enabled { "Allow list entry enabled" } else { "Allow list entry disabled" }.to_string()),
+            error: None,
+        }),
+        Err(e) => e.into_response::<AllowListEntry>(),
+    }
+}
+
+// POST /api/allow-lists/import - Add many patterns at once, skipping invalid ones and duplicates
+pub async fn import_allow_list_entries(
+    req: HttpRequest,
+    import_req: web::Json<ImportListEntriesRequest>,
+) -> impl Responder {
+    if !auth::validate_token(&req) {
+        return HttpResponse::Unauthorized().json(ApiResponse::<ImportListEntriesResult> {
+            success: false,
+            data: None,
+            message: None,
+            error: Some("Invalid or missing API token".to_string()),
+        });
+    }
+    
+    match import_list_entries(ListKind::Allow, import_req.into_inner()) {
+        Ok(result) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            message: Some(format!(
+                "Imported {} allow list entries, skipped {}",
+                result.imported.len(),
+                result.skipped.len()
+            )),
+            data: Some(result),
+            error: None,
+        }),
+        Err(e) => e.into_response::<ImportListEntriesResult>(),
+    }
+}
+
+// ===== BLOCK LISTS ENDPOINTS =====
+
+// GET /api/block-lists - Get paginated block list entries (?page=&page_size=&search=&enabled=)
+pub async fn get_block_lists(req: HttpRequest) -> impl Responder {
+    if !auth::validate_token(&req) {
+        return HttpResponse::Unauthorized().json(ApiResponse::<PaginatedResponse<BlockListEntry>> {
+            success: false,
+            data: None,
+            message: None,
+            error: Some("Invalid or missing API token".to_string()),
+        });
+    }
+    
+    match query_list_entries(ListKind::Block, &req) {
+        Ok(paginated_data) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            data: Some(paginated_data),
+            message: None,
+            error: None,
+        }),
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse::<PaginatedResponse<BlockListEntry>> {
+            success: false,
+            data: None,
+            message: None,
+            error: Some(e),
+        }),
+    }
+}
+
+// GET /api/block-lists/{id} - Get a single block list entry
+pub async fn get_block_list_entry(req: HttpRequest, path: web::Path<String>) -> impl Responder {
+    if !auth::validate_token(&req) {
+        return HttpResponse::Unauthorized().json(ApiResponse::<BlockListEntry> {
+            success: false,
+            data: None,
+            message: None,
+            error: Some("Invalid or missing API token".to_string()),
+        });
+    }
+    
+    match find_list_entry(ListKind::Block, &path.into_inner()) {
+        Ok(entry) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            data: Some(entry),
+            message: None,
+            error: None,
+        }),
+        Err(e) => e.into_response::<BlockListEntry>(),
+    }
+}
+
+// POST /api/block-lists - Add a block list entry
+pub async fn create_block_list_entry(
+    req: HttpRequest,
+    entry_req: web::Json<CreateListEntryRequest>,
+) -> impl Responder {
+    if !auth::validate_token(&req) {
+        return HttpResponse::Unauthorized().json(ApiResponse::<BlockListEntry> {
+            success: false,
+            data: None,
+            message: None,
+            error: Some("Invalid or missing API token".to_string()),
+        });
+    }
+    
+    match create_list_entry(ListKind::Block, entry_req.into_inner()) {
+        Ok(entry) => HttpResponse::Created().json(ApiResponse {
+            success: true,
+            data: Some(entry),
+            message: Some("Block list entry created successfully".to_string()),
+            error: None,
+        }),
+        Err(e) => e.into_response::<BlockListEntry>(),
+    }
+}
+
+// PUT /api/block-lists/{id} - Replace a block list entry
+pub async fn replace_block_list_entry(
+    req: HttpRequest,
+    path: web::Path<String>,
+    entry_req: web::Json<CreateListEntryRequest>,
+) -> impl Responder {
+    if !auth::validate_token(&req) {
+        return HttpResponse::Unauthorized().json(ApiResponse::<BlockListEntry> {
+            success: false,
+            data: None,
+            message: None,
+            error: Some("Invalid or missing API token".to_string()),
+        });
+    }
+    
+    match update_list_entry(ListKind::Block, &path.into_inner(), entry_req.into_inner().into()) {
+        Ok(entry) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            data: Some(entry),
+            message: Some("Block list entry replaced successfully".to_string()),
+            error: None,
+        }),
+        Err(e) => e.into_response::<BlockListEntry>(),
+    }
+}
+
+// PATCH /api/block-lists/{id} - Update selected fields of a block list entry
+pub async fn update_block_list_entry(
+    req: HttpRequest,
+    path: web::Path<String>,
+    update_req: web::Json<UpdateListEntryRequest>,
+) -> impl Responder {
+    if !auth::validate_token(&req) {
+        return HttpResponse::Unauthorized().json(ApiResponse::<BlockListEntry> {
+            success: false,
+            data: None,
+            message: None,
+            error: Some("Invalid or missing API token".to_string()),
+        });
+    }
+    
+    match update_list_entry(ListKind::Block, &path.into_inner(), update_req.into_inner()) {
+        Ok(entry) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            data: Some(entry),
+            message: Some("Block list entry updated successfully".to_string()),
+            error: None,
+        }),
+        Err(e) => e.into_response::<BlockListEntry>(),
+    }
+}
+
+// DELETE /api/block-lists/{id} - Remove a block list entry
+pub async fn delete_block_list_entry(req: HttpRequest, path: web::Path<String>) -> impl Responder {
+    if !auth::validate_token(&req) {
+        return HttpResponse::Unauthorized().json(ApiResponse::<BlockListEntry> {
+            success: false,
+            data: None,
+            message: None,
+            error: Some("Invalid or missing API token".to_string()),
+        });
+    }
+    
+    match delete_list_entry(ListKind::Block, &path.into_inner()) {
+        Ok(entry) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            data: Some(entry),
+            message: Some("Block list entry deleted successfully".to_string()),
+            error: None,
+        }),
+        Err(e) => e.into_response::<BlockListEntry>(),
+    }
+}
+
+// POST /api/block-lists/import - Add domains pasted as text, skipping invalid ones and duplicates
+pub async fn import_block_list_entries(
+    req: HttpRequest,
+    import_req: web::Json<ImportListEntriesRequest>,
+) -> impl Responder {
+    if !auth::validate_token(&req) {
+        return HttpResponse::Unauthorized().json(ApiResponse::<ImportListEntriesResult> {
+            success: false,
+            data: None,
+            message: None,
+            error: Some("Invalid or missing API token".to_string()),
+        });
+    }
+    
+    match import_list_entries(ListKind::Block, import_req.into_inner()) {
+        Ok(result) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            message: Some(format!(
+                "Imported {} block list entries, skipped {}",
+                result.imported.len(),
+                result.skipped.len()
+            )),
+            data: Some(result),
+            error: None,
+        }),
+        Err(e) => e.into_response::<ImportListEntriesResult>(),
+    }
+}
+
+// ===== SOURCES ENDPOINTS =====
+
+fn find_source(id: &str) -> Result<Source, HostOperationError> {
+    store::store()
+        .get_source(id)
+        .map_err(HostOperationError::Failed)?
+        .ok_or_else(|| HostOperationError::NotFound(format!("No source with id {}", id)))
+}
+
+fn validate_source_name(name: &str) -> Result<String, HostOperationError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(HostOperationError::Invalid("Source name is empty".to_string()));
     }
-    
-    Ok(())
+    Ok(name.to_string())
 }
 
-// Get total count of hosts entries
-fn get_hosts_file_count() -> Result<usize, String> {
-    let hosts_path = "/etc/hosts";
-    let backup_path = "/etc/hosts.backup";
-    
-    // Check if hosts file is disabled (backup exists, original doesn't)
-    if Path::new(backup_path).exists() && !Path::new(hosts_path).exists() {
-        return Err("Hosts file is currently disabled".to_string());
+// Validate a source URL and make sure no other source is subscribed to it
+fn validate_source_url(url: &str, except_id: Option<&str>) -> Result<String, HostOperationError> {
+    let url = sources::validate_url(url).map_err(HostOperationError::Invalid)?;
+    if store::store().source_url_exists(&url, except_id).map_err(HostOperationError::Failed)? {
+        return Err(HostOperationError::Conflict(format!("Already subscribed to {}", url)));
     }
-    
-    if !Path::new(hosts_path).exists() {
-        return Ok(0);
+    Ok(url)
+}
+
+fn create_source(request: CreateSourceRequest) -> Result<Source, HostOperationError> {
+    let source = Source {
+        id: generate_id(),
+        name: validate_source_name(&request.name)?,
+        url: validate_source_url(&request.url, None)?,
+        enabled: request.enabled.unwrap_or(true),
+        kind: request.kind,
+        format: request.format.unwrap_or_default(),
+        created_at: now(),
+        last_attempt_at: None,
+        last_success_at: None,
+        last_error: None,
+        size_bytes: 0,
+        domain_count: 0,
+    };
+    store::store().insert_source(&source).map_err(HostOperationError::Failed)?;
+    Ok(source)
+}
+
+fn update_source(id: &str, changes: UpdateSourceRequest) -> Result<Source, HostOperationError> {
+    let mut source = find_source(id)?;
+    if let Some(name) = changes.name {
+        source.name = validate_source_name(&name)?;
     }
-    
-    // Regex patterns for validation (same as streaming function)
-    let ipv4_pattern = Regex::new(r"^(?:(?:25[0-5]|2[0-4][0-9]|[01]?[0-9][0-9]?)\.){3}(?:25[0-5]|2[0-4][0-9]|[01]?[0-9][0-9]?)$").unwrap();
-    let ipv6_pattern = Regex::new(r"^(?:(?:[0-9a-fA-F]{1,4}:){7}[0-9a-fA-F]{1,4}|(?:[0-9a-fA-F]{1,4}:){1,7}:|(?:[0-9a-fA-F]{1,4}:){1,6}:[0-9a-fA-F]{1,4}|(?:[0-9a-fA-F]{1,4}:){1,5}(?::[0-9a-fA-F]{1,4}){1,2}|(?:[0-9a-fA-F]{1,4}:){1,4}(?::[0-9a-fA-F]{1,4}){1,3}|(?:[0-9a-fA-F]{1,4}:){1,3}(?::[0-9a-fA-F]{1,4}){1,4}|(?:[0-9a-fA-F]{1,4}:){1,2}(?::[0-9a-fA-F]{1,4}){1,5}|[0-9a-fA-F]{1,4}:(?:(?::[0-9a-fA-F]{1,4}){1,6})|:(?:(?::[0-9a-fA-F]{1,4}){1,7}|:)|fe80:(?::[0-9a-fA-F]{0,4}){0,4}%[0-9a-zA-Z]{1,}|::(?:ffff(?::0{1,4}){0,1}:){0,1}(?:(?:25[0-5]|(?:2[0-4]|1{0,1}[0-9]){0,1}[0-9])\.){3,3}(?:25[0-5]|(?:2[0-4]|1{0,1}[0-9]){0,1}[0-9])|(?:[0-9a-fA-F]{1,4}:){1,4}:(?:(?:25[0-5]|(?:2[0-4]|1{0,1}[0-9]){0,1}[0-9])\.){3,3}(?:25[0-5]|(?:2[0-4]|1{0,1}[0-9]){0,1}[0-9]))$").unwrap();
-    
-    let file = File::open(hosts_path)
-        .map_err(|_| "Failed to open hosts file".to_string())?;
-    
-    let reader = BufReader::new(file);
-    let mut count = 0;
-    
-    for line in reader.lines() {
-        if let Ok(line) = line {
-            let trimmed_line = line.trim();
-            
-            // Skip empty lines
-            if trimmed_line.is_empty() {
-                continue;
-            }
-            
-            // Check if line is commented out (disabled)
-            let is_enabled = !trimmed_line.starts_with('#');
-            let working_line = if is_enabled { trimmed_line } else { &trimmed_line[1..].trim() };
-            
-            // Skip pure comments (lines that start with # and don't have valid IP/hostname data)
-            if working_line.is_empty() || working_line.starts_with('#') {
-                continue;
-            }
-            
-            // Parse IP and hostname
-            let parts: Vec<&str> = working_line.split_whitespace().collect();
-            if parts.len() >= 2 {
-                let ip = parts[0];
-                let hostname = parts[1];
-                
-                // Validate IP using regex (same as streaming function)
-                let is_valid_ip = ipv4_pattern.is_match(ip) || ipv6_pattern.is_match(ip);
-                
-                // More permissive hostname validation (same as streaming function)
-                let is_valid_hostname = !hostname.is_empty() && 
-                                       !hostname.starts_with('#') && 
-                                       !hostname.contains(char::is_whitespace) &&
-                                       hostname.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '.' || c == '%');
-                
-                // Only count entry if both IP and hostname are valid
-                if is_valid_ip && is_valid_hostname {
-                    count += 1;
-                }
-            }
-        }
+    if let Some(url) = changes.url {
+        source.url = validate_source_url(&url, Some(id))?;
+    }
+    if let Some(kind) = changes.kind {
+        source.kind = kind;
+    }
+    if let Some(format) = changes.format {
+        source.format = format;
+    }
+    if let Some(enabled) = changes.enabled {
+        source.enabled = enabled;
     }
     
-    Ok(count)
+    if !store::store().update_source(&source).map_err(HostOperationError::Failed)? {
+        return Err(HostOperationError::NotFound(format!("No source with id {}", id)));
+    }
+    Ok(source)
 }
 
-// ===== HOST ENTRIES ENDPOINTS =====
+fn delete_source(id: &str) -> Result<Source, HostOperationError> {
+    let source = find_source(id)?;
+    store::store().delete_source(id).map_err(HostOperationError::Failed)?;
+    sources::cache().remove(id);
+    Ok(source)
+}
 
-// GET /api/hosts - Get paginated host entries from /etc/hosts
-pub async fn get_host_entries(req: HttpRequest) -> impl Responder {
+// GET /api/sources - List source subscriptions
+pub async fn get_sources(req: HttpRequest) -> impl Responder {
     if !auth::validate_token(&req) {
-        return HttpResponse::Unauthorized().json(ApiResponse::<PaginatedResponse<HostEntry>> {
+        return HttpResponse::Unauthorized().json(ApiResponse::<Vec<Source>> {
             success: false,
             data: None,
             message: None,
@@ -413,40 +1593,14 @@ pub async fn get_host_entries(req: HttpRequest) -> impl Responder {
         });
     }
     
-    // Get query parameters
-    let page = req.query_string()
-        .split('&')
-        .filter_map(|pair| {
-            let mut parts = pair.splitn(2, '=');
-            let key = parts.next()?;
-            let value = parts.next()?;
-            if key == "page" { value.parse::<usize>().ok() } else { None }
-        })
-        .next()
-        .unwrap_or(0);
-    let page_size = req.query_string()
-        .split('&')
-        .filter_map(|pair| {
-            let mut parts = pair.splitn(2, '=');
-            let key = parts.next()?;
-            let value = parts.next()?;
-            if key == "page_size" { value.parse::<usize>().ok() } else { None }
-        })
-        .next()
-        .unwrap_or(1000) // Default to 1000
-        .min(1000) // Minimum of 1000
-        .max(20000); // Maximum of 20000
-    
-    match read_hosts_file_streaming(page, page_size) {
-        Ok(paginated_data) => {
-            HttpResponse::Ok().json(ApiResponse {
-                success: true,
-                data: Some(paginated_data),
-                message: None,
-                error: None,
-            })
-        }
-        Err(e) => HttpResponse::InternalServerError().json(ApiResponse::<PaginatedResponse<HostEntry>> {
+    match store::store().list_sources() {
+        Ok(sources) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            data: Some(sources),
+            message: None,
+            error: None,
+        }),
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse::<Vec<Source>> {
             success: false,
             data: None,
             message: None,
@@ -455,13 +1609,10 @@ pub async fn get_host_entries(req: HttpRequest) -> impl Responder {
     }
 }
 
-// POST /api/hosts - Add a new host entry to /etc/hosts
-pub async fn create_host_entry(
-    req: HttpRequest,
-    entry_req: web::Json<CreateHostEntryRequest>,
-) -> impl Responder {
+// GET /api/sources/{id} - Get a single source subscription
+pub async fn get_source(req: HttpRequest, path: web::Path<String>) -> impl Responder {
     if !auth::validate_token(&req) {
-        return HttpResponse::Unauthorized().json(ApiResponse::<HostEntry> {
+        return HttpResponse::Unauthorized().json(ApiResponse::<Source> {
             success: false,
             data: None,
             message: None,
@@ -469,61 +1620,24 @@ pub async fn create_host_entry(
         });
     }
     
-    // Read current entries
-    let current_entries = match read_hosts_file_streaming(0, usize::MAX) {
-        Ok(data) => data.data,
-        Err(e) => {
-            return HttpResponse::InternalServerError().json(ApiResponse::<HostEntry> {
-                success: false,
-                data: None,
-                message: None,
-                error: Some(e),
-            });
-        }
-    };
-    
-    // Create new entry
-    let new_entry = HostEntry {
-        id: generate_id(),
-        ip: entry_req.ip.clone(),
-        hostname: entry_req.hostname.clone(),
-        comment: entry_req.comment.clone(),
-        enabled: entry_req.enabled.unwrap_or(true),
-        created_at: now(),
-        updated_at: now(),
-    };
-    
-    // Add to list and write back
-    let mut updated_entries = current_entries;
-    updated_entries.push(new_entry.clone());
-    
-    match write_hosts_file_streaming(updated_entries) {
-        Ok(_) => {
-            HttpResponse::Created().json(ApiResponse {
-                success: true,
-                data: Some(new_entry),
-                message: Some("Host entry created successfully".to_string()),
-                error: None,
-            })
-        }
-        Err(e) => HttpResponse::InternalServerError().json(ApiResponse::<HostEntry> {
-            success: false,
-            data: None,
+    match find_source(&path.into_inner()) {
+        Ok(source) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            data: Some(source),
             message: None,
-            error: Some(e),
+            error: None,
         }),
+        Err(e) => e.into_response::<Source>(),
     }
 }
 
-
-
-
-// ===== BACKUPS ENDPOINTS =====
-
-// GET /api/backups/folder-status - Check if hosts_backups folder exists and create if needed
-pub async fn get_backup_folder_status(req: HttpRequest) -> impl Responder {
+// POST /api/sources - Subscribe to a remote block or allow list
+pub async fn create_source_subscription(
+    req: HttpRequest,
+    source_req: web::Json<CreateSourceRequest>,
+) -> impl Responder {
     if !auth::validate_token(&req) {
-        return HttpResponse::Unauthorized().json(ApiResponse::<serde_json::Value> {
+        return HttpResponse::Unauthorized().json(ApiResponse::<Source> {
             success: false,
             data: None,
             message: None,
@@ -531,72 +1645,25 @@ pub async fn get_backup_folder_status(req: HttpRequest) -> impl Responder {
         });
     }
     
-    // Check for hosts_backups folder in user's home directory
-    let home_dir = std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string());
-    let hosts_backups_dir = format!("{}/hosts_backups", home_dir);
-    let backup_dir = format!("{}/.hostdni/backups", home_dir);
-    
-    let mut hosts_backups_exists = Path::new(&hosts_backups_dir).exists();
-    let backup_dir_exists = Path::new(&backup_dir).exists();
-    let mut folder_created = false;
-    let mut error_message = None;
-    
-    // Create hosts_backups folder if it doesn't exist
-    if !hosts_backups_exists {
-        // Try to create the directory normally first
-        match std::fs::create_dir_all(&hosts_backups_dir) {
-            Ok(_) => {
-                hosts_backups_exists = true;
-                folder_created = true;
-            }
-            Err(e) => {
-                // If normal creation fails, try with elevated permissions
-                let script = format!(
-                    "do shell script \"mkdir -p {}\" with administrator privileges",
-                    hosts_backups_dir
-                );
-                
-                let output = std::process::Command::new("osascript")
-                    .args(&["-e", &script])
-                    .output();
-                
-                match output {
-                    Ok(result) => {
-                        if result.status.success() {
-                            hosts_backups_exists = true;
-                            folder_created = true;
-                        } else {
-                            let error_msg = String::from_utf8_lossy(&result.stderr);
-                            error_message = Some(format!("Failed to create hosts_backups folder: {}", error_msg));
-                        }
-                    }
-                    Err(e) => {
-                        error_message = Some(format!("Failed to execute command: {}", e));
-                    }
-                }
-            }
-        }
+    match create_source(source_req.into_inner()) {
+        Ok(source) => HttpResponse::Created().json(ApiResponse {
+            success: true,
+            data: Some(source),
+            message: Some("Source created successfully; refresh it to fetch its domains".to_string()),
+            error: None,
+        }),
+        Err(e) => e.into_response::<Source>(),
     }
-    
-    HttpResponse::Ok().json(ApiResponse {
-        success: error_message.is_none(),
-        data: Some(serde_json::json!({
-            "hosts_backups_exists": hosts_backups_exists,
-            "backup_dir_exists": backup_dir_exists,
-            "hosts_backups_path": hosts_backups_dir,
-            "backup_dir_path": backup_dir,
-            "folder_created": folder_created,
-            "timestamp": now().to_rfc3339()
-        })),
-        message: if folder_created { Some("Hosts backups folder created successfully".to_string()) } else { None },
-        error: error_message,
-    })
 }
 
-// GET /api/backups/files - Get list of backup files from hosts_backups directory
-pub async fn get_backup_files(req: HttpRequest) -> impl Responder {
+// PUT /api/sources/{id} - Replace a source subscription
+pub async fn replace_source(
+    req: HttpRequest,
+    path: web::Path<String>,
+    source_req: web::Json<CreateSourceRequest>,
+) -> impl Responder {
     if !auth::validate_token(&req) {
-        return HttpResponse::Unauthorized().json(ApiResponse::<Vec<serde_json::Value>> {
+        return HttpResponse::Unauthorized().json(ApiResponse::<Source> {
             success: false,
             data: None,
             message: None,
@@ -604,125 +1671,47 @@ pub async fn get_backup_files(req: HttpRequest) -> impl Responder {
         });
     }
     
-    // Get hosts_backups directory path
-    let home_dir = std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string());
-    let hosts_backups_dir = format!("{}/hosts_backups", home_dir);
-    
-    // Check if hosts_backups directory exists and create if needed
-    if !Path::new(&hosts_backups_dir).exists() {
-        // Try to create the directory normally first
-        match std::fs::create_dir_all(&hosts_backups_dir) {
-            Ok(_) => {
-                // Directory created successfully
-            }
-            Err(e) => {
-                // If normal creation fails, try with elevated permissions
-                let script = format!(
-                    "do shell script \"mkdir -p {}\" with administrator privileges",
-                    hosts_backups_dir
-                );
-                
-                let output = std::process::Command::new("osascript")
-                    .args(&["-e", &script])
-                    .output();
-                
-                match output {
-                    Ok(result) => {
-                        if !result.status.success() {
-                            let error_msg = String::from_utf8_lossy(&result.stderr);
-                            return HttpResponse::InternalServerError().json(ApiResponse::<Vec<serde_json::Value>> {
-                                success: false,
-                                data: None,
-                                message: None,
-                                error: Some(format!("Failed to create hosts_backups directory: {}", error_msg)),
-                            });
-                        }
-                    }
-                    Err(e) => {
-                        return HttpResponse::InternalServerError().json(ApiResponse::<Vec<serde_json::Value>> {
-                            success: false,
-                            data: None,
-                            message: None,
-                            error: Some(format!("Failed to execute command: {}", e)),
-                        });
-                    }
-                }
-            }
-        }
+    match update_source(&path.into_inner(), source_req.into_inner().into()) {
+        Ok(source) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            data: Some(source),
+            message: Some("Source replaced successfully".to_string()),
+            error: None,
+        }),
+        Err(e) => e.into_response::<Source>(),
     }
-    
-    let mut backup_files = Vec::new();
-    
-    // Read directory entries
-    match std::fs::read_dir(&hosts_backups_dir) {
-        Ok(entries) => {
-            for entry in entries {
-                if let Ok(entry) = entry {
-                    let path = entry.path();
-                    
-                    // Check if it's a file and has .backup extension
-                    if path.is_file() && path.extension().and_then(|s| s.to_str()) == Some("backup") {
-                        if let Ok(metadata) = std::fs::metadata(&path) {
-                            let filename = path.file_name()
-                                .and_then(|n| n.to_str())
-                                .unwrap_or("unknown")
-                                .to_string();
-                            
-                            let file_path = path.to_string_lossy().to_string();
-                            let file_size = metadata.len();
-                            
-                            // Get creation time
-                            let created_time = metadata.created()
-                                .unwrap_or_else(|_| SystemTime::now())
-                                .duration_since(UNIX_EPOCH)
-                                .unwrap_or_else(|_| Duration::from_secs(0))
-                                .as_secs();
-                            
-                            // Format date
-                            let backup_date = chrono::DateTime::from_timestamp(created_time as i64, 0)
-                                .unwrap_or_else(|| chrono::Utc::now())
-                                .format("%Y-%m-%d %H:%M:%S")
-                                .to_string();
-                            
-                            backup_files.push(serde_json::json!({
-                                "filename": filename,
-                                "file_path": file_path,
-                                "backup_date": backup_date,
-                                "file_size": file_size,
-                                "created_timestamp": created_time
-                            }));
-                        }
-                    }
-                }
-            }
-            
-            // Sort by creation time (newest first)
-            backup_files.sort_by(|a, b| {
-                let a_time = a["created_timestamp"].as_u64().unwrap_or(0);
-                let b_time = b["created_timestamp"].as_u64().unwrap_or(0);
-                b_time.cmp(&a_time)
-            });
-            
-            HttpResponse::Ok().json(ApiResponse {
-                success: true,
-                data: Some(backup_files),
-                message: None,
-                error: None,
-            })
-        }
-        Err(e) => HttpResponse::InternalServerError().json(ApiResponse::<Vec<serde_json::Value>> {
+}
+
+// PATCH /api/sources/{id} - Update selected fields of a source subscription
+pub async fn update_source_subscription(
+    req: HttpRequest,
+    path: web::Path<String>,
+    update_req: web::Json<UpdateSourceRequest>,
+) -> impl Responder {
+    if !auth::validate_token(&req) {
+        return HttpResponse::Unauthorized().json(ApiResponse::<Source> {
             success: false,
             data: None,
             message: None,
-            error: Some(format!("Failed to read hosts backups directory: {}", e)),
+            error: Some("Invalid or missing API token".to_string()),
+        });
+    }
+    
+    match update_source(&path.into_inner(), update_req.into_inner()) {
+        Ok(source) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            data: Some(source),
+            message: Some("Source updated successfully".to_string()),
+            error: None,
         }),
+        Err(e) => e.into_response::<Source>(),
     }
 }
 
-// GET /api/backups - Get all backup configurations
-pub async fn get_backups(req: HttpRequest) -> impl Responder {
+// DELETE /api/sources/{id} - Unsubscribe and drop the source's domains
+pub async fn delete_source_subscription(req: HttpRequest, path: web::Path<String>) -> impl Responder {
     if !auth::validate_token(&req) {
-        return HttpResponse::Unauthorized().json(ApiResponse::<Vec<BackupConfig>> {
+        return HttpResponse::Unauthorized().json(ApiResponse::<Source> {
             success: false,
             data: None,
             message: None,
@@ -730,24 +1719,21 @@ pub async fn get_backups(req: HttpRequest) -> impl Responder {
         });
     }
     
-    let backups = BACKUPS.lock().unwrap();
-    let backups_vec: Vec<BackupConfig> = backups.values().cloned().collect();
-    
-    HttpResponse::Ok().json(ApiResponse {
-        success: true,
-        data: Some(backups_vec),
-        message: None,
-        error: None,
-    })
+    match delete_source(&path.into_inner()) {
+        Ok(source) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            data: Some(source),
+            message: Some("Source deleted successfully".to_string()),
+            error: None,
+        }),
+        Err(e) => e.into_response::<Source>(),
+    }
 }
 
-// POST /api/backups - Create a new backup
-pub async fn create_backup(
-    req: HttpRequest,
-    backup_req: web::Json<CreateBackupRequest>,
-) -> impl Responder {
+// POST /api/sources/{id}/refresh - Fetch the source now (conditionally) and update its domain set
+pub async fn refresh_source(req: HttpRequest, path: web::Path<String>) -> impl Responder {
     if !auth::validate_token(&req) {
-        return HttpResponse::Unauthorized().json(ApiResponse::<BackupConfig> {
+        return HttpResponse::Unauthorized().json(ApiResponse::<RefreshResult> {
             success: false,
             data: None,
             message: None,
@@ -755,34 +1741,47 @@ pub async fn create_backup(
         });
     }
     
-    let id = generate_id();
-    let now = now();
-    let entries_count = get_hosts_file_count().unwrap_or(0);
-    
-    let new_backup = BackupConfig {
-        id: id.clone(),
-        name: backup_req.name.clone(),
-        description: backup_req.description.clone(),
-        created_at: now,
-        entries_count,
+    let source = match find_source(&path.into_inner()) {
+        Ok(source) => source,
+        Err(e) => return e.into_response::<RefreshResult>(),
     };
     
-    BACKUPS.lock().unwrap().insert(id.clone(), new_backup.clone());
-    
-    HttpResponse::Created().json(ApiResponse {
-        success: true,
-        data: Some(new_backup),
-        message: Some("Backup created successfully".to_string()),
-        error: None,
-    })
+    match sources::refresh(store::store(), &sources::cache(), &source).await {
+        Ok(result) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            message: Some(match result.outcome {
+                RefreshOutcome::Updated => format!("Fetched {} domains from {}", result.source.domain_count, result.source.url),
+                RefreshOutcome::NotModified => format!("{} has not changed", result.source.url),
+                RefreshOutcome::CachedCopy => format!(
+                    "Using the cached copy; {}",
+                    result.source.last_error.as_deref().unwrap_or("download failed")
+                ),
+            }),
+            data: Some(result),
+            error: None,
+        }),
+        Err(e) => HttpResponse::BadGateway().json(ApiResponse::<RefreshResult> {
+            success: false,
+            data: None,
+            message: None,
+            error: Some(e),
+        }),
+    }
 }
 
-// ===== ALLOW LISTS ENDPOINTS =====
+// ===== CONFIG ENDPOINTS =====
 
-// GET /api/allow-lists - Get all allow list entries
-pub async fn get_allow_lists(req: HttpRequest) -> impl Responder {
+#[derive(Debug, Serialize)]
+pub struct ConfigUpdateResult {
+    pub config: Config,
+    // Settings saved but not applied until the app restarts
+    pub restart_required: Vec<String>,
+}
+
+// GET /api/config - Get the configuration in effect
+pub async fn get_config(req: HttpRequest) -> impl Responder {
     if !auth::validate_token(&req) {
-        return HttpResponse::Unauthorized().json(ApiResponse::<Vec<AllowListEntry>> {
+        return HttpResponse::Unauthorized().json(ApiResponse::<Config> {
             success: false,
             data: None,
             message: None,
@@ -790,23 +1789,21 @@ pub async fn get_allow_lists(req: HttpRequest) -> impl Responder {
         });
     }
     
-    let allow_lists = ALLOW_LISTS.lock().unwrap();
-    let allow_lists_vec: Vec<AllowListEntry> = allow_lists.values().cloned().collect();
-    
     HttpResponse::Ok().json(ApiResponse {
         success: true,
-        data: Some(allow_lists_vec),
+        data: Some(config::current()),
         message: None,
         error: None,
     })
 }
 
-// ===== BLOCK LISTS ENDPOINTS =====
-
-// GET /api/block-lists - Get all block list entries
-pub async fn get_block_lists(req: HttpRequest) -> impl Responder {
+// PUT /api/config - Validate, save and apply a new configuration
+pub async fn update_config(
+    req: HttpRequest,
+    new_config: web::Json<Config>,
+) -> impl Responder {
     if !auth::validate_token(&req) {
-        return HttpResponse::Unauthorized().json(ApiResponse::<Vec<BlockListEntry>> {
+        return HttpResponse::Unauthorized().json(ApiResponse::<ConfigUpdateResult> {
             success: false,
             data: None,
             message: None,
@@ -814,15 +1811,37 @@ pub async fn get_block_lists(req: HttpRequest) -> impl Responder {
         });
     }
     
-    let block_lists = BLOCK_LISTS.lock().unwrap();
-    let block_lists_vec: Vec<BlockListEntry> = block_lists.values().cloned().collect();
+    let new_config = new_config.into_inner();
+    if let Err(e) = new_config.validate() {
+        return HttpResponse::BadRequest().json(ApiResponse::<ConfigUpdateResult> {
+            success: false,
+            data: None,
+            message: None,
+            error: Some(e),
+        });
+    }
     
-    HttpResponse::Ok().json(ApiResponse {
-        success: true,
-        data: Some(block_lists_vec),
-        message: None,
-        error: None,
-    })
+    match config::update(new_config) {
+        Ok(restart_required) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            message: Some(if restart_required.is_empty() {
+                "Configuration saved and applied".to_string()
+            } else {
+                "Configuration saved; restart HostDNI to apply all changes".to_string()
+            }),
+            data: Some(ConfigUpdateResult {
+                config: config::current(),
+                restart_required,
+            }),
+            error: None,
+        }),
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse::<ConfigUpdateResult> {
+            success: false,
+            data: None,
+            message: None,
+            error: Some(e),
+        }),
+    }
 }
 
 // ===== SYSTEM ENDPOINTS =====
@@ -853,9 +1872,10 @@ pub async fn get_stats(req: HttpRequest) -> impl Responder {
     }
     
     let host_entries_count = get_hosts_file_count().unwrap_or(0);
-    let backups_count = BACKUPS.lock().unwrap().len();
-    let allow_lists_count = ALLOW_LISTS.lock().unwrap().len();
-    let block_lists_count = BLOCK_LISTS.lock().unwrap().len();
+    let store = store::store();
+    let backups_count = backups::repository().list().map(|backups| backups.len()).unwrap_or(0);
+    let allow_lists_count = store.count_entries(ListKind::Allow).unwrap_or(0);
+    let block_lists_count = store.count_entries(ListKind::Block).unwrap_or(0);
     
     HttpResponse::Ok().json(ApiResponse {
         success: true,
@@ -902,77 +1922,17 @@ pub async fn get_hosts_count(req: HttpRequest) -> impl Responder {
 
 // Stream hosts file in chunks for lazy loading
 fn stream_hosts_file_chunks(chunk_size: usize, offset: usize) -> Result<Vec<HostEntry>, String> {
-    let hosts_path = "/etc/hosts";
-    let backup_path = "/etc/hosts.backup";
-    
-    // Check if hosts file is disabled (backup exists, original doesn't)
-    if Path::new(backup_path).exists() && !Path::new(hosts_path).exists() {
-        return Err("Hosts file is currently disabled".to_string());
-    }
-    
-    // Check if file exists
-    if !Path::new(hosts_path).exists() {
-        return Err("Hosts file not found".to_string());
-    }
-    
-    let file = File::open(hosts_path)
-        .map_err(|_| "Failed to open hosts file".to_string())?;
-    
-    let reader = BufReader::new(file);
-    let mut entries = Vec::new();
-    let mut current_entry = 0;
-    let start_index = offset;
-    let end_index = offset + chunk_size;
-    
-    for (index, line) in reader.lines().enumerate() {
-        if let Ok(line) = line {
-            let trimmed_line = line.trim();
-            
-            if trimmed_line.is_empty() {
-                continue;
-            }
-            
-            let is_enabled = !trimmed_line.starts_with('#');
-            let working_line = if is_enabled { trimmed_line } else { &trimmed_line[1..].trim() };
-            
-            if working_line.is_empty() || working_line.starts_with('#') {
-                continue;
-            }
-            
-            let parts: Vec<&str> = working_line.split_whitespace().collect();
-            if parts.len() >= 2 {
-                if current_entry >= start_index && current_entry < end_index {
-                    let ip = parts[0];
-                    let hostname = parts[1];
-                    
-                    // Basic validation
-                    if !ip.is_empty() && !hostname.is_empty() {
-                        let comment = if parts.len() > 2 {
-                            Some(parts[2..].join(" "))
-                        } else {
-                            None
-                        };
-                        
-                        entries.push(HostEntry {
-                            id: format!("entry_{}", index),
-                            ip: ip.to_string(),
-                            hostname: hostname.to_string(),
-                            comment,
-                            enabled: is_enabled,
-                            created_at: now(),
-                            updated_at: now(),
-                        });
-                    }
-                }
-                current_entry += 1;
-            }
-        }
-    }
-    
-    Ok(entries)
+    let hosts_file = load_hosts_file()?;
+    
+    Ok(hosts_file.entries_with_ids()
+        .into_iter()
+        .skip(offset)
+        .take(chunk_size)
+        .map(|(id, entry)| to_host_entry(id, entry))
+        .collect())
 }
 
-// GET /api/hosts/stream - Stream hosts entries in chunks for lazy loading
+// GET /api/etc/hosts/stream - Stream hosts entries in chunks for lazy loading
 pub async fn stream_host_entries(req: HttpRequest) -> impl Responder {
     if !auth::validate_token(&req) {
         return HttpResponse::Unauthorized().json(ApiResponse::<Vec<HostEntry>> {
@@ -983,29 +1943,13 @@ pub async fn stream_host_entries(req: HttpRequest) -> impl Responder {
         });
     }
     
-    // Get query parameters
-    let chunk_size = req.query_string()
-        .split('&')
-        .filter_map(|pair| {
-            let mut parts = pair.splitn(2, '=');
-            let key = parts.next()?;
-            let value = parts.next()?;
-            if key == "chunk_size" { value.parse::<usize>().ok() } else { None }
-        })
-        .next()
+    // ?chunk_size=&offset=
+    let params = query_params(&req);
+    let chunk_size = params.get("chunk_size")
+        .and_then(|value| value.parse::<usize>().ok())
         .unwrap_or(1000)
-        .min(10000); // Limit chunk size to prevent memory issues
-    
-    let offset = req.query_string()
-        .split('&')
-        .filter_map(|pair| {
-            let mut parts = pair.splitn(2, '=');
-            let key = parts.next()?;
-            let value = parts.next()?;
-            if key == "offset" { value.parse::<usize>().ok() } else { None }
-        })
-        .next()
-        .unwrap_or(0);
+        .min(config::current().limits.max_chunk_size); // Limit chunk size to prevent memory issues
+    let offset = params.get("offset").and_then(|value| value.parse::<usize>().ok()).unwrap_or(0);
     
     match stream_hosts_file_chunks(chunk_size, offset) {
         Ok(entries) => {
@@ -1038,14 +1982,15 @@ pub async fn get_hosts_status(req: HttpRequest) -> impl Responder {
         });
     }
     
-    let hosts_path = "/etc/hosts";
-    let disabled_path = "/etc/hosts.disabled";
-    let backup_path = "/etc/hosts.backup";
+    let paths = paths::hosts_paths();
+    let hosts_path = &paths.hosts;
+    let disabled_path = &paths.disabled;
+    let backup_path = &paths.backup;
     
     // Check file existence and status
-    let hosts_exists = Path::new(hosts_path).exists();
-    let disabled_exists = Path::new(disabled_path).exists();
-    let backup_exists = Path::new(backup_path).exists();
+    let hosts_exists = hosts_path.exists();
+    let disabled_exists = disabled_path.exists();
+    let backup_exists = backup_path.exists();
     let is_disabled = disabled_exists && !hosts_exists;
     
     // Get file sizes if they exist
@@ -1077,6 +2022,7 @@ pub async fn get_hosts_status(req: HttpRequest) -> impl Responder {
             "hosts_size": hosts_size,
             "disabled_size": disabled_size,
             "backup_size": backup_size,
+            "hosts_path": hosts_path,
             "timestamp": now().to_rfc3339()
         })),
         message: None,
@@ -1095,13 +2041,14 @@ pub async fn disable_hosts_file(req: HttpRequest) -> impl Responder {
         });
     }
     
-    let hosts_path = "/etc/hosts";
-    let disabled_path = "/etc/hosts.disabled";
+    let paths = paths::hosts_paths();
+    let hosts_path = &paths.hosts;
+    let disabled_path = &paths.disabled;
     
     // Check if hosts file exists and is accessible
-    if !Path::new(hosts_path).exists() {
+    if !hosts_path.exists() {
         // Check if hosts.disabled file exists (already disabled)
-        if Path::new(disabled_path).exists() {
+        if disabled_path.exists() {
             return HttpResponse::BadRequest().json(ApiResponse::<serde_json::Value> {
                 success: false,
                 data: None,
@@ -1113,7 +2060,7 @@ pub async fn disable_hosts_file(req: HttpRequest) -> impl Responder {
             success: false,
             data: None,
             message: None,
-            error: Some("Hosts file not found at /etc/hosts".to_string()),
+            error: Some(format!("Hosts file not found at {}", hosts_path.display())),
         });
     }
     
@@ -1128,7 +2075,7 @@ pub async fn disable_hosts_file(req: HttpRequest) -> impl Responder {
     }
     
     // Check if already disabled (hosts.disabled exists and hosts file doesn't)
-    if Path::new(disabled_path).exists() && !Path::new(hosts_path).exists() {
+    if paths.is_disabled() {
         return HttpResponse::BadRequest().json(ApiResponse::<serde_json::Value> {
             success: false,
             data: None,
@@ -1138,52 +2085,30 @@ pub async fn disable_hosts_file(req: HttpRequest) -> impl Responder {
     }
     
     // Check if hosts.disabled file already exists (to avoid overwriting)
-    if Path::new(disabled_path).exists() {
+    if disabled_path.exists() {
         return HttpResponse::BadRequest().json(ApiResponse::<serde_json::Value> {
             success: false,
             data: None,
             message: None,
-            error: Some("Hosts file is already disabled. Please remove /etc/hosts.disabled first or enable the hosts file.".to_string()),
+            error: Some(format!("Hosts file is already disabled. Please remove {} first or enable the hosts file.", disabled_path.display())),
         });
     }
     
-    // Use osascript to show native macOS authentication dialog
-    let script = format!(
-        "do shell script \"mv {} {}\" with administrator privileges",
-        hosts_path, disabled_path
-    );
-    
-    let output = std::process::Command::new("osascript")
-        .args(&["-e", &script])
-        .output();
-    
-    match output {
-        Ok(result) => {
-            if result.status.success() {
-                HttpResponse::Ok().json(ApiResponse {
-                    success: true,
-                    data: Some(serde_json::json!({
-                        "message": "Hosts file disabled successfully",
-                        "timestamp": now().to_rfc3339()
-                    })),
-                    message: Some("Hosts file disabled".to_string()),
-                    error: None,
-                })
-            } else {
-                let error_msg = String::from_utf8_lossy(&result.stderr);
-                HttpResponse::InternalServerError().json(ApiResponse::<serde_json::Value> {
-                    success: false,
-                    data: None,
-                    message: None,
-                    error: Some(format!("Failed to disable hosts file: {}", error_msg)),
-                })
-            }
-        }
+    match privilege::executor().execute(&[PrivilegedAction::Move { from: hosts_path.clone(), to: disabled_path.clone() }]) {
+        Ok(_) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            data: Some(serde_json::json!({
+                "message": "Hosts file disabled successfully",
+                "timestamp": now().to_rfc3339()
+            })),
+            message: Some("Hosts file disabled".to_string()),
+            error: None,
+        }),
         Err(e) => HttpResponse::InternalServerError().json(ApiResponse::<serde_json::Value> {
             success: false,
             data: None,
             message: None,
-            error: Some(format!("Failed to execute command: {}", e)),
+            error: Some(format!("Failed to disable hosts file: {}", e)),
         }),
     }
 }
@@ -1199,16 +2124,17 @@ pub async fn enable_hosts_file(req: HttpRequest) -> impl Responder {
         });
     }
     
-    let hosts_path = "/etc/hosts";
-    let disabled_path = "/etc/hosts.disabled";
+    let paths = paths::hosts_paths();
+    let hosts_path = &paths.hosts;
+    let disabled_path = &paths.disabled;
     
     // Check if hosts.disabled file exists and is accessible
-    if !Path::new(disabled_path).exists() {
+    if !disabled_path.exists() {
         return HttpResponse::BadRequest().json(ApiResponse::<serde_json::Value> {
             success: false,
             data: None,
             message: None,
-            error: Some("No disabled hosts file found at /etc/hosts.disabled".to_string()),
+            error: Some(format!("No disabled hosts file found at {}", disabled_path.display())),
         });
     }
     
@@ -1223,62 +2149,41 @@ pub async fn enable_hosts_file(req: HttpRequest) -> impl Responder {
     }
     
     // Check if hosts file already exists
-    if Path::new(hosts_path).exists() {
+    if hosts_path.exists() {
         return HttpResponse::BadRequest().json(ApiResponse::<serde_json::Value> {
             success: false,
             data: None,
             message: None,
-            error: Some("Hosts file already exists at /etc/hosts".to_string()),
+            error: Some(format!("Hosts file already exists at {}", hosts_path.display())),
         });
     }
     
-    // Use osascript to show native macOS authentication dialog
-    let script = format!(
-        "do shell script \"mv {} {}\" with administrator privileges",
-        disabled_path, hosts_path
-    );
-    
-    let output = std::process::Command::new("osascript")
-        .args(&["-e", &script])
-        .output();
-    
-    match output {
-        Ok(result) => {
-            if result.status.success() {
-                HttpResponse::Ok().json(ApiResponse {
-                    success: true,
-                    data: Some(serde_json::json!({
-                        "message": "Hosts file enabled successfully",
-                        "timestamp": now().to_rfc3339()
-                    })),
-                    message: Some("Hosts file enabled".to_string()),
-                    error: None,
-                })
-            } else {
-                let error_msg = String::from_utf8_lossy(&result.stderr);
-                HttpResponse::InternalServerError().json(ApiResponse::<serde_json::Value> {
-                    success: false,
-                    data: None,
-                    message: None,
-                    error: Some(format!("Failed to enable hosts file: {}", error_msg)),
-                })
-            }
-        }
+    match privilege::executor().execute(&[PrivilegedAction::Move { from: disabled_path.clone(), to: hosts_path.clone() }]) {
+        Ok(_) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            data: Some(serde_json::json!({
+                "message": "Hosts file enabled successfully",
+                "timestamp": now().to_rfc3339()
+            })),
+            message: Some("Hosts file enabled".to_string()),
+            error: None,
+        }),
         Err(e) => HttpResponse::InternalServerError().json(ApiResponse::<serde_json::Value> {
             success: false,
             data: None,
             message: None,
-            error: Some(format!("Failed to execute command: {}", e)),
+            error: Some(format!("Failed to enable hosts file: {}", e)),
         }),
     }
 }
 
-// POST /api/etc/hosts/build_and_save - Build and save hosts file with enabled entries
-pub async fn build_and_save_hosts_file(req: actix_web::HttpRequest) -> actix_web::HttpResponse {
-    
-    use std::path::Path;
-    if !crate::auth::validate_token(&req) {
-        return actix_web::HttpResponse::Unauthorized().json(ApiResponse::<serde_json::Value> {
+// POST /api/etc/hosts/preview - Compile the hosts file and diff it against the current one, writing nothing
+pub async fn preview_hosts_file(
+    req: HttpRequest,
+    build_req: Option<web::Json<BuildHostsRequest>>,
+) -> impl Responder {
+    if !auth::validate_token(&req) {
+        return HttpResponse::Unauthorized().json(ApiResponse::<HostsPreview> {
             success: false,
             data: None,
             message: None,
@@ -1286,65 +2191,122 @@ pub async fn build_and_save_hosts_file(req: actix_web::HttpRequest) -> actix_web
         });
     }
     
-    // For now, just create a basic hosts file
-    let timestamp = now().to_rfc3339();
-    let hosts_content = format!("# Managed by HostDNI. Last updated at: {}\n", timestamp);
-    let hosts_path = "/etc/hosts";
-    
-    // Create a temporary file in a writable location
-    let temp_dir = std::env::temp_dir();
-    let temp_file = temp_dir.join("hostdni_hosts_temp");
+    let build_req = build_req.map(|build_req| build_req.into_inner()).unwrap_or_default();
+    let sink_ip = match deploy::sink_ip(build_req.sink_ip.as_deref()) {
+        Ok(sink_ip) => sink_ip,
+        Err(e) => return HostOperationError::Invalid(e).into_response::<HostsPreview>(),
+    };
     
-    // Write content to temporary file
-    if let Err(e) = std::fs::write(&temp_file, hosts_content) {
-        return actix_web::HttpResponse::InternalServerError().json(ApiResponse::<serde_json::Value> {
+    match deploy::preview(&sink_ip) {
+        Ok(preview) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            message: Some(format!(
+                "{} added, {} removed, {} changed",
+                preview.changes.added, preview.changes.removed, preview.changes.changed
+            )),
+            data: Some(preview),
+            error: None,
+        }),
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse::<HostsPreview> {
             success: false,
             data: None,
             message: None,
-            error: Some(format!("Failed to create temporary hosts file: {}", e)),
-        });
+            error: Some(e),
+        }),
     }
-    
-    // Use osascript to show native macOS authentication dialog
-    let script = format!(
-        "do shell script \"cp {} {}\" with administrator privileges",
-        temp_file.to_str().unwrap(), hosts_path
-    );
-    
-    let output = std::process::Command::new("osascript")
-        .args(&["-e", &script])
-        .output();
-    
-    // Clean up temporary file
-    let _ = std::fs::remove_file(&temp_file);
-    
-    match output {
-        Ok(result) => {
-            if result.status.success() {
-                actix_web::HttpResponse::Ok().json(ApiResponse {
-                    success: true,
-                    data: Some(serde_json::json!({
-                        "message": "Hosts file built and saved successfully",
-                        "timestamp": timestamp
-                    })),
-                    message: Some("Hosts file updated".to_string()),
-                    error: None,
-                })
-            } else {
-                let error_msg = String::from_utf8_lossy(&result.stderr);
-                actix_web::HttpResponse::InternalServerError().json(ApiResponse::<serde_json::Value> {
-                    success: false,
-                    data: None,
-                    message: None,
-                    error: Some(format!("Failed to save hosts file: {}", error_msg)),
-                })
-            }
-        }
-        Err(e) => actix_web::HttpResponse::InternalServerError().json(ApiResponse::<serde_json::Value> {
+}
+
+// POST /api/etc/hosts/build_and_save - Compile the hosts file from the lists and sources and write it
+pub async fn build_and_save_hosts_file(
+    req: HttpRequest,
+    build_req: Option<web::Json<BuildHostsRequest>>,
+) -> HttpResponse {
+    if !auth::validate_token(&req) {
+        return HttpResponse::Unauthorized().json(ApiResponse::<BuildHostsResult> {
             success: false,
             data: None,
             message: None,
-            error: Some(format!("Failed to execute command: {}", e)),
+            error: Some("Invalid or missing API token".to_string()),
+        });
+    }
+    
+    let build_req = build_req.map(|build_req| build_req.into_inner()).unwrap_or_default();
+    let sink_ip = match deploy::sink_ip(build_req.sink_ip.as_deref()) {
+        Ok(sink_ip) => sink_ip,
+        Err(e) => return HostOperationError::Invalid(e).into_response::<BuildHostsResult>(),
+    };
+    let mode = config::current().deploy.mode;
+    let current = match deploy::read_current() {
+        Ok(current) => current,
+        Err(e) => return HostOperationError::Failed(e).into_response::<BuildHostsResult>(),
+    };
+    let mut compiled = match deploy::build(&current, &sink_ip, mode) {
+        Ok(compiled) => compiled,
+        Err(e) => return HostOperationError::Failed(e).into_response::<BuildHostsResult>(),
+    };
+    let assembled = match deploy::assemble(&current, &mut compiled.file, mode) {
+        Ok(assembled) => assembled,
+        Err(e) => return HostOperationError::Conflict(e).into_response::<BuildHostsResult>(),
+    };
+    let size_bytes = assembled.content.len();
+    let snapshot = match backups::snapshot_before_deploy("Before build_and_save") {
+        Ok(snapshot) => snapshot,
+        Err(e) => return HostOperationError::Failed(e).into_response::<BuildHostsResult>(),
+    };
+    
+    match deploy::write(&assembled.content) {
+        Ok(()) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            message: Some(guarded_message(
+                &format!("Hosts file built and saved with {} blocked domains", compiled.stats.blocked_domains),
+                &assembled.guard,
+            )),
+            data: Some(BuildHostsResult {
+                size_bytes,
+                stats: compiled.stats,
+                guard: assembled.guard,
+                snapshot,
+            }),
+            error: None,
         }),
+        Err(e) => HostOperationError::Failed(e).into_response::<BuildHostsResult>(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refuses_comments_that_would_inject_lines() {
+        let create: CreateHostEntryRequest =
+            serde_json::from_str(r#"{"ip":"10.0.0.1","hostname":"nas.lan","comment":"x\n0.0.0.0 bank.com"}"#).unwrap();
+        assert!(validate_entry(&create.to_entry()).unwrap_err().starts_with("Invalid comment"));
+
+        let mut entry = HostsFile::parse("10.0.0.1 nas.lan\n").entries().next().unwrap().clone();
+        for comment in ["x\r\n0.0.0.0 bank.com", "x\r", "tab\there", "nul\0"] {
+            let update = UpdateHostEntryRequest { ip: None, hostname: None, aliases: None, comment: Some(comment.to_string()), enabled: None };
+            let mut updated = entry.clone();
+            update.apply_to(&mut updated);
+            assert!(validate_entry(&updated).is_err(), "accepted {:?}", comment);
+        }
+
+        entry.comment = Some("storage # basement".to_string());
+        assert!(validate_entry(&entry).is_ok());
+    }
+
+    #[test]
+    fn single_entry_responses_carry_the_guard_report() {
+        let entry = HostsFile::parse("10.0.0.1 nas.lan\n").entries().next().unwrap().clone();
+        let guard = GuardReport {
+            guard: guard::PROTECTED_ENTRIES_GUARD.to_string(),
+            action: guard::GuardAction::Reinjected,
+            entries: vec!["127.0.0.1 localhost".to_string()],
+        };
+        let body = serde_json::to_value(GuardedHostEntry { entry: to_host_entry("e-0".to_string(), &entry), guard: Some(guard) }).unwrap();
+        assert_eq!(body["id"], "e-0");
+        assert_eq!(body["hostname"], "nas.lan");
+        assert_eq!(body["guard"]["action"], "reinjected");
+        assert_eq!(body["guard"]["entries"][0], "127.0.0.1 localhost");
     }
-}
\ No newline at end of file
+}
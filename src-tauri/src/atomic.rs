@@ -0,0 +1,209 @@
+// Crash-safe file replacement for HostDNI
+// - Contents are written to a temporary file in the target's directory and fsynced
+// - The temporary file takes over the original's ownership and mode
+// - The original is copied to a rollback path before being replaced
+// - The temporary file is renamed into place, then the result is read back and verified
+// - If the rename or the verification fails, the original is restored from the rollback copy
+
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+// Temporary file next to `path`, so the final rename stays on one filesystem
+pub fn temp_path_for(path: &Path) -> Result<PathBuf, String> {
+    let dir = path.parent().ok_or_else(|| format!("Invalid target path: {}", path.display()))?;
+    let name = path.file_name()
+        .and_then(|n| n.to_str())
+        .ok_or_else(|| format!("Invalid target path: {}", path.display()))?;
+    Ok(dir.join(format!(".{}.hostdni-{}.tmp", name, uuid::Uuid::new_v4().simple())))
+}
+
+// Flush directory metadata so the rename itself survives a crash
+fn sync_dir(path: &Path) {
+    if let Some(dir) = path.parent() {
+        if let Ok(dir) = File::open(dir) {
+            let _ = dir.sync_all();
+        }
+    }
+}
+
+// Give `file` the same mode and owner as `original`
+#[cfg(unix)]
+fn copy_ownership(file: &File, original: &fs::Metadata) -> Result<(), String> {
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+    file.set_permissions(fs::Permissions::from_mode(original.mode() & 0o7777))
+        .map_err(|e| format!("Failed to set file mode: {}", e))?;
+
+    let current = file.metadata().map_err(|e| format!("Failed to read file metadata: {}", e))?;
+    if current.uid() != original.uid() || current.gid() != original.gid() {
+        std::os::unix::fs::fchown(file, Some(original.uid()), Some(original.gid()))
+            .map_err(|e| format!("Failed to set file owner: {}", e))?;
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn copy_ownership(file: &File, original: &fs::Metadata) -> Result<(), String> {
+    file.set_permissions(original.permissions())
+        .map_err(|e| format!("Failed to set file permissions: {}", e))
+}
+
+// Put the rollback copy back in place of `path`
+fn restore(path: &Path, rollback_path: &Path) -> Result<(), String> {
+    let temp_path = temp_path_for(path)?;
+    fs::copy(rollback_path, &temp_path)
+        .and_then(|_| fs::rename(&temp_path, path))
+        .map_err(|e| {
+            let _ = fs::remove_file(&temp_path);
+            format!("Failed to restore {} from {}: {}", path.display(), rollback_path.display(), e)
+        })?;
+    sync_dir(path);
+    Ok(())
+}
+
+// Atomically replace `path` with `contents`.
+// When `rollback_path` is given, the previous contents are copied there first
+// and are used to undo the write if it cannot be completed or verified.
+pub fn write_atomic(path: &Path, contents: &[u8], rollback_path: Option<&Path>) -> Result<(), String> {
+    write_verified(path, contents, rollback_path, |path| fs::read(path))
+}
+
+// `write_atomic`, reading the result back with `read_back` (tests use it to fail the verification)
+fn write_verified(
+    path: &Path,
+    contents: &[u8],
+    rollback_path: Option<&Path>,
+    read_back: impl Fn(&Path) -> std::io::Result<Vec<u8>>,
+) -> Result<(), String> {
+    let original = fs::metadata(path).ok();
+    let temp_path = temp_path_for(path)?;
+
+    let staged = (|| -> Result<(), String> {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp_path)
+            .map_err(|e| format!("Failed to create temporary file: {}", e))?;
+        file.write_all(contents)
+            .map_err(|e| format!("Failed to write temporary file: {}", e))?;
+        if let Some(ref original) = original {
+            copy_ownership(&file, original)?;
+        }
+        file.sync_all()
+            .map_err(|e| format!("Failed to sync temporary file: {}", e))
+    })();
+    if let Err(e) = staged {
+        let _ = fs::remove_file(&temp_path);
+        return Err(e);
+    }
+
+    // Keep the old copy for rollback
+    let rollback_path = match (original.is_some(), rollback_path) {
+        (true, Some(rollback_path)) => {
+            if let Err(e) = fs::copy(path, rollback_path) {
+                let _ = fs::remove_file(&temp_path);
+                return Err(format!("Failed to create rollback copy: {}", e));
+            }
+            Some(rollback_path)
+        }
+        _ => None,
+    };
+
+    if let Err(e) = fs::rename(&temp_path, path) {
+        let _ = fs::remove_file(&temp_path);
+        return Err(format!("Failed to move new file into place: {}", e));
+    }
+    sync_dir(path);
+
+    // Verify what actually landed on disk
+    let verified = read_back(path).map(|written| written == contents).unwrap_or(false);
+    if !verified {
+        let message = format!("Verification of {} failed after write", path.display());
+        return match rollback_path {
+            Some(rollback_path) => match restore(path, rollback_path) {
+                Ok(_) => Err(format!("{}; previous version restored", message)),
+                Err(e) => Err(format!("{}; {}", message, e)),
+            },
+            None => Err(message),
+        };
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("hostdni-atomic-{}", uuid::Uuid::new_v4().simple()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // Every file in `dir`, sorted; a leftover temporary file shows up here
+    fn files(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn replaces_through_a_temporary_file_and_keeps_a_rollback_copy() {
+        let dir = scratch_dir();
+        let (hosts, rollback) = (dir.join("hosts"), dir.join("hosts.backup"));
+        write_atomic(&hosts, b"127.0.0.1 localhost\n", Some(&rollback)).unwrap();
+        // Nothing to roll back to for a new file
+        assert_eq!(files(&dir), vec!["hosts"]);
+
+        write_atomic(&hosts, b"::1 localhost\n", Some(&rollback)).unwrap();
+        assert_eq!(fs::read(&hosts).unwrap(), b"::1 localhost\n");
+        assert_eq!(fs::read(&rollback).unwrap(), b"127.0.0.1 localhost\n");
+        assert_eq!(files(&dir), vec!["hosts", "hosts.backup"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn failed_verification_restores_the_original() {
+        let dir = scratch_dir();
+        let (hosts, rollback) = (dir.join("hosts"), dir.join("hosts.backup"));
+        fs::write(&hosts, "127.0.0.1 localhost\n").unwrap();
+
+        let error = write_verified(&hosts, b"0.0.0.0 bad\n", Some(&rollback), |_| Ok(b"torn".to_vec())).unwrap_err();
+        assert!(error.ends_with("previous version restored"), "{}", error);
+        assert_eq!(fs::read(&hosts).unwrap(), b"127.0.0.1 localhost\n");
+        assert_eq!(files(&dir), vec!["hosts", "hosts.backup"]);
+
+        // An unreadable result counts as a failed verification too
+        let unreadable = |_: &Path| Err(std::io::Error::other("unreadable"));
+        assert!(write_verified(&hosts, b"0.0.0.0 bad\n", Some(&rollback), unreadable).is_err());
+        assert_eq!(fs::read(&hosts).unwrap(), b"127.0.0.1 localhost\n");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn preserves_mode_and_ownership() {
+        use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+        let dir = scratch_dir();
+        let hosts = dir.join("hosts");
+        fs::write(&hosts, "127.0.0.1 localhost\n").unwrap();
+        fs::set_permissions(&hosts, fs::Permissions::from_mode(0o640)).unwrap();
+        // Only succeeds as root, which then checks that another owner is kept too
+        let _ = std::os::unix::fs::chown(&hosts, Some(4321), Some(4321));
+        let before = fs::metadata(&hosts).unwrap();
+
+        write_atomic(&hosts, b"::1 localhost\n", None).unwrap();
+        let after = fs::metadata(&hosts).unwrap();
+        assert_eq!(after.mode() & 0o7777, 0o640);
+        assert_eq!((after.uid(), after.gid()), (before.uid(), before.gid()));
+        // A new inode: the file was renamed into place, not rewritten
+        assert_ne!(after.ino(), before.ino());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,11 +1,12 @@
 // API Token Auth System for HostDNI
 // - No user/password logic
-// - Random API token generated at startup and rotated every 10 minutes
+// - Random API token generated at startup and rotated every 10 minutes (auth.token_rotation_secs)
 // - /api/auth/token returns the current token
 // - All protected endpoints require Authorization: Bearer <token>
-// - 1-minute grace period for previous token after rotation
+// - 1-minute grace period for previous token after rotation (auth.token_grace_secs)
 // - Responds 401 if token is missing/invalid
 
+use crate::config;
 use actix_web::{HttpRequest, HttpResponse, Responder};
 use actix_web::http::header;
 use serde::Serialize;
@@ -16,10 +17,6 @@ use rand::{thread_rng, Rng};
 use rand::distributions::Alphanumeric;
 use std::thread;
 
-// Token rotation interval and grace period
-const TOKEN_ROTATION_INTERVAL_SECS: u64 = 600; // 10 minutes
-const TOKEN_GRACE_PERIOD_SECS: u64 = 60; // 1 minute
-
 // Global token state
 struct TokenState {
     current_token: String,
@@ -40,11 +37,16 @@ fn generate_token() -> String {
 }
 
 // Start token rotation in a background thread
+// The interval is re-read every second, so config changes apply to the running timer
 pub fn start_token_rotation() {
     let token_state = TOKEN_STATE.clone();
     thread::spawn(move || loop {
-        thread::sleep(Duration::from_secs(TOKEN_ROTATION_INTERVAL_SECS));
+        thread::sleep(Duration::from_secs(1));
+        let interval = config::current().auth.token_rotation_secs;
         let mut state = token_state.lock().unwrap();
+        if state.last_rotation.elapsed().as_secs() < interval {
+            continue;
+        }
         state.previous_token = Some(state.current_token.clone());
         state.current_token = generate_token();
         state.last_rotation = Instant::now();
@@ -60,8 +62,9 @@ struct TokenResponse {
 }
 
 pub async fn get_token() -> impl Responder {
+    let interval = config::current().auth.token_rotation_secs;
     let state = TOKEN_STATE.lock().unwrap();
-    let expires_in = TOKEN_ROTATION_INTERVAL_SECS - state.last_rotation.elapsed().as_secs().min(TOKEN_ROTATION_INTERVAL_SECS);
+    let expires_in = interval - state.last_rotation.elapsed().as_secs().min(interval);
     HttpResponse::Ok().json(TokenResponse {
         token: state.current_token.clone(),
         expires_in,
@@ -75,12 +78,13 @@ pub fn validate_token(req: &HttpRequest) -> bool {
         .and_then(|h| h.to_str().ok())
         .and_then(|s| s.strip_prefix("Bearer "));
     if let Some(token) = token {
+        let grace_period = config::current().auth.token_grace_secs;
         let state = TOKEN_STATE.lock().unwrap();
         if token == state.current_token {
             return true;
         }
         if let Some(prev) = &state.previous_token {
-            if token == prev && state.last_rotation.elapsed().as_secs() < TOKEN_GRACE_PERIOD_SECS {
+            if token == prev && state.last_rotation.elapsed().as_secs() < grace_period {
                 return true;
             }
         }
@@ -0,0 +1,644 @@
+// Hosts file backups for HostDNI
+// - One repository (config `backups.dir`) shared by the Tauri commands, /api/backups/* and restores
+// - Backups are `hosts_YYYYMMDD_HHMMSS.backup` files, addressed by their file name, each with a
+//   `<name>.json` metadata sidecar: creation time, SHA-256, entry count, size, origin and the
+//   optional name and description
+// - Pinned backups are never pruned; after every new backup the repository is pruned to the
+//   retention policy (see retention.rs) unless `backups.retention.auto_prune` is off
+// - Any two backups, or a backup and the live hosts file (`live`), can be compared
+// - Backups without a readable sidecar (copied in by hand) get one computed from their contents
+//   the first time they are listed
+// - Backups from the earlier locations (backups.app_dir, the privileged hosts_backups directory)
+//   are imported at startup
+// - Restoring validates the backup with the shared parser, snapshots the current hosts file into
+//   the repository, runs the write preflight and writes through the privileged executor
+
+use crate::atomic;
+use crate::config::{self, Config, RetentionConfig};
+use crate::deploy;
+use crate::diff::{self, EntryChanges, EntryDiff};
+use crate::guard::{self, GuardReport};
+use crate::hosts::{HostsFile, LineKind};
+use crate::paths;
+use crate::retention::{self, KeptBackup};
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+pub const BACKUP_EXTENSION: &str = "backup";
+// Stands for the live hosts file where a backup name is expected
+pub const LIVE: &str = "live";
+const NAME_PREFIX: &str = "hosts_";
+const NAME_TIME_FORMAT: &str = "%Y%m%d_%H%M%S";
+
+#[derive(Debug)]
+pub enum BackupError {
+    NotFound(String),
+    // Bad backup name, or a backup that is not a usable hosts file
+    Invalid(String),
+    // A write guard refused the backup
+    Refused(String),
+    Failed(String),
+}
+
+impl BackupError {
+    pub fn message(&self) -> &str {
+        match self {
+            BackupError::NotFound(e) | BackupError::Invalid(e) | BackupError::Refused(e) | BackupError::Failed(e) => e,
+        }
+    }
+}
+
+// Why a backup was taken
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackupOrigin {
+    #[default]
+    Manual,
+    // Taken right before a deployment or restore replaced the hosts file
+    PreDeploy,
+    Scheduled,
+}
+
+// Contents of a backup's metadata sidecar
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BackupMeta {
+    pub created_at: DateTime<Utc>,
+    pub size_bytes: u64,
+    // Hex SHA-256 of the backup contents
+    pub sha256: String,
+    // Host entries (enabled and disabled) according to the shared parser
+    pub entries_count: usize,
+    pub origin: BackupOrigin,
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub pinned: bool,
+}
+
+impl BackupMeta {
+    fn new(contents: &[u8], created_at: DateTime<Utc>, label: BackupLabel) -> BackupMeta {
+        BackupMeta {
+            created_at,
+            size_bytes: contents.len() as u64,
+            sha256: format!("{:x}", Sha256::digest(contents)),
+            entries_count: HostsFile::parse(&String::from_utf8_lossy(contents)).entry_count(),
+            origin: label.origin,
+            name: label.name,
+            description: label.description,
+            pinned: label.pinned,
+        }
+    }
+}
+
+// What the caller says about a new backup
+#[derive(Debug, Clone, Default)]
+pub struct BackupLabel {
+    pub origin: BackupOrigin,
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub pinned: bool,
+}
+
+impl BackupLabel {
+    pub fn new(origin: BackupOrigin, name: Option<String>, description: Option<String>) -> BackupLabel {
+        // Blank names and descriptions are no names and descriptions
+        let clean = |text: Option<String>| text.map(|text| text.trim().to_string()).filter(|text| !text.is_empty());
+        BackupLabel { origin, name: clean(name), description: clean(description), pinned: false }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Backup {
+    // File name in the repository
+    pub id: String,
+    pub path: PathBuf,
+    #[serde(flatten)]
+    pub meta: BackupMeta,
+}
+
+// Listing entry served by /api/backups/files and the get_backup_files command
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupFile {
+    pub filename: String,
+    pub file_path: String,
+    // Local time, `YYYY-MM-DD HH:MM:SS`
+    pub backup_date: String,
+    pub file_size: u64,
+    pub created_timestamp: i64,
+}
+
+impl From<&Backup> for BackupFile {
+    fn from(backup: &Backup) -> BackupFile {
+        BackupFile {
+            filename: backup.id.clone(),
+            file_path: backup.path.display().to_string(),
+            backup_date: backup.meta.created_at.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S").to_string(),
+            file_size: backup.meta.size_bytes,
+            created_timestamp: backup.meta.created_at.timestamp(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RestoreResult {
+    // The restored backup
+    pub backup: String,
+    // Snapshot of the replaced hosts file; None when there was no hosts file
+    pub snapshot: Option<String>,
+    // Unified diff from the replaced file to the restored one
+    pub diff: String,
+    pub changes: EntryChanges,
+    pub guard: Option<GuardReport>,
+    // Lines of the backup that are not hosts entries; restored as they are
+    pub warnings: Vec<String>,
+}
+
+// Comparison of two backups, or of a backup and the live hosts file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupDiff {
+    // Backup names or `live`
+    pub from: String,
+    pub to: String,
+    // Unified diff from `from` to `to`
+    pub diff: String,
+    pub changes: EntryChanges,
+    pub entries: EntryDiff,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PruneReport {
+    pub dry_run: bool,
+    // Backups that were (or, in a dry run, would be) deleted, newest first
+    pub removed: Vec<String>,
+    pub kept: Vec<KeptBackup>,
+}
+
+fn is_backup_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('.')
+        && !name.contains(['/', '\\'])
+        && Path::new(name).extension().and_then(|ext| ext.to_str()) == Some(BACKUP_EXTENSION)
+}
+
+// Creation time encoded in a `hosts_YYYYMMDD_HHMMSS[_N].backup` name
+fn time_from_name(name: &str) -> Option<DateTime<Utc>> {
+    let stamp = name.strip_prefix(NAME_PREFIX)?.get(..15)?;
+    let naive = NaiveDateTime::parse_from_str(stamp, NAME_TIME_FORMAT).ok()?;
+    Local.from_local_datetime(&naive).earliest().map(|time| time.with_timezone(&Utc))
+}
+
+fn sidecar_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".json");
+    path.with_file_name(name)
+}
+
+fn write_sidecar(path: &Path, meta: &BackupMeta) -> Result<(), String> {
+    let json = serde_json::to_vec_pretty(meta).map_err(|e| format!("Failed to encode backup metadata: {}", e))?;
+    atomic::write_atomic(&sidecar_path(path), &json, None)
+}
+
+// Parse backup contents, rejecting anything that is not a hosts file: non-UTF-8 content or
+// content without an enabled localhost entry. Lines the parser does not recognize are kept as
+// they are and returned as warnings.
+pub fn validate(contents: &[u8]) -> Result<(HostsFile, Vec<String>), String> {
+    let text = std::str::from_utf8(contents).map_err(|_| "Backup is not a text file".to_string())?;
+    let file = HostsFile::parse(text);
+    let has_localhost = file.entries().any(|entry| {
+        entry.enabled && std::iter::once(&entry.hostname).chain(&entry.aliases).any(|name| name.eq_ignore_ascii_case("localhost"))
+    });
+    if !has_localhost {
+        return Err("Backup has no localhost entry".to_string());
+    }
+    let warnings = file
+        .lines
+        .iter()
+        .zip(text.lines())
+        .enumerate()
+        .filter(|(_, (line, _))| line.kind == LineKind::Invalid)
+        .map(|(index, (_, raw))| format!("Line {} is not a hosts entry: {}", index + 1, raw.trim()))
+        .collect();
+    Ok((file, warnings))
+}
+
+#[derive(Debug, Clone)]
+pub struct BackupRepository {
+    root: PathBuf,
+    // Policy applied after every new backup; None never prunes automatically
+    auto_prune: Option<RetentionConfig>,
+}
+
+// The repository at `backups.dir`, with the configured retention
+pub fn repository() -> BackupRepository {
+    let config = config::current();
+    let retention = &config.backups.retention;
+    BackupRepository::new(config.backups_dir()).with_auto_prune(retention.auto_prune.then(|| retention.clone()))
+}
+
+// Snapshot the hosts file as a `pre_deploy` backup before a deployment replaces it.
+// Returns the backup's name; None when there is no hosts file yet.
+pub fn snapshot_before_deploy(description: &str) -> Result<Option<String>, String> {
+    let label = BackupLabel::new(BackupOrigin::PreDeploy, None, Some(description.to_string()));
+    match repository().create_from_hosts(label) {
+        Ok(backup) => Ok(Some(backup.id)),
+        Err(BackupError::NotFound(_)) => Ok(None),
+        Err(e) => Err(format!("Failed to snapshot the hosts file: {}", e.message())),
+    }
+}
+
+// Backup directories used before the repository existed
+pub fn legacy_dirs(config: &Config) -> Vec<PathBuf> {
+    vec![
+        config.app_backups_dir(),
+        config.system_backups_dir().unwrap_or_else(|| paths::hosts_paths().backups_dir.clone()),
+    ]
+}
+
+impl BackupRepository {
+    pub fn new(root: impl Into<PathBuf>) -> BackupRepository {
+        BackupRepository { root: root.into(), auto_prune: None }
+    }
+
+    pub fn with_auto_prune(mut self, retention: Option<RetentionConfig>) -> BackupRepository {
+        self.auto_prune = retention;
+        self
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn ensure_root(&self) -> Result<(), String> {
+        fs::create_dir_all(&self.root).map_err(|e| format!("Failed to create backup directory {}: {}", self.root.display(), e))
+    }
+
+    // Metadata from the sidecar. A missing or unreadable sidecar is rebuilt from the contents,
+    // with the creation time taken from the name or the modification time.
+    fn load(&self, id: &str) -> Result<Backup, String> {
+        let path = self.root.join(id);
+        let sidecar: Option<BackupMeta> = fs::read(sidecar_path(&path)).ok().and_then(|json| serde_json::from_slice(&json).ok());
+        if let Some(meta) = sidecar {
+            return Ok(Backup { id: id.to_string(), path, meta });
+        }
+
+        let contents = fs::read(&path).map_err(|e| format!("Failed to read backup {}: {}", id, e))?;
+        let modified = fs::metadata(&path).and_then(|metadata| metadata.modified()).unwrap_or(SystemTime::UNIX_EPOCH);
+        let created_at = time_from_name(id).unwrap_or_else(|| DateTime::<Utc>::from(modified));
+        let meta = BackupMeta::new(&contents, created_at, BackupLabel::default());
+        // Best effort: the metadata is served either way and recomputed next time
+        if let Err(e) = write_sidecar(&path, &meta) {
+            eprintln!("Failed to write metadata for backup {}: {}", id, e);
+        }
+        Ok(Backup { id: id.to_string(), path, meta })
+    }
+
+    // Every readable backup, newest first; unreadable files are skipped with a warning
+    pub fn list(&self) -> Result<Vec<Backup>, String> {
+        if !self.root.exists() {
+            return Ok(Vec::new());
+        }
+        let entries = fs::read_dir(&self.root).map_err(|e| format!("Failed to read backup directory: {}", e))?;
+        let mut backups = Vec::new();
+        for entry in entries.flatten() {
+            let Some(name) = entry.file_name().to_str().map(str::to_string) else { continue };
+            if is_backup_name(&name) && entry.path().is_file() {
+                match self.load(&name) {
+                    Ok(backup) => backups.push(backup),
+                    // One bad file must not hide the rest of the repository, or stop pruning
+                    Err(e) => eprintln!("Skipping backup {}: {}", name, e),
+                }
+            }
+        }
+        backups.sort_by(|a, b| b.meta.created_at.cmp(&a.meta.created_at).then_with(|| b.id.cmp(&a.id)));
+        Ok(backups)
+    }
+
+    pub fn get(&self, id: &str) -> Result<Backup, BackupError> {
+        if !is_backup_name(id) {
+            return Err(BackupError::Invalid(format!("Invalid backup name: {}", id)));
+        }
+        if !self.root.join(id).is_file() {
+            return Err(BackupError::NotFound(format!("Backup not found: {}", id)));
+        }
+        self.load(id).map_err(BackupError::Failed)
+    }
+
+    pub fn read(&self, id: &str) -> Result<Vec<u8>, BackupError> {
+        let backup = self.get(id)?;
+        fs::read(&backup.path).map_err(|e| BackupError::Failed(format!("Failed to read backup {}: {}", id, e)))
+    }
+
+    // Text and diff label of a backup, or of the live hosts file for `live`
+    fn version(&self, id: &str) -> Result<(String, String), BackupError> {
+        if id == LIVE {
+            let content = deploy::read_current().map_err(BackupError::Failed)?;
+            return Ok((content, paths::hosts_paths().hosts.display().to_string()));
+        }
+        let contents = self.read(id)?;
+        let content = String::from_utf8(contents).map_err(|_| BackupError::Invalid(format!("Backup {} is not a text file", id)))?;
+        Ok((content, id.to_string()))
+    }
+
+    // What changed from `from` to `to`; either may be `live`
+    pub fn diff(&self, from: &str, to: &str) -> Result<BackupDiff, BackupError> {
+        let (old, old_label) = self.version(from)?;
+        let (new, new_label) = self.version(to)?;
+        let entries = diff::entry_diff(&HostsFile::parse(&old), &HostsFile::parse(&new));
+        Ok(BackupDiff {
+            from: from.to_string(),
+            to: to.to_string(),
+            diff: diff::unified_diff(&old, &new, &old_label, &new_label),
+            changes: entries.counts(),
+            entries,
+        })
+    }
+
+    // A free `hosts_YYYYMMDD_HHMMSS.backup` name for `time`
+    fn free_name(&self, time: DateTime<Utc>) -> String {
+        let stem = format!("{}{}", NAME_PREFIX, time.with_timezone(&Local).format(NAME_TIME_FORMAT));
+        let mut name = format!("{}.{}", stem, BACKUP_EXTENSION);
+        let mut counter = 1;
+        while self.root.join(&name).exists() {
+            counter += 1;
+            name = format!("{}_{}.{}", stem, counter, BACKUP_EXTENSION);
+        }
+        name
+    }
+
+    fn store(&self, name: &str, contents: &[u8], meta: BackupMeta) -> Result<Backup, String> {
+        self.ensure_root()?;
+        let path = self.root.join(name);
+        atomic::write_atomic(&path, contents, None)?;
+        write_sidecar(&path, &meta)?;
+        Ok(Backup { id: name.to_string(), path, meta })
+    }
+
+    // Save `contents` as a new backup, then prune
+    pub fn create(&self, contents: &[u8], label: BackupLabel) -> Result<Backup, String> {
+        let backup = self.create_unpruned(contents, label)?;
+        self.prune_automatically(None);
+        Ok(backup)
+    }
+
+    fn create_unpruned(&self, contents: &[u8], label: BackupLabel) -> Result<Backup, String> {
+        let created_at = Utc::now();
+        self.store(&self.free_name(created_at), contents, BackupMeta::new(contents, created_at, label))
+    }
+
+    pub fn set_pinned(&self, id: &str, pinned: bool) -> Result<Backup, BackupError> {
+        let mut backup = self.get(id)?;
+        backup.meta.pinned = pinned;
+        write_sidecar(&backup.path, &backup.meta).map_err(BackupError::Failed)?;
+        Ok(backup)
+    }
+
+    // Delete the backups `retention` does not keep; a dry run only reports them
+    pub fn prune(&self, retention: &RetentionConfig, dry_run: bool) -> Result<PruneReport, String> {
+        self.prune_except(retention, dry_run, None)
+    }
+
+    // `exempt` is neither kept nor removed, as if it were not in the repository
+    fn prune_except(&self, retention: &RetentionConfig, dry_run: bool, exempt: Option<&str>) -> Result<PruneReport, String> {
+        let backups: Vec<Backup> = self.list()?.into_iter().filter(|backup| Some(backup.id.as_str()) != exempt).collect();
+        let plan = retention::plan(&backups, retention, Utc::now());
+        if !dry_run {
+            for id in &plan.removed {
+                let path = self.root.join(id);
+                fs::remove_file(&path).map_err(|e| format!("Failed to delete backup {}: {}", id, e))?;
+                match fs::remove_file(sidecar_path(&path)) {
+                    Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                        return Err(format!("Failed to delete metadata of backup {}: {}", id, e));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(PruneReport { dry_run, removed: plan.removed, kept: plan.kept })
+    }
+
+    // A failed prune never fails the backup that triggered it
+    fn prune_automatically(&self, exempt: Option<&str>) {
+        let Some(retention) = &self.auto_prune else { return };
+        match self.prune_except(retention, false, exempt) {
+            Ok(report) if !report.removed.is_empty() => println!("Pruned backups: {}", report.removed.join(", ")),
+            Ok(_) => {}
+            Err(e) => eprintln!("Failed to prune backups: {}", e),
+        }
+    }
+
+    // Back up the live hosts file
+    pub fn create_from_hosts(&self, label: BackupLabel) -> Result<Backup, BackupError> {
+        let hosts_path = &paths::hosts_paths().hosts;
+        if !hosts_path.exists() {
+            return Err(BackupError::NotFound("No hosts file found to backup".to_string()));
+        }
+        let contents = fs::read(hosts_path).map_err(|e| BackupError::Failed(format!("Failed to read hosts file: {}", e)))?;
+        self.create(&contents, label)
+            .map_err(|e| BackupError::Failed(format!("Failed to create backup: {}", e)))
+    }
+
+    // Copy backups from `dirs` that the repository does not have yet; returns how many
+    pub fn import(&self, dirs: &[PathBuf]) -> usize {
+        let mut imported = 0;
+        for dir in dirs.iter().filter(|dir| dir.as_path() != self.root.as_path()) {
+            let Ok(entries) = fs::read_dir(dir) else { continue };
+            for entry in entries.flatten() {
+                let Some(name) = entry.file_name().to_str().map(str::to_string) else { continue };
+                if !is_backup_name(&name) || self.root.join(&name).exists() {
+                    continue;
+                }
+                let Ok(contents) = fs::read(entry.path()) else { continue };
+                let modified = entry.metadata().and_then(|metadata| metadata.modified()).unwrap_or(SystemTime::UNIX_EPOCH);
+                let created_at = time_from_name(&name).unwrap_or_else(|| DateTime::<Utc>::from(modified));
+                match self.store(&name, &contents, BackupMeta::new(&contents, created_at, BackupLabel::default())) {
+                    Ok(_) => imported += 1,
+                    Err(e) => eprintln!("Failed to import backup {}: {}", entry.path().display(), e),
+                }
+            }
+        }
+        imported
+    }
+
+    // Restore the backup `id` over the hosts file
+    pub fn restore(&self, id: &str) -> Result<RestoreResult, BackupError> {
+        let contents = self.read(id)?;
+        let (mut restored, warnings) = validate(&contents).map_err(BackupError::Invalid)?;
+
+        let current = deploy::read_current().map_err(BackupError::Failed)?;
+        let guard = guard::preflight(&mut restored, &config::current().protected)
+            .map_err(|report| BackupError::Refused(report.message()))?;
+
+        let snapshot = if paths::hosts_paths().hosts.exists() {
+            let label = BackupLabel::new(BackupOrigin::PreDeploy, None, Some(format!("Before restoring {}", id)));
+            let snapshot = self
+                .create_unpruned(current.as_bytes(), label)
+                .map_err(|e| BackupError::Failed(format!("Failed to snapshot the hosts file: {}", e)))?;
+            Some(snapshot.id)
+        } else {
+            None
+        };
+
+        let content = restored.to_string();
+        deploy::write(&content).map_err(BackupError::Failed)?;
+        // Only once the write succeeded, and never the backup that was just restored
+        self.prune_automatically(Some(id));
+
+        let label = paths::hosts_paths().hosts.display().to_string();
+        Ok(RestoreResult {
+            backup: id.to_string(),
+            snapshot,
+            diff: diff::unified_diff(&current, &content, &label, id),
+            changes: diff::entry_changes(&HostsFile::parse(&current), &restored),
+            guard,
+            warnings,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("hostdni-backups-{}", uuid::Uuid::new_v4().simple()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn validates_backup_contents() {
+        let (file, warnings) = validate(b"# comment\n127.0.0.1 localhost\n# 10.0.0.1 off.local\n").unwrap();
+        assert_eq!((file.entry_count(), warnings.len()), (2, 0));
+        assert!(validate(b"::1 ip6-localhost localhost\n").is_ok());
+        assert!(validate(b"# nothing here\n").unwrap_err().contains("no localhost entry"));
+        assert!(validate(b"10.0.0.1 nas.lan\n# 127.0.0.1 localhost\n").unwrap_err().contains("no localhost entry"));
+        assert!(validate(&[0xff, 0xfe, 0x00]).is_err());
+
+        // Entries the parser rejects (here an underscore hostname) are restored as they are
+        let contents = "127.0.0.1 localhost\r\n10.0.0.2 my_printer.lan\r\n<html>\r\n";
+        let (file, warnings) = validate(contents.as_bytes()).unwrap();
+        assert_eq!(
+            warnings,
+            vec!["Line 2 is not a hosts entry: 10.0.0.2 my_printer.lan", "Line 3 is not a hosts entry: <html>"]
+        );
+        assert_eq!(file.to_string(), contents);
+    }
+
+    #[test]
+    fn creates_and_lists_backups_with_sidecars() {
+        let repository = BackupRepository::new(scratch_dir());
+        let first = repository.create(b"127.0.0.1 localhost\n", BackupLabel::default()).unwrap();
+        let second = repository.create(b"::1 localhost\n", BackupLabel::default()).unwrap();
+        assert_ne!(first.id, second.id);
+        assert!(first.id.starts_with("hosts_") && first.id.ends_with(".backup"));
+        assert!(sidecar_path(&first.path).is_file());
+
+        let listed = repository.list().unwrap();
+        assert_eq!(listed.iter().map(|backup| backup.id.as_str()).collect::<Vec<_>>(), vec![&second.id, &first.id]);
+        assert_eq!(listed[1].meta, first.meta);
+        assert_eq!(repository.read(&first.id).unwrap(), b"127.0.0.1 localhost\n");
+        fs::remove_dir_all(repository.root()).unwrap();
+    }
+
+    // A file that exists but cannot be read, even as root
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn lists_around_unreadable_backups() {
+        let repository = BackupRepository::new(scratch_dir());
+        let good = repository.create(b"127.0.0.1 localhost\n", BackupLabel::default()).unwrap();
+        std::os::unix::fs::symlink("/proc/self/mem", repository.root().join("hosts_20200101_000000.backup")).unwrap();
+
+        let listed = repository.list().unwrap();
+        assert_eq!(listed.iter().map(|backup| backup.id.as_str()).collect::<Vec<_>>(), vec![good.id.as_str()]);
+        assert!(repository.prune(&RetentionConfig { keep_last: 1, ..RetentionConfig::default() }, true).is_ok());
+        fs::remove_dir_all(repository.root()).unwrap();
+    }
+
+    #[test]
+    fn only_plain_backup_names_resolve() {
+        let repository = BackupRepository::new(scratch_dir());
+        fs::write(repository.root().join("hosts_20240101_120000.backup"), "127.0.0.1 localhost\n").unwrap();
+        let backup = repository.get("hosts_20240101_120000.backup").unwrap();
+        assert_eq!(backup.meta.created_at, time_from_name("hosts_20240101_120000.backup").unwrap());
+        assert!(matches!(repository.get("hosts_20240102_120000.backup"), Err(BackupError::NotFound(_))));
+        assert!(matches!(repository.get("../hosts.backup"), Err(BackupError::Invalid(_))));
+        assert!(matches!(repository.get("hosts"), Err(BackupError::Invalid(_))));
+        fs::remove_dir_all(repository.root()).unwrap();
+    }
+
+    #[test]
+    fn imports_legacy_backups_once() {
+        let repository = BackupRepository::new(scratch_dir());
+        let legacy = scratch_dir();
+        fs::write(legacy.join("hosts_20240101_120000.backup"), "127.0.0.1 localhost\n").unwrap();
+        fs::write(legacy.join("notes.txt"), "not a backup").unwrap();
+
+        assert_eq!(repository.import(std::slice::from_ref(&legacy)), 1);
+        assert_eq!(repository.import(std::slice::from_ref(&legacy)), 0);
+        let backup = repository.get("hosts_20240101_120000.backup").unwrap();
+        assert_eq!(backup.meta.size_bytes, 20);
+        assert!(sidecar_path(&backup.path).is_file());
+        fs::remove_dir_all(repository.root()).unwrap();
+        fs::remove_dir_all(legacy).unwrap();
+    }
+
+    #[test]
+    fn records_checksum_entries_and_label() {
+        let repository = BackupRepository::new(scratch_dir());
+        let label = BackupLabel::new(BackupOrigin::Scheduled, Some("  nightly ".to_string()), Some(" ".to_string()));
+        let backup = repository.create(b"127.0.0.1 localhost\n# 10.0.0.5 dev.local\n", label).unwrap();
+        assert_eq!(backup.meta.entries_count, 2);
+        assert_eq!(backup.meta.origin, BackupOrigin::Scheduled);
+        assert_eq!((backup.meta.name.as_deref(), backup.meta.description.as_deref()), (Some("nightly"), None));
+        assert_eq!(
+            BackupMeta::new(b"", backup.meta.created_at, BackupLabel::default()).sha256,
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+
+        let json = serde_json::to_value(&backup).unwrap();
+        assert_eq!(json["origin"], "scheduled");
+        assert_eq!(json["sha256"], backup.meta.sha256);
+        fs::remove_dir_all(repository.root()).unwrap();
+    }
+
+    #[test]
+    fn prunes_to_the_retention_policy() {
+        let retention = RetentionConfig { keep_last: 2, keep_daily_days: 0, keep_weekly_weeks: 0, auto_prune: true };
+        let repository = BackupRepository::new(scratch_dir()).with_auto_prune(Some(retention.clone()));
+        let first = repository.create(b"127.0.0.1 localhost\n", BackupLabel::default()).unwrap();
+        let second = repository.create(b"127.0.0.1 localhost\n", BackupLabel::default()).unwrap();
+        repository.set_pinned(&first.id, true).unwrap();
+        let third = repository.create(b"127.0.0.1 localhost\n", BackupLabel::default()).unwrap();
+        let fourth = repository.create(b"127.0.0.1 localhost\n", BackupLabel::default()).unwrap();
+
+        // The fourth backup pruned the second; the first is pinned
+        let ids = |repository: &BackupRepository| repository.list().unwrap().into_iter().map(|backup| backup.id).collect::<Vec<_>>();
+        assert_eq!(ids(&repository), vec![fourth.id.clone(), third.id.clone(), first.id.clone()]);
+        assert!(!sidecar_path(&second.path).exists());
+
+        repository.set_pinned(&first.id, false).unwrap();
+        let report = repository.prune(&retention, true).unwrap();
+        assert_eq!(report.removed, vec![first.id.clone()]);
+        assert_eq!(ids(&repository).len(), 3);
+        repository.prune(&retention, false).unwrap();
+        assert_eq!(ids(&repository), vec![fourth.id.clone(), third.id.clone()]);
+        fs::remove_dir_all(repository.root()).unwrap();
+    }
+
+    #[test]
+    fn diffs_two_backups() {
+        let repository = BackupRepository::new(scratch_dir());
+        let old = repository.create(b"127.0.0.1 localhost\n10.0.0.1 nas.lan\n", BackupLabel::default()).unwrap();
+        let new = repository.create(b"127.0.0.1 localhost\n# 10.0.0.1 nas.lan\n", BackupLabel::default()).unwrap();
+        let diff = repository.diff(&old.id, &new.id).unwrap();
+        assert!(diff.diff.contains(&format!("--- {}\n+++ {}\n", old.id, new.id)));
+        assert!(diff.diff.contains("+# 10.0.0.1 nas.lan"));
+        assert_eq!(diff.entries.toggled.len(), 1);
+        assert_eq!(diff.changes, EntryChanges { added: 0, removed: 0, changed: 1 });
+        assert!(matches!(repository.diff(&old.id, "hosts_20240101_120000.backup"), Err(BackupError::NotFound(_))));
+        fs::remove_dir_all(repository.root()).unwrap();
+    }
+}
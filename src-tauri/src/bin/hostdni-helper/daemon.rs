@@ -0,0 +1,381 @@
+// Server side of the privileged helper (Unix only)
+// - Only accepts the operations defined in src/helper.rs (HelperRequest)
+// - Hosts files are checked against the protected entries of the system config
+//   (/etc/hostdni/config.toml, never the user's) and refused when any is missing
+
+use hostdni_lib::config::{self, ProtectedConfig, ProtectedMode};
+use hostdni_lib::{atomic, guard, helper, hosts, paths};
+use helper::{HelperRequest, HelperResponse};
+use hosts::HostsFile;
+use paths::HostsPaths;
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+// Largest request accepted; generous for big block lists
+const MAX_REQUEST_BYTES: u64 = 64 * 1024 * 1024;
+
+// Per read or write on a connection, so a silent client cannot block later requests
+const IO_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct HelperConfig {
+    pub socket_path: PathBuf,
+    pub allowed_uid: u32,
+    pub paths: HostsPaths,
+    pub protected: ProtectedConfig,
+    pub io_timeout: Duration,
+}
+
+#[cfg(target_os = "linux")]
+fn peer_uid(stream: &UnixStream) -> Result<u32, String> {
+    let mut cred = libc::ucred { pid: 0, uid: 0, gid: 0 };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    let rc = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut cred as *mut libc::ucred as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    if rc != 0 {
+        return Err(format!("Failed to read peer credentials: {}", std::io::Error::last_os_error()));
+    }
+    Ok(cred.uid)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn peer_uid(stream: &UnixStream) -> Result<u32, String> {
+    let mut uid: libc::uid_t = 0;
+    let mut gid: libc::gid_t = 0;
+    let rc = unsafe { libc::getpeereid(stream.as_raw_fd(), &mut uid, &mut gid) };
+    if rc != 0 {
+        return Err(format!("Failed to read peer credentials: {}", std::io::Error::last_os_error()));
+    }
+    Ok(uid)
+}
+
+// Only the installing user and root may talk to the helper
+fn is_allowed_peer(config: &HelperConfig, uid: u32) -> bool {
+    uid == config.allowed_uid || uid == 0
+}
+
+// Protected entries from the root-owned system config; the user config is not trusted here.
+// Missing entries are always refused, the app has already re-injected them if configured to.
+fn system_protected_config() -> ProtectedConfig {
+    let mut protected = config::load_from(&[Path::new(config::SYSTEM_CONFIG_PATH)])
+        .map(|config| config.protected)
+        .unwrap_or_else(|e| {
+            eprintln!("[HostDNI helper] {}; using default protected entries", e);
+            ProtectedConfig::default()
+        });
+    protected.on_missing = ProtectedMode::Refuse;
+    protected
+}
+
+// Replace the hosts file with `content` once it passes the write preflight
+fn write_hosts(config: &HelperConfig, content: &str) -> Result<(), String> {
+    let mut hosts_file = HostsFile::parse(content);
+    guard::preflight(&mut hosts_file, &config.protected).map_err(|report| report.message())?;
+    atomic::write_atomic(
+        &config.paths.hosts,
+        hosts_file.to_string().as_bytes(),
+        Some(&config.paths.backup),
+    )
+}
+
+fn backup_file(config: &HelperConfig, file_name: &str) -> Result<PathBuf, String> {
+    if !helper::is_valid_backup_name(file_name) {
+        return Err(format!("Invalid backup name: {}", file_name));
+    }
+    Ok(config.paths.backups_dir.join(file_name))
+}
+
+// Validate and perform a single request
+fn handle_request(config: &HelperConfig, request: HelperRequest) -> Result<(), String> {
+    match request {
+        HelperRequest::WriteHosts { content } => {
+            if content.contains('\0') {
+                return Err("Hosts content contains a NUL byte".to_string());
+            }
+            write_hosts(config, &content)
+        }
+        HelperRequest::Backup { file_name } => {
+            let target = backup_file(config, &file_name)?;
+            fs::create_dir_all(&config.paths.backups_dir)
+                .map_err(|e| format!("Failed to create backup directory: {}", e))?;
+            fs::copy(&config.paths.hosts, &target)
+                .map(|_| ())
+                .map_err(|e| format!("Failed to create backup: {}", e))
+        }
+        HelperRequest::Restore { file_name } => {
+            let source = backup_file(config, &file_name)?;
+            let content = fs::read_to_string(&source)
+                .map_err(|e| format!("Failed to read backup {}: {}", file_name, e))?;
+            write_hosts(config, &content)
+        }
+        HelperRequest::Disable => {
+            if config.paths.disabled.exists() {
+                return Err("Hosts file is already disabled".to_string());
+            }
+            fs::rename(&config.paths.hosts, &config.paths.disabled)
+                .map_err(|e| format!("Failed to disable hosts file: {}", e))
+        }
+        HelperRequest::Enable => {
+            if config.paths.hosts.exists() {
+                return Err("Hosts file already exists".to_string());
+            }
+            fs::rename(&config.paths.disabled, &config.paths.hosts)
+                .map_err(|e| format!("Failed to enable hosts file: {}", e))
+        }
+    }
+}
+
+// Read one newline-terminated JSON request of at most `limit` bytes
+fn read_request(reader: impl Read, limit: u64) -> Result<HelperRequest, String> {
+    let mut line = String::new();
+    BufReader::new(reader.take(limit))
+        .read_line(&mut line)
+        .map_err(|e| format!("Failed to read request: {}", e))?;
+    serde_json::from_str(&line).map_err(|e| format!("Invalid request: {}", e))
+}
+
+fn handle_connection(config: &HelperConfig, stream: UnixStream) -> Result<(), String> {
+    stream
+        .set_read_timeout(Some(config.io_timeout))
+        .and_then(|_| stream.set_write_timeout(Some(config.io_timeout)))
+        .map_err(|e| format!("Failed to set connection timeouts: {}", e))?;
+
+    let uid = peer_uid(&stream)?;
+    let result = if !is_allowed_peer(config, uid) {
+        Err(format!("Connection from uid {} rejected", uid))
+    } else {
+        read_request(&stream, MAX_REQUEST_BYTES).and_then(|request| handle_request(config, request))
+    };
+
+    let response = HelperResponse {
+        success: result.is_ok(),
+        error: result.err(),
+    };
+    let mut payload = serde_json::to_vec(&response).map_err(|e| e.to_string())?;
+    payload.push(b'\n');
+    (&stream).write_all(&payload).map_err(|e| e.to_string())
+}
+
+// Run the helper until the process is stopped. Requests are handled one at a
+// time, which also serializes writes to /etc/hosts.
+pub fn serve(config: HelperConfig) -> Result<(), String> {
+    use std::os::unix::fs::PermissionsExt;
+
+    if config.socket_path.exists() {
+        fs::remove_file(&config.socket_path)
+            .map_err(|e| format!("Failed to remove stale socket: {}", e))?;
+    }
+    let listener = UnixListener::bind(&config.socket_path)
+        .map_err(|e| format!("Failed to bind {}: {}", config.socket_path.display(), e))?;
+    fs::set_permissions(&config.socket_path, fs::Permissions::from_mode(0o600))
+        .map_err(|e| format!("Failed to restrict socket permissions: {}", e))?;
+    std::os::unix::fs::chown(&config.socket_path, Some(config.allowed_uid), None)
+        .map_err(|e| format!("Failed to set socket owner: {}", e))?;
+
+    println!("[HostDNI helper] Listening on {}", config.socket_path.display());
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                if let Err(e) = handle_connection(&config, stream) {
+                    eprintln!("[HostDNI helper] {}", e);
+                }
+            }
+            Err(e) => eprintln!("[HostDNI helper] Failed to accept connection: {}", e),
+        }
+    }
+    Ok(())
+}
+
+pub fn main() {
+    let mut config = HelperConfig {
+        socket_path: PathBuf::from(helper::DEFAULT_SOCKET_PATH),
+        allowed_uid: 0,
+        paths: HostsPaths::system(),
+        protected: system_protected_config(),
+        io_timeout: IO_TIMEOUT,
+    };
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = args.next();
+        match (arg.as_str(), value) {
+            ("--socket", Some(value)) => config.socket_path = PathBuf::from(value),
+            ("--allow-uid", Some(value)) => match value.parse() {
+                Ok(uid) => config.allowed_uid = uid,
+                Err(_) => {
+                    eprintln!("Invalid uid: {}", value);
+                    std::process::exit(2);
+                }
+            },
+            _ => {
+                eprintln!("Usage: hostdni-helper [--socket PATH] [--allow-uid UID]");
+                std::process::exit(2);
+            }
+        }
+    }
+
+    if let Err(e) = serve(config) {
+        eprintln!("[HostDNI helper] {}", e);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Shutdown;
+
+    const HOSTS: &str = "127.0.0.1 localhost\n255.255.255.255 broadcasthost\n::1 localhost\n";
+    const BACKUP_NAME: &str = "hosts_20240101_120000.backup";
+
+    fn scratch_config(name: &str) -> (PathBuf, HelperConfig) {
+        let dir = std::env::temp_dir().join(format!("hostdni-test-{}-{}", name, uuid::Uuid::new_v4().simple()));
+        fs::create_dir_all(&dir).unwrap();
+        let paths = HostsPaths::for_hosts_file(dir.join("hosts"));
+        fs::write(&paths.hosts, HOSTS).unwrap();
+        let config = HelperConfig {
+            socket_path: dir.join("helper.sock"),
+            allowed_uid: unsafe { libc::getuid() },
+            paths,
+            protected: ProtectedConfig { on_missing: ProtectedMode::Refuse, ..ProtectedConfig::default() },
+            io_timeout: Duration::from_millis(200),
+        };
+        (dir, config)
+    }
+
+    // Send `request` over a socket pair to `handle_connection` and return its response
+    fn exchange(config: &HelperConfig, request: &[u8]) -> HelperResponse {
+        let (mut client, server) = UnixStream::pair().unwrap();
+        client.write_all(request).unwrap();
+        client.shutdown(Shutdown::Write).unwrap();
+        handle_connection(config, server).unwrap();
+        let mut line = String::new();
+        BufReader::new(client).read_line(&mut line).unwrap();
+        serde_json::from_str(&line).unwrap()
+    }
+
+    #[test]
+    fn writes_hosts_files_and_keeps_the_previous_version() {
+        let (dir, config) = scratch_config("helper-write");
+        let content = format!("{}0.0.0.0 ads.example.com\n", HOSTS);
+        handle_request(&config, HelperRequest::WriteHosts { content: content.clone() }).unwrap();
+        assert_eq!(fs::read_to_string(&config.paths.hosts).unwrap(), content);
+        assert_eq!(fs::read_to_string(&config.paths.backup).unwrap(), HOSTS);
+
+        let error = handle_request(&config, HelperRequest::WriteHosts { content: format!("{}\0", HOSTS) }).unwrap_err();
+        assert!(error.contains("NUL"), "{}", error);
+        assert_eq!(fs::read_to_string(&config.paths.hosts).unwrap(), content);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn refuses_hosts_files_without_the_protected_entries() {
+        let (dir, config) = scratch_config("helper-refuse");
+        for content in ["0.0.0.0 ads.example.com\n", "# 127.0.0.1 localhost\n255.255.255.255 broadcasthost\n::1 localhost\n"] {
+            let error = handle_request(&config, HelperRequest::WriteHosts { content: content.to_string() }).unwrap_err();
+            assert!(error.contains("127.0.0.1 localhost"), "{}", error);
+        }
+
+        fs::create_dir_all(&config.paths.backups_dir).unwrap();
+        fs::write(config.paths.backups_dir.join(BACKUP_NAME), "0.0.0.0 ads.example.com\n").unwrap();
+        let error = handle_request(&config, HelperRequest::Restore { file_name: BACKUP_NAME.to_string() }).unwrap_err();
+        assert!(error.contains("127.0.0.1 localhost"), "{}", error);
+        assert_eq!(fs::read_to_string(&config.paths.hosts).unwrap(), HOSTS);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn backs_up_and_restores_only_validated_backup_names() {
+        let (dir, config) = scratch_config("helper-backup");
+        handle_request(&config, HelperRequest::Backup { file_name: BACKUP_NAME.to_string() }).unwrap();
+        assert_eq!(fs::read_to_string(config.paths.backups_dir.join(BACKUP_NAME)).unwrap(), HOSTS);
+
+        fs::write(&config.paths.hosts, "127.0.0.1 localhost\n").unwrap();
+        handle_request(&config, HelperRequest::Restore { file_name: BACKUP_NAME.to_string() }).unwrap();
+        assert_eq!(fs::read_to_string(&config.paths.hosts).unwrap(), HOSTS);
+
+        for file_name in ["../hosts", "hosts", "/etc/shadow", "hosts_20240101_120000.backup/../../x"] {
+            let backup = HelperRequest::Backup { file_name: file_name.to_string() };
+            assert!(handle_request(&config, backup).unwrap_err().starts_with("Invalid backup name"));
+            let restore = HelperRequest::Restore { file_name: file_name.to_string() };
+            assert!(handle_request(&config, restore).unwrap_err().starts_with("Invalid backup name"));
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn disables_and_enables_the_hosts_file() {
+        let (dir, config) = scratch_config("helper-toggle");
+        assert!(handle_request(&config, HelperRequest::Enable).is_err());
+        handle_request(&config, HelperRequest::Disable).unwrap();
+        assert!(!config.paths.hosts.exists());
+        assert!(handle_request(&config, HelperRequest::Disable).is_err());
+        handle_request(&config, HelperRequest::Enable).unwrap();
+        assert_eq!(fs::read_to_string(&config.paths.hosts).unwrap(), HOSTS);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn only_the_installing_user_and_root_are_allowed() {
+        let (dir, mut config) = scratch_config("helper-peer");
+        config.allowed_uid = 501;
+        assert!(is_allowed_peer(&config, 501));
+        assert!(is_allowed_peer(&config, 0));
+        assert!(!is_allowed_peer(&config, 502));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn reads_one_bounded_json_line_per_request() {
+        let request = read_request(&b"{\"op\":\"disable\"}\n{\"op\":\"enable\"}\n"[..], 1024).unwrap();
+        assert_eq!(request, HelperRequest::Disable);
+        // Unterminated requests are accepted at end of stream
+        assert_eq!(read_request(&b"{\"op\":\"enable\"}"[..], 1024).unwrap(), HelperRequest::Enable);
+
+        for (input, limit) in [
+            (&b"{\"op\":\"disable\"}\n"[..], 8),
+            (&b"{\"op\":\"chmod\",\"path\":\"/etc/shadow\"}\n"[..], 1024),
+            (&b"not json\n"[..], 1024),
+            (&b""[..], 1024),
+        ] {
+            let error = read_request(input, limit).unwrap_err();
+            assert!(error.starts_with("Invalid request"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn answers_every_connection_with_one_json_line() {
+        let (dir, config) = scratch_config("helper-connection");
+        let response = exchange(&config, b"{\"op\":\"disable\"}\n");
+        assert!(response.success);
+        assert!(config.paths.disabled.exists());
+
+        let response = exchange(&config, b"{\"op\":\"restore\"}\n");
+        assert!(!response.success);
+        assert!(response.error.unwrap().starts_with("Invalid request"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn silent_clients_time_out() {
+        let (dir, config) = scratch_config("helper-timeout");
+        let (client, server) = UnixStream::pair().unwrap();
+        // The failed read is still answered
+        handle_connection(&config, server).unwrap();
+        let mut line = String::new();
+        BufReader::new(client).read_line(&mut line).unwrap();
+        let response: HelperResponse = serde_json::from_str(&line).unwrap();
+        assert!(response.error.unwrap().starts_with("Failed to read request"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,18 @@
+// HostDNI privileged helper
+// - Runs as root (launchd / systemd), installed once from the app
+// - Usage: hostdni-helper [--socket PATH] [--allow-uid UID]
+// - Unix only; elsewhere it exits with an error (see daemon.rs)
+
+#[cfg(unix)]
+mod daemon;
+
+#[cfg(unix)]
+fn main() {
+    daemon::main();
+}
+
+#[cfg(not(unix))]
+fn main() {
+    eprintln!("hostdni-helper is only available on macOS and Linux");
+    std::process::exit(1);
+}
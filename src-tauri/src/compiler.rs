@@ -0,0 +1,363 @@
+// Hosts file compiler for HostDNI
+// - Builds the deployed hosts file from base entries, custom entries, the enabled block list
+//   and block sources, minus the enabled allow list and allow sources
+// - Blocked domains are deduplicated across sections (the first section wins), never shadow a
+//   base or custom entry, and all point at one sink address
+// - Output is deterministic: the same inputs always produce the same bytes, in labeled sections
+// - Custom entries are carried over from the current hosts file: the "Custom entries" section of
+//   a file HostDNI generated, or every non-base entry of a file it did not, except entries that
+//   point at a sink address (blocks from another tool, which the lists now provide)
+// - In managed-section mode the output is only the managed section; entries outside it are
+//   never blocked, and base entries they already provide are not repeated
+
+use crate::config::{self, ProtectedConfig};
+use crate::guard;
+use crate::hosts::{self, Entry, HostsFile, Line};
+use crate::sources::SourceKind;
+use crate::store::{ListKind, Store};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashSet};
+
+pub const HEADER: &str = "Generated by HostDNI. Only the \"Custom entries\" section is kept between deployments.";
+pub const CUSTOM_SECTION: &str = "Custom entries";
+
+// Addresses blocked domains commonly point at
+const SINK_ADDRESSES: &[&str] = &["0.0.0.0", "127.0.0.1", "::"];
+
+// A named set of domains to block
+#[derive(Debug, Clone)]
+pub struct BlockSet {
+    pub label: String,
+    pub domains: BTreeSet<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CompileInput {
+    pub base: Vec<Entry>,
+    pub custom: Vec<Entry>,
+    // Entries outside the managed section; empty when HostDNI writes the whole file
+    pub external: Vec<Entry>,
+    // In section order
+    pub blocked: Vec<BlockSet>,
+    // Allow-list patterns: exact domains, `*.suffix` or `/regex/`
+    pub allow_patterns: Vec<String>,
+    // Domains from allow sources
+    pub allowed: BTreeSet<String>,
+    pub sink_ip: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SectionStats {
+    pub label: String,
+    pub entries: usize,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CompileStats {
+    pub base_entries: usize,
+    pub custom_entries: usize,
+    pub blocked_domains: usize,
+    // Blocked domains dropped because the allow list matches them
+    pub allowed_removed: usize,
+    // Blocked domains already emitted by an earlier section
+    pub duplicates_removed: usize,
+    // Blocked domains dropped because a base, custom or external entry maps them
+    pub custom_overrides: usize,
+    // Allow-list regexes that failed to compile and were ignored
+    pub invalid_allow_patterns: Vec<String>,
+    pub sections: Vec<SectionStats>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CompiledHosts {
+    pub file: HostsFile,
+    pub stats: CompileStats,
+}
+
+// Section marker comment: `# ===== Label =====`
+fn section_marker(label: &str) -> Line {
+    Line::comment(&format!("===== {} =====", label))
+}
+
+// The label of a section marker line, if it is one
+fn section_label(raw: &str) -> Option<&str> {
+    raw.trim()
+        .strip_prefix('#')?
+        .trim()
+        .strip_prefix("=====")?
+        .strip_suffix("=====")
+        .map(str::trim)
+}
+
+fn section_marker_label(line: &Line) -> Option<&str> {
+    section_label(line.raw()?)
+}
+
+// Base entries are the protected entries (see guard.rs), so a compiled file always passes the preflight
+pub fn base_entries(config: &ProtectedConfig) -> Result<Vec<Entry>, String> {
+    guard::protected_entries(config)
+}
+
+// Custom entries to carry over from the current hosts file. A file HostDNI did not generate
+// loses its entries pointing at `sink_ip` or another sink address along with the base entries.
+pub fn custom_entries(current: &HostsFile, base: &[Entry], sink_ip: &str) -> Vec<Entry> {
+    let generated = current.lines.iter().any(|line| section_marker_label(line) == Some(CUSTOM_SECTION));
+
+    let mut in_custom = false;
+    let mut custom = Vec::new();
+    for line in &current.lines {
+        if let Some(label) = section_marker_label(line) {
+            in_custom = label == CUSTOM_SECTION;
+            continue;
+        }
+        let Some(entry) = line.as_entry() else { continue };
+        if generated && !in_custom {
+            continue;
+        }
+        if !generated && (entry.ip == sink_ip || SINK_ADDRESSES.contains(&entry.ip.as_str())) {
+            continue;
+        }
+        let is_base = base
+            .iter()
+            .any(|base| base.ip == entry.ip && base.hostname == entry.hostname && base.aliases == entry.aliases);
+        if !is_base {
+            custom.push(entry.clone());
+        }
+    }
+    custom
+}
+
+// Collect the compiler inputs from the store and the current hosts file. `current` is the part
+// HostDNI owns (the whole file or the managed section), `outside` the rest of the file.
+pub fn gather(store: &Store, current: &HostsFile, outside: &HostsFile, sink_ip: &str) -> Result<CompileInput, String> {
+    let protected = base_entries(&config::current().protected)?;
+    let custom = custom_entries(current, &protected, sink_ip);
+    let base = guard::missing_entries(outside, &protected);
+
+    let mut blocked = vec![BlockSet {
+        label: "Block list".to_string(),
+        domains: store.enabled_patterns(ListKind::Block)?.into_iter().collect(),
+    }];
+    let mut allowed = BTreeSet::new();
+    let mut sources: Vec<_> = store.list_sources()?.into_iter().filter(|source| source.enabled).collect();
+    sources.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.id.cmp(&b.id)));
+    for source in sources {
+        let domains = store.source_domains(&source.id)?;
+        match source.kind {
+            SourceKind::Block => blocked.push(BlockSet { label: format!("Source: {}", source.name), domains }),
+            SourceKind::Allow => allowed.extend(domains),
+        }
+    }
+
+    Ok(CompileInput {
+        base,
+        custom,
+        external: outside.entries().filter(|entry| entry.enabled).cloned().collect(),
+        blocked,
+        allow_patterns: store.enabled_patterns(ListKind::Allow)?,
+        allowed,
+        sink_ip: sink_ip.to_string(),
+    })
+}
+
+// Allow-list matcher built from exact domains, wildcard suffixes and regexes
+struct AllowMatcher {
+    exact: HashSet<String>,
+    // `*.example.com` matches subdomains of example.com, not example.com itself
+    suffixes: Vec<String>,
+    regexes: Vec<Regex>,
+    // Regex patterns that failed to compile
+    invalid: Vec<String>,
+}
+
+impl AllowMatcher {
+    fn new(patterns: &[String], allowed: &BTreeSet<String>) -> AllowMatcher {
+        let mut matcher = AllowMatcher {
+            exact: allowed.iter().cloned().collect(),
+            suffixes: Vec::new(),
+            regexes: Vec::new(),
+            invalid: Vec::new(),
+        };
+        for pattern in patterns {
+            if pattern.len() >= 2 && pattern.starts_with('/') && pattern.ends_with('/') {
+                match Regex::new(&pattern[1..pattern.len() - 1]) {
+                    Ok(regex) => matcher.regexes.push(regex),
+                    Err(_) => matcher.invalid.push(pattern.clone()),
+                }
+            } else if let Some(suffix) = pattern.strip_prefix("*.") {
+                matcher.suffixes.push(format!(".{}", suffix));
+            } else {
+                matcher.exact.insert(pattern.clone());
+            }
+        }
+        matcher
+    }
+
+    fn matches(&self, domain: &str) -> bool {
+        self.exact.contains(domain)
+            || self.suffixes.iter().any(|suffix| domain.ends_with(suffix.as_str()))
+            || self.regexes.iter().any(|regex| regex.is_match(domain))
+    }
+}
+
+pub fn compile(input: &CompileInput) -> Result<CompiledHosts, String> {
+    if !hosts::is_valid_ip(&input.sink_ip) {
+        return Err(format!("Invalid sink address: {}", input.sink_ip));
+    }
+
+    let mut file = HostsFile::default();
+    let mut stats = CompileStats::default();
+    file.push_line(Line::comment(HEADER));
+    file.push_line(Line::comment(&format!("Blocked domains point at {}", input.sink_ip)));
+
+    // Names with a real mapping are never blocked
+    let mut taken: HashSet<String> = HashSet::new();
+    for entry in input.base.iter().chain(input.custom.iter().chain(&input.external).filter(|entry| entry.enabled)) {
+        taken.insert(entry.hostname.to_ascii_lowercase());
+        taken.extend(entry.aliases.iter().map(|alias| alias.to_ascii_lowercase()));
+    }
+
+    let mut push_section = |file: &mut HostsFile, label: &str, entries: Vec<Entry>| {
+        file.push_line(Line::blank());
+        file.push_line(section_marker(label));
+        stats.sections.push(SectionStats { label: label.to_string(), entries: entries.len() });
+        for entry in entries {
+            file.push_entry(entry);
+        }
+    };
+
+    push_section(&mut file, "Base entries", input.base.clone());
+    push_section(&mut file, CUSTOM_SECTION, input.custom.clone());
+
+    let allow = AllowMatcher::new(&input.allow_patterns, &input.allowed);
+    let mut emitted: HashSet<&str> = HashSet::new();
+    let (mut allowed_removed, mut duplicates_removed, mut custom_overrides) = (0, 0, 0);
+    for set in &input.blocked {
+        let mut entries = Vec::new();
+        for domain in &set.domains {
+            if taken.contains(domain) {
+                custom_overrides += 1;
+            } else if allow.matches(domain) {
+                allowed_removed += 1;
+            } else if !emitted.insert(domain) {
+                duplicates_removed += 1;
+            } else {
+                entries.push(Entry {
+                    ip: input.sink_ip.clone(),
+                    hostname: domain.clone(),
+                    aliases: Vec::new(),
+                    comment: None,
+                    enabled: true,
+                });
+            }
+        }
+        push_section(&mut file, &set.label, entries);
+    }
+
+    stats.base_entries = input.base.len();
+    stats.custom_entries = input.custom.len();
+    stats.blocked_domains = emitted.len();
+    stats.allowed_removed = allowed_removed;
+    stats.duplicates_removed = duplicates_removed;
+    stats.custom_overrides = custom_overrides;
+    stats.invalid_allow_patterns = allow.invalid;
+    Ok(CompiledHosts { file, stats })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(label: &str, domains: &[&str]) -> BlockSet {
+        BlockSet {
+            label: label.to_string(),
+            domains: domains.iter().map(|domain| domain.to_string()).collect(),
+        }
+    }
+
+    fn input(current: &str) -> CompileInput {
+        let base = base_entries(&ProtectedConfig::default()).unwrap();
+        CompileInput {
+            custom: custom_entries(&HostsFile::parse(current), &base, "0.0.0.0"),
+            base,
+            external: Vec::new(),
+            blocked: vec![
+                set("Block list", &["ads.example.com", "dev.example.org"]),
+                set("Source: Test", &["ads.example.com", "cdn.good.net", "tracker.example.net"]),
+            ],
+            allow_patterns: vec!["*.good.net".to_string()],
+            allowed: BTreeSet::new(),
+            sink_ip: "0.0.0.0".to_string(),
+        }
+    }
+
+    #[test]
+    fn subtracts_allowed_and_deduplicates() {
+        let compiled = compile(&input("127.0.0.1 localhost\n10.0.0.5 dev.example.org\n")).unwrap();
+        let stats = &compiled.stats;
+        assert_eq!(stats.custom_entries, 1);
+        assert_eq!(stats.blocked_domains, 2);
+        assert_eq!((stats.allowed_removed, stats.duplicates_removed, stats.custom_overrides), (1, 1, 1));
+
+        let content = compiled.file.to_string();
+        assert!(content.contains("# ===== Custom entries =====\n10.0.0.5\tdev.example.org\n"));
+        assert!(content.contains("# ===== Block list =====\n0.0.0.0\tads.example.com\n"));
+        assert!(content.contains("# ===== Source: Test =====\n0.0.0.0\ttracker.example.net\n"));
+        assert!(!content.contains("cdn.good.net"));
+    }
+
+    #[test]
+    fn output_is_deterministic_and_keeps_custom_section() {
+        let first = compile(&input("10.0.0.5 dev.example.org\n")).unwrap().file.to_string();
+        assert_eq!(first, compile(&input("10.0.0.5 dev.example.org\n")).unwrap().file.to_string());
+
+        // Recompiling a generated file keeps only its custom section
+        assert_eq!(compile(&input(&first)).unwrap().file.to_string(), first);
+    }
+
+    #[test]
+    fn first_build_over_another_file_drops_its_blocks() {
+        let legacy = "127.0.0.1 localhost\n10.0.0.5 dev.example.org\n0.0.0.0 ads.example.com\n\
+                      127.0.0.1 tracker.example.net\n:: ipv6.example.com\n10.0.0.1 sink.example.com\n";
+        let base = base_entries(&ProtectedConfig::default()).unwrap();
+        let custom = custom_entries(&HostsFile::parse(legacy), &base, "10.0.0.1");
+        let hostnames: Vec<&str> = custom.iter().map(|entry| entry.hostname.as_str()).collect();
+        assert_eq!(hostnames, vec!["dev.example.org"]);
+
+        // 10.0.0.1 is not a sink for this build; the blocked domain is emitted by its list instead
+        let compiled = compile(&input(legacy)).unwrap();
+        assert_eq!(compiled.stats.custom_entries, 2);
+        assert!(compiled.file.to_string().contains("# ===== Block list =====\n0.0.0.0\tads.example.com\n"));
+    }
+
+    #[test]
+    fn never_blocks_names_mapped_outside_the_managed_section() {
+        let mut input = input("");
+        input.base.clear();
+        input.external = HostsFile::parse("172.17.0.2 tracker.example.net\n").entries().cloned().collect();
+        let compiled = compile(&input).unwrap();
+        assert_eq!(compiled.stats.custom_overrides, 1);
+        assert!(!compiled.file.to_string().contains("tracker.example.net"));
+        assert!(compiled.file.to_string().contains("# ===== Base entries =====\n\n"));
+    }
+
+    #[test]
+    fn allow_matcher_handles_exact_domains_wildcard_suffixes_and_regexes() {
+        let patterns: Vec<String> = ["good.org", "*.cdn.example.com", r"/^ads[0-9]+\.example\.net$/", "/[/"]
+            .iter()
+            .map(|pattern| pattern.to_string())
+            .collect();
+        let allowed = BTreeSet::from(["from-source.example".to_string()]);
+        let matcher = AllowMatcher::new(&patterns, &allowed);
+        assert_eq!(matcher.invalid, vec!["/[/".to_string()]);
+
+        for domain in ["good.org", "from-source.example", "a.cdn.example.com", "x.y.cdn.example.com", "ads1.example.net"] {
+            assert!(matcher.matches(domain), "{} is not allowed", domain);
+        }
+        // Wildcards only match subdomains, regexes are anchored by the pattern itself
+        for domain in ["sub.good.org", "cdn.example.com", "evilcdn.example.com", "ads.example.net", "ads1.example.net.evil"] {
+            assert!(!matcher.matches(domain), "{} is allowed", domain);
+        }
+    }
+}
@@ -0,0 +1,613 @@
+// Persistent configuration for HostDNI
+// - TOML files: /etc/hostdni/config.toml (system), then ~/.hostdni/config.toml (user) on top
+// - HOSTDNI_CONFIG__<SECTION>__<KEY> environment variables override both files
+//   (e.g. HOSTDNI_CONFIG__SERVER__PORT=9090); values are TOML, or plain strings
+// - Missing files and missing keys fall back to the built-in defaults
+// - Loaded once at startup and validated into a typed `Config`
+// - GET/PUT /api/config and the get_config/update_config commands read and replace it;
+//   updates save only the user layer (settings that differ from the defaults and the
+//   system file, never environment overrides) to the user file
+// - Everything except the server address applies live; see `restart_required`
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+pub const SYSTEM_CONFIG_PATH: &str = "/etc/hostdni/config.toml";
+pub const ENV_PREFIX: &str = "HOSTDNI_CONFIG__";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub host: String,
+    pub port: u16,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            host: "127.0.0.1".to_string(),
+            port: 8080,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    // How often the API token is replaced
+    pub token_rotation_secs: u64,
+    // How long the previous token keeps working after a rotation
+    pub token_grace_secs: u64,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        AuthConfig {
+            token_rotation_secs: 600,
+            token_grace_secs: 60,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BackupsConfig {
+    // Backup repository used by the desktop app, /api/backups/* and restores
+    pub dir: String,
+    // Earlier locations of the desktop app's backups and of the privileged snapshots (next to
+    // the hosts file when unset); their backups are imported into `dir` at startup
+    pub app_dir: String,
+    pub system_dir: Option<String>,
+    pub retention: RetentionConfig,
+}
+
+impl Default for BackupsConfig {
+    fn default() -> Self {
+        BackupsConfig {
+            dir: "~/hosts_backups".to_string(),
+            app_dir: "~/.hostdni/backups".to_string(),
+            system_dir: None,
+            retention: RetentionConfig::default(),
+        }
+    }
+}
+
+// Which backups pruning keeps (see retention.rs); pinned backups are always kept
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetentionConfig {
+    // The newest N backups
+    pub keep_last: usize,
+    // The newest backup of each of the last D days, today included
+    pub keep_daily_days: u32,
+    // The newest backup of each of the last W weeks (Monday to Sunday), this week included
+    pub keep_weekly_weeks: u32,
+    // Prune after every new backup
+    pub auto_prune: bool,
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        RetentionConfig {
+            keep_last: 10,
+            keep_daily_days: 7,
+            keep_weekly_weeks: 4,
+            auto_prune: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SourcesConfig {
+    // Last good download of every subscribed list, with its HTTP validators
+    pub cache_dir: String,
+}
+
+impl Default for SourcesConfig {
+    fn default() -> Self {
+        SourcesConfig {
+            cache_dir: "~/.hostdni/sources".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeployMode {
+    // HostDNI writes the entire hosts file
+    WholeFile,
+    // HostDNI owns only the `# BEGIN HostDNI` ... `# END HostDNI` block (see managed.rs)
+    ManagedSection,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DeployConfig {
+    // Address blocked domains are mapped to
+    pub sink_ip: String,
+    pub mode: DeployMode,
+}
+
+impl Default for DeployConfig {
+    fn default() -> Self {
+        DeployConfig {
+            sink_ip: "0.0.0.0".to_string(),
+            mode: DeployMode::WholeFile,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProtectedMode {
+    // Put missing entries back and write
+    Reinject,
+    // Abort the write
+    Refuse,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProtectedConfig {
+    // `ip hostname [aliases]` entries every written hosts file must contain
+    pub entries: Vec<String>,
+    pub on_missing: ProtectedMode,
+}
+
+impl Default for ProtectedConfig {
+    fn default() -> Self {
+        ProtectedConfig {
+            entries: vec![
+                "127.0.0.1 localhost".to_string(),
+                "255.255.255.255 broadcasthost".to_string(),
+                "::1 localhost".to_string(),
+            ],
+            on_missing: ProtectedMode::Reinject,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+    pub default_page_size: usize,
+    pub min_page_size: usize,
+    pub max_page_size: usize,
+    pub max_chunk_size: usize,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        LimitsConfig {
+            default_page_size: 1000,
+            min_page_size: 1000,
+            max_page_size: 20000,
+            max_chunk_size: 10000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub auth: AuthConfig,
+    pub backups: BackupsConfig,
+    pub sources: SourcesConfig,
+    pub deploy: DeployConfig,
+    pub protected: ProtectedConfig,
+    pub limits: LimitsConfig,
+}
+
+impl Config {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.server.host.parse::<std::net::IpAddr>().is_err() {
+            return Err(format!("server.host must be an IP address: {}", self.server.host));
+        }
+        if self.server.port == 0 {
+            return Err("server.port must be between 1 and 65535".to_string());
+        }
+        if self.auth.token_rotation_secs < 60 {
+            return Err("auth.token_rotation_secs must be at least 60".to_string());
+        }
+        if self.auth.token_grace_secs >= self.auth.token_rotation_secs {
+            return Err("auth.token_grace_secs must be shorter than auth.token_rotation_secs".to_string());
+        }
+        if self.backups.dir.trim().is_empty() || self.backups.app_dir.trim().is_empty() {
+            return Err("backups.dir and backups.app_dir must not be empty".to_string());
+        }
+        if self.backups.system_dir.as_deref().is_some_and(|dir| dir.trim().is_empty()) {
+            return Err("backups.system_dir must not be empty when set".to_string());
+        }
+        // The backup just taken is the newest one, so pruning never removes it
+        if self.backups.retention.keep_last == 0 {
+            return Err("backups.retention.keep_last must be at least 1".to_string());
+        }
+        if self.sources.cache_dir.trim().is_empty() {
+            return Err("sources.cache_dir must not be empty".to_string());
+        }
+        if !crate::hosts::is_valid_ip(&self.deploy.sink_ip) {
+            return Err(format!("deploy.sink_ip must be an IP address: {}", self.deploy.sink_ip));
+        }
+        // An empty list would silently turn the write guard off
+        if self.protected.entries.is_empty() {
+            return Err("protected.entries must not be empty".to_string());
+        }
+        crate::guard::protected_entries(&self.protected).map_err(|e| format!("protected.entries: {}", e))?;
+        let limits = &self.limits;
+        if limits.min_page_size == 0 || limits.max_chunk_size == 0 {
+            return Err("limits.min_page_size and limits.max_chunk_size must be greater than 0".to_string());
+        }
+        if limits.min_page_size > limits.max_page_size {
+            return Err("limits.min_page_size must not exceed limits.max_page_size".to_string());
+        }
+        if limits.default_page_size < limits.min_page_size || limits.default_page_size > limits.max_page_size {
+            return Err("limits.default_page_size must be between limits.min_page_size and limits.max_page_size".to_string());
+        }
+        Ok(())
+    }
+
+    // Settings that only take effect after a restart and differ from `running`
+    pub fn restart_required(&self, running: &Config) -> Vec<String> {
+        let mut fields = Vec::new();
+        if self.server.host != running.server.host {
+            fields.push("server.host".to_string());
+        }
+        if self.server.port != running.server.port {
+            fields.push("server.port".to_string());
+        }
+        fields
+    }
+
+    pub fn bind_address(&self) -> String {
+        format!("{}:{}", self.server.host, self.server.port)
+    }
+
+    // Clamp a requested page size to the configured limits
+    pub fn page_size(&self, requested: Option<usize>) -> usize {
+        requested
+            .unwrap_or(self.limits.default_page_size)
+            .clamp(self.limits.min_page_size, self.limits.max_page_size)
+    }
+
+    pub fn backups_dir(&self) -> PathBuf {
+        expand_home(&self.backups.dir)
+    }
+
+    pub fn app_backups_dir(&self) -> PathBuf {
+        expand_home(&self.backups.app_dir)
+    }
+
+    pub fn system_backups_dir(&self) -> Option<PathBuf> {
+        self.backups.system_dir.as_deref().map(expand_home)
+    }
+
+    pub fn sources_cache_dir(&self) -> PathBuf {
+        expand_home(&self.sources.cache_dir)
+    }
+}
+
+// Expand a leading `~` to $HOME
+pub fn expand_home(path: &str) -> PathBuf {
+    let home = || std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string());
+    if path == "~" {
+        PathBuf::from(home())
+    } else if let Some(rest) = path.strip_prefix("~/") {
+        Path::new(&home()).join(rest)
+    } else {
+        PathBuf::from(path)
+    }
+}
+
+pub fn user_config_path() -> PathBuf {
+    expand_home("~/.hostdni/config.toml")
+}
+
+// Overlay `overlay` onto `base`, table by table
+fn merge(base: &mut toml::Value, overlay: toml::Value) {
+    match (base, overlay) {
+        (toml::Value::Table(base), toml::Value::Table(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+fn read_toml(path: &Path) -> Result<Option<toml::Value>, String> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    toml::from_str(&content)
+        .map(Some)
+        .map_err(|e| format!("Invalid config {}: {}", path.display(), e))
+}
+
+// The config layer set by HOSTDNI_CONFIG__ variables in `vars`
+fn env_overrides(vars: impl IntoIterator<Item = (String, String)>) -> Result<toml::Value, String> {
+    let mut overrides = toml::Value::Table(toml::map::Map::new());
+    for (name, raw) in vars {
+        let Some(key) = name.strip_prefix(ENV_PREFIX) else {
+            continue;
+        };
+        let keys: Vec<String> = key.split("__").map(str::to_lowercase).collect();
+        if keys.iter().any(String::is_empty) {
+            return Err(format!("Invalid config variable: {}", name));
+        }
+        // Parsed as the value of a TOML key, so `9090`, `true` and `["a"]` keep their types
+        let mut value = toml::from_str::<toml::Table>(&format!("value = {}", raw))
+            .ok()
+            .and_then(|mut table| table.remove("value"))
+            .unwrap_or(toml::Value::String(raw));
+        for key in keys.into_iter().rev() {
+            value = toml::Value::Table(toml::map::Map::from_iter([(key, value)]));
+        }
+        merge(&mut overrides, value);
+    }
+    Ok(overrides)
+}
+
+// Merge `layers` in order over the defaults, and validate
+fn merge_layers(layers: impl IntoIterator<Item = toml::Value>) -> Result<Config, String> {
+    let mut merged = toml::Value::Table(toml::map::Map::new());
+    for layer in layers {
+        merge(&mut merged, layer);
+    }
+    let config: Config = merged.try_into().map_err(|e| format!("Invalid config: {}", e))?;
+    config.validate()?;
+    Ok(config)
+}
+
+// Merge the files in `paths` and then `overrides` over the defaults, and validate
+fn load_layers(paths: &[&Path], overrides: Option<toml::Value>) -> Result<Config, String> {
+    let mut layers = Vec::new();
+    for path in paths {
+        if let Some(value) = read_toml(path)? {
+            layers.push(value);
+        }
+    }
+    layers.extend(overrides);
+    merge_layers(layers)
+}
+
+// Load and validate the layered configuration files
+pub fn load_from(paths: &[&Path]) -> Result<Config, String> {
+    load_layers(paths, None)
+}
+
+pub fn load() -> Result<Config, String> {
+    let overrides = env_overrides(std::env::vars())?;
+    load_layers(&[Path::new(SYSTEM_CONFIG_PATH), &user_config_path()], Some(overrides))
+}
+
+static CONFIG: Lazy<RwLock<Config>> = Lazy::new(|| {
+    RwLock::new(load().unwrap_or_else(|e| {
+        println!("[HostDNI] {}; using default configuration", e);
+        Config::default()
+    }))
+});
+
+// The configuration currently in effect
+pub fn current() -> Config {
+    CONFIG.read().unwrap().clone()
+}
+
+// The configuration the process started with (what the REST server is bound to)
+static STARTUP: Lazy<Config> = Lazy::new(current);
+
+pub fn startup() -> &'static Config {
+    &STARTUP
+}
+
+// The keys of `value` that differ from `base`; None when nothing differs
+fn changed_keys(value: toml::Value, base: &toml::Value) -> Option<toml::Value> {
+    match (value, base) {
+        (toml::Value::Table(table), toml::Value::Table(base)) => {
+            let changed: toml::map::Map<String, toml::Value> = table
+                .into_iter()
+                .filter_map(|(key, value)| match base.get(&key) {
+                    Some(base) => changed_keys(value, base).map(|value| (key, value)),
+                    None => Some((key, value)),
+                })
+                .collect();
+            (!changed.is_empty()).then_some(toml::Value::Table(changed))
+        }
+        (value, base) => (&value != base).then_some(value),
+    }
+}
+
+// Give every key set in `overrides` its value from `previous` in `layer`, or drop it,
+// so environment values never end up in the user file
+fn keep_overridden(layer: &mut toml::Table, overrides: &toml::Table, previous: Option<&toml::Table>) {
+    for (key, overridden) in overrides {
+        let previous = previous.and_then(|previous| previous.get(key));
+        match overridden {
+            toml::Value::Table(overrides) => {
+                let table = layer.entry(key.as_str()).or_insert_with(|| toml::Value::Table(toml::Table::new()));
+                if let toml::Value::Table(table) = table {
+                    keep_overridden(table, overrides, previous.and_then(toml::Value::as_table));
+                    if table.is_empty() {
+                        layer.remove(key);
+                    }
+                }
+            }
+            _ => match previous {
+                Some(value) => {
+                    layer.insert(key.clone(), value.clone());
+                }
+                None => {
+                    layer.remove(key);
+                }
+            },
+        }
+    }
+}
+
+// Write the user file that gives `config` when layered between the system file and
+// `overrides`, and return the configuration in effect with it
+fn save_user_layer(config: &Config, system: &Path, user: &Path, overrides: toml::Value) -> Result<Config, String> {
+    config.validate()?;
+
+    let encode = |config: &Config| toml::Value::try_from(config).map_err(|e| format!("Failed to encode config: {}", e));
+    let system = read_toml(system)?;
+    let mut base = encode(&Config::default())?;
+    if let Some(ref system) = system {
+        merge(&mut base, system.clone());
+    }
+    let mut layer = match changed_keys(encode(config)?, &base) {
+        Some(toml::Value::Table(layer)) => layer,
+        _ => toml::Table::new(),
+    };
+    if let toml::Value::Table(ref overrides) = overrides {
+        // An unreadable user file is replaced
+        let previous = read_toml(user).ok().flatten();
+        keep_overridden(&mut layer, overrides, previous.as_ref().and_then(toml::Value::as_table));
+    }
+    let effective = merge_layers(system.into_iter().chain([toml::Value::Table(layer.clone()), overrides]))?;
+
+    if let Some(dir) = user.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    }
+    let content = toml::to_string_pretty(&layer).map_err(|e| format!("Failed to encode config: {}", e))?;
+    crate::atomic::write_atomic(user, content.as_bytes(), None)?;
+    Ok(effective)
+}
+
+// Validate, persist the user layer of `config` and apply it.
+// Returns the settings that need a restart to take effect.
+pub fn update(config: Config) -> Result<Vec<String>, String> {
+    let overrides = env_overrides(std::env::vars())?;
+    let config = save_user_layer(&config, Path::new(SYSTEM_CONFIG_PATH), &user_config_path(), overrides)?;
+
+    let restart_required = config.restart_required(startup());
+    *CONFIG.write().unwrap() = config;
+    Ok(restart_required)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("hostdni-test-{}-{}", name, uuid::Uuid::new_v4().simple()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn vars(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn layers_override_defaults_system_then_user_then_env() {
+        let dir = scratch_dir("config-layers");
+        let system = dir.join("system.toml");
+        let user = dir.join("user.toml");
+        fs::write(&system, "[server]\nport = 9000\nhost = \"0.0.0.0\"\n[deploy]\nsink_ip = \"127.0.0.1\"\n").unwrap();
+        fs::write(&user, "[server]\nport = 9100\n[backups.retention]\nkeep_last = 3\n").unwrap();
+        let env = env_overrides(vars(&[
+            ("HOSTDNI_CONFIG__SERVER__PORT", "9200"),
+            ("HOSTDNI_CONFIG__SOURCES__CACHE_DIR", "/var/cache/hostdni"),
+            ("HOSTDNI_DB_PATH", "/ignored.db"),
+        ]))
+        .unwrap();
+
+        let config = load_layers(&[&system, &user], Some(env)).unwrap();
+        assert_eq!(config.server.port, 9200);
+        assert_eq!(config.server.host, "0.0.0.0");
+        assert_eq!(config.deploy.sink_ip, "127.0.0.1");
+        assert_eq!(config.backups.retention.keep_last, 3);
+        assert_eq!(config.sources.cache_dir, "/var/cache/hostdni");
+        assert_eq!(config.limits, LimitsConfig::default());
+
+        let config = load_from(&[&system, &user]).unwrap();
+        assert_eq!(config.server.port, 9100);
+        assert_eq!(load_from(&[&system]).unwrap().server.port, 9000);
+        assert_eq!(load_from(&[&dir.join("missing.toml")]).unwrap(), Config::default());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn saves_only_the_user_layer() {
+        let dir = scratch_dir("config-save");
+        let system = dir.join("system.toml");
+        let user = dir.join("user.toml");
+        fs::write(&system, "[server]\nhost = \"0.0.0.0\"\n").unwrap();
+        fs::write(&user, "[server]\nport = 9100\n[sources]\ncache_dir = \"~/cache\"\n").unwrap();
+        let env = env_overrides(vars(&[
+            ("HOSTDNI_CONFIG__SOURCES__CACHE_DIR", "/var/cache/hostdni"),
+            ("HOSTDNI_CONFIG__DEPLOY__SINK_IP", "127.0.0.1"),
+        ]))
+        .unwrap();
+
+        let mut config = load_layers(&[&system, &user], Some(env.clone())).unwrap();
+        config.backups.retention.keep_last = 3;
+        assert_eq!(save_user_layer(&config, &system, &user, env).unwrap(), config);
+
+        // System and environment values stay out; the user's own value under an override is kept
+        let written: toml::Value = toml::from_str(&fs::read_to_string(&user).unwrap()).unwrap();
+        let expected: toml::Value =
+            toml::from_str("[server]\nport = 9100\n[sources]\ncache_dir = \"~/cache\"\n[backups.retention]\nkeep_last = 3\n")
+                .unwrap();
+        assert_eq!(written, expected);
+
+        // Later changes to the system file still apply
+        fs::write(&system, "[server]\nhost = \"127.0.0.1\"\n").unwrap();
+        let reloaded = load_from(&[&system, &user]).unwrap();
+        assert_eq!((reloaded.server.host.as_str(), reloaded.server.port), ("127.0.0.1", 9100));
+        assert_eq!(reloaded.deploy.sink_ip, DeployConfig::default().sink_ip);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_unknown_keys_and_bad_overrides() {
+        let dir = scratch_dir("config-invalid");
+        let path = dir.join("config.toml");
+        fs::write(&path, "[server]\nprot = 9000\n").unwrap();
+        assert!(load_from(&[&path]).unwrap_err().contains("prot"));
+
+        let env = env_overrides(vars(&[("HOSTDNI_CONFIG__SERVER__PORT", "not a port")])).unwrap();
+        assert!(load_layers(&[], Some(env)).is_err());
+        assert!(env_overrides(vars(&[("HOSTDNI_CONFIG__SERVER____PORT", "1")])).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn validates_every_section() {
+        assert_eq!(Config::default().validate(), Ok(()));
+
+        let invalid: Vec<fn(&mut Config)> = vec![
+            |c| c.server.host = "localhost".to_string(),
+            |c| c.server.port = 0,
+            |c| c.auth.token_rotation_secs = 59,
+            |c| c.auth.token_grace_secs = c.auth.token_rotation_secs,
+            |c| c.backups.dir = " ".to_string(),
+            |c| c.backups.system_dir = Some(String::new()),
+            |c| c.backups.retention.keep_last = 0,
+            |c| c.sources.cache_dir = String::new(),
+            |c| c.deploy.sink_ip = "0.0.0".to_string(),
+            |c| c.protected.entries = vec!["localhost".to_string()],
+            |c| c.protected.entries.clear(),
+            |c| c.limits.min_page_size = 0,
+            |c| c.limits.min_page_size = c.limits.max_page_size + 1,
+            |c| c.limits.default_page_size = c.limits.max_page_size + 1,
+        ];
+        for (index, change) in invalid.into_iter().enumerate() {
+            let mut config = Config::default();
+            change(&mut config);
+            assert!(config.validate().is_err(), "case {} passed validation", index);
+        }
+    }
+}
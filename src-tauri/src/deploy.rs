@@ -0,0 +1,150 @@
+// Deployment pipeline for HostDNI
+// - Shared by the REST API (api.rs) and the Tauri commands (main.rs)
+// - `build` compiles the candidate hosts file from the current file, the lists and the sources
+// - `assemble` turns the compiled part into the full file for the deploy mode: the part itself,
+//   or the current file with its managed section replaced; the write preflight runs here
+// - `preview` compares the candidate with the current file; it only reads, so it needs no privileges
+// - `write` replaces the hosts file through the privileged executor
+
+use crate::compiler::{self, CompileStats, CompiledHosts};
+use crate::config::{self, DeployMode};
+use crate::diff::{self, EntryChanges};
+use crate::guard::{self, GuardReport};
+use crate::hosts::{self, HostsFile};
+use crate::managed;
+use crate::paths::{self, HostsPaths};
+use crate::privilege::{self, PrivilegedAction, PrivilegedExecutor};
+use crate::store;
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HostsPreview {
+    // The file a deployment would write
+    pub content: String,
+    // Unified diff from the current hosts file to `content`
+    pub diff: String,
+    pub changes: EntryChanges,
+    pub stats: CompileStats,
+    // Set when the write preflight would re-inject protected entries
+    pub guard: Option<GuardReport>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Assembled {
+    // The full hosts file to write
+    pub content: String,
+    pub guard: Option<GuardReport>,
+}
+
+// Sink address for a build: `requested`, or deploy.sink_ip
+pub fn sink_ip(requested: Option<&str>) -> Result<String, String> {
+    let sink_ip = requested.map(str::to_string).unwrap_or_else(|| config::current().deploy.sink_ip);
+    if !hosts::is_valid_ip(&sink_ip) {
+        return Err(format!("Invalid sink address: {}", sink_ip));
+    }
+    Ok(sink_ip)
+}
+
+// Text of the current hosts file; empty when there is none yet
+pub fn read_current() -> Result<String, String> {
+    let paths = paths::hosts_paths();
+    if paths.is_disabled() {
+        return Err("Hosts file is currently disabled".to_string());
+    }
+    if !paths.hosts.exists() {
+        return Ok(String::new());
+    }
+    fs::read_to_string(&paths.hosts).map_err(|e| format!("Failed to read hosts file: {}", e))
+}
+
+// Replace the hosts file with `content`; callers have already run the write preflight
+pub fn write(content: &str) -> Result<(), String> {
+    write_to(paths::hosts_paths(), privilege::executor().as_ref(), content)
+}
+
+fn write_to(paths: &HostsPaths, executor: &dyn PrivilegedExecutor, content: &str) -> Result<(), String> {
+    if paths.is_disabled() {
+        return Err("Cannot write to hosts file while it is disabled".to_string());
+    }
+    executor
+        .execute(&[PrivilegedAction::WriteBytes {
+            path: paths.hosts.clone(),
+            contents: content.as_bytes().to_vec(),
+            rollback: Some(paths.backup.clone()),
+        }])
+        .map_err(|e| format!("Failed to save hosts file: {}", e))
+}
+
+// Compile HostDNI's part of the hosts file against `current`
+pub fn build(current: &str, sink_ip: &str, mode: DeployMode) -> Result<CompiledHosts, String> {
+    let (owned, outside) = match mode {
+        DeployMode::WholeFile => (HostsFile::parse(current), HostsFile::default()),
+        DeployMode::ManagedSection => {
+            let sections = managed::split(current)?;
+            (HostsFile::parse(sections.inside()), HostsFile::parse(&sections.outside()))
+        }
+    };
+    let input = compiler::gather(store::store(), &owned, &outside, sink_ip)?;
+    compiler::compile(&input)
+}
+
+// The full file to write with `part` as HostDNI's part of it, after the write preflight.
+// `current` is only read in managed-section mode.
+pub fn assemble(current: &str, part: &mut HostsFile, mode: DeployMode) -> Result<Assembled, String> {
+    let protected = &config::current().protected;
+    match mode {
+        DeployMode::WholeFile => {
+            let guard = guard::preflight(part, protected).map_err(|report| report.message())?;
+            Ok(Assembled { content: part.to_string(), guard })
+        }
+        DeployMode::ManagedSection => {
+            let sections = managed::split(current)?;
+            let outside = HostsFile::parse(&sections.outside());
+            let guard = guard::preflight_section(part, &outside, protected).map_err(|report| report.message())?;
+            Ok(Assembled { content: sections.replace(&part.to_string())?, guard })
+        }
+    }
+}
+
+// Compile the hosts file and compare it with the current one, without writing anything
+pub fn preview(sink_ip: &str) -> Result<HostsPreview, String> {
+    let mode = config::current().deploy.mode;
+    let current = read_current()?;
+    let mut compiled = build(&current, sink_ip, mode)?;
+    let Assembled { content, guard } = assemble(&current, &mut compiled.file, mode)?;
+    let label = paths::hosts_paths().hosts.display().to_string();
+
+    Ok(HostsPreview {
+        diff: diff::unified_diff(&current, &content, &label, &format!("{} (preview)", label)),
+        changes: diff::entry_changes(&HostsFile::parse(&current), &HostsFile::parse(&content)),
+        content,
+        stats: compiled.stats,
+        guard,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::privilege::FakeExecutor;
+
+    #[test]
+    fn refuses_to_write_while_the_hosts_file_is_disabled() {
+        let dir = std::env::temp_dir().join(format!("hostdni-deploy-{}", uuid::Uuid::new_v4().simple()));
+        fs::create_dir_all(&dir).unwrap();
+        let paths = HostsPaths::for_hosts_file(dir.join("hosts"));
+        fs::write(&paths.disabled, "127.0.0.1 localhost\n").unwrap();
+        let executor = FakeExecutor::default();
+
+        let error = write_to(&paths, &executor, "127.0.0.1 localhost\n").unwrap_err();
+        assert!(error.contains("disabled"));
+        assert!(executor.actions.lock().unwrap().is_empty());
+
+        // Enabled again: the write goes through the executor
+        fs::rename(&paths.disabled, &paths.hosts).unwrap();
+        write_to(&paths, &executor, "127.0.0.1 localhost\n").unwrap();
+        assert_eq!(executor.actions.lock().unwrap().len(), 1);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
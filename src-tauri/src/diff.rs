@@ -0,0 +1,174 @@
+// Hosts file comparison for HostDNI
+// - Unified text diff of two versions of a hosts file
+// - Entry-level comparison using the shared parser: a name is keyed by itself and its address
+//   family, so `127.0.0.1 localhost` and `::1 localhost` are tracked separately
+// - Disabled (commented-out) entries take part, so enabling or disabling one counts as a change
+// - `entry_diff` lists the names that were added, removed, toggled or moved to another address;
+//   `entry_changes` only counts them
+
+use crate::hosts::HostsFile;
+use serde::{Deserialize, Serialize};
+use similar::{Algorithm, TextDiff};
+use std::collections::HashMap;
+use std::time::Duration;
+
+// Lines of context around each change
+const CONTEXT_LINES: usize = 3;
+
+// Give up on a minimal diff after this long and fall back to a coarser one
+const DIFF_DEADLINE: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EntryChanges {
+    // Names mapped only in the new version
+    pub added: usize,
+    // Names mapped only in the old version
+    pub removed: usize,
+    // Names mapped in both, with a different address or enabled state
+    pub changed: usize,
+}
+
+// One name's mapping in one version of the file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MappedName {
+    pub name: String,
+    pub ip: String,
+    pub enabled: bool,
+}
+
+// One name mapped in both versions, differently
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NameChange {
+    pub name: String,
+    pub old_ip: String,
+    pub new_ip: String,
+    pub old_enabled: bool,
+    pub new_enabled: bool,
+}
+
+// Every list is sorted by name, IPv4 before IPv6
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EntryDiff {
+    pub added: Vec<MappedName>,
+    pub removed: Vec<MappedName>,
+    // Same address, enabled or disabled
+    pub toggled: Vec<NameChange>,
+    // Another address; the enabled state may have changed too
+    pub ip_changed: Vec<NameChange>,
+}
+
+impl EntryDiff {
+    pub fn counts(&self) -> EntryChanges {
+        EntryChanges {
+            added: self.added.len(),
+            removed: self.removed.len(),
+            changed: self.toggled.len() + self.ip_changed.len(),
+        }
+    }
+}
+
+// Unified diff of `old` and `new`; empty when they are identical
+pub fn unified_diff(old: &str, new: &str, old_label: &str, new_label: &str) -> String {
+    TextDiff::configure()
+        .algorithm(Algorithm::Patience)
+        .timeout(DIFF_DEADLINE)
+        .diff_lines(old, new)
+        .unified_diff()
+        .context_radius(CONTEXT_LINES)
+        .header(old_label, new_label)
+        .to_string()
+}
+
+// (name, is IPv6) -> (address, enabled)
+fn entry_map(file: &HostsFile) -> HashMap<(String, bool), (&str, bool)> {
+    let mut map = HashMap::new();
+    // Enabled entries win over disabled ones for the same name
+    for enabled in [true, false] {
+        for entry in file.entries().filter(|entry| entry.enabled == enabled) {
+            let is_v6 = entry.ip.contains(':');
+            for name in std::iter::once(&entry.hostname).chain(entry.aliases.iter()) {
+                map.entry((name.to_ascii_lowercase(), is_v6))
+                    .or_insert((entry.ip.as_str(), entry.enabled));
+            }
+        }
+    }
+    map
+}
+
+pub fn entry_diff(old: &HostsFile, new: &HostsFile) -> EntryDiff {
+    let old_entries = entry_map(old);
+    let new_entries = entry_map(new);
+    let mapped = |(name, _): &(String, bool), &(ip, enabled): &(&str, bool)| MappedName {
+        name: name.clone(),
+        ip: ip.to_string(),
+        enabled,
+    };
+
+    let mut keys: Vec<&(String, bool)> = old_entries.keys().chain(new_entries.keys()).collect();
+    keys.sort();
+    keys.dedup();
+    let mut diff = EntryDiff::default();
+    for key in keys {
+        match (old_entries.get(key), new_entries.get(key)) {
+            (None, Some(new_value)) => diff.added.push(mapped(key, new_value)),
+            (Some(old_value), None) => diff.removed.push(mapped(key, old_value)),
+            (Some(&(old_ip, old_enabled)), Some(&(new_ip, new_enabled))) if (old_ip, old_enabled) != (new_ip, new_enabled) => {
+                let change = NameChange {
+                    name: key.0.clone(),
+                    old_ip: old_ip.to_string(),
+                    new_ip: new_ip.to_string(),
+                    old_enabled,
+                    new_enabled,
+                };
+                if old_ip == new_ip {
+                    diff.toggled.push(change);
+                } else {
+                    diff.ip_changed.push(change);
+                }
+            }
+            _ => {}
+        }
+    }
+    diff
+}
+
+pub fn entry_changes(old: &HostsFile, new: &HostsFile) -> EntryChanges {
+    entry_diff(old, new).counts()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_added_removed_and_changed_names() {
+        let old = HostsFile::parse("127.0.0.1 localhost\n10.0.0.1 nas.lan\n10.0.0.2 printer.lan\n0.0.0.0 ads.example.com\n");
+        let new = HostsFile::parse("127.0.0.1 localhost\n::1 localhost\n10.0.0.9 nas.lan\n# 10.0.0.2 printer.lan\n");
+        let changes = entry_changes(&old, &new);
+        assert_eq!(changes, EntryChanges { added: 1, removed: 1, changed: 2 });
+    }
+
+    #[test]
+    fn lists_each_kind_of_entry_change() {
+        let old = HostsFile::parse("127.0.0.1 localhost\n10.0.0.1 nas.lan\n10.0.0.2 printer.lan\n# 10.0.0.3 wiki.lan\n0.0.0.0 ads.example.com\n");
+        let new = HostsFile::parse("127.0.0.1 localhost\n::1 localhost\n10.0.0.9 nas.lan\n# 10.0.0.2 printer.lan\n# 10.0.0.4 wiki.lan\n");
+        let diff = entry_diff(&old, &new);
+        assert_eq!(diff.added, vec![MappedName { name: "localhost".to_string(), ip: "::1".to_string(), enabled: true }]);
+        assert_eq!(diff.removed.iter().map(|name| name.name.as_str()).collect::<Vec<_>>(), vec!["ads.example.com"]);
+        assert_eq!(diff.toggled.len(), 1);
+        assert_eq!((diff.toggled[0].name.as_str(), diff.toggled[0].new_enabled), ("printer.lan", false));
+        assert_eq!(
+            diff.ip_changed.iter().map(|change| (change.name.as_str(), change.new_ip.as_str())).collect::<Vec<_>>(),
+            vec![("nas.lan", "10.0.0.9"), ("wiki.lan", "10.0.0.4")]
+        );
+        assert_eq!(diff.counts(), EntryChanges { added: 1, removed: 1, changed: 3 });
+        assert_eq!(entry_diff(&new, &new), EntryDiff::default());
+    }
+
+    #[test]
+    fn identical_files_have_an_empty_diff() {
+        let content = "127.0.0.1 localhost\n";
+        assert_eq!(unified_diff(content, content, "a", "b"), "");
+        assert!(unified_diff(content, "", "a", "b").contains("-127.0.0.1 localhost"));
+    }
+}
@@ -0,0 +1,179 @@
+// Domain and pattern validation for HostDNI allow and block lists
+// - Domains are normalized (trimmed, lowercased, trailing dot removed) before validation;
+//   internationalized names are converted to punycode (`bücher.example` -> `xn--bcher-kva.example`)
+// - Labels: 1-63 letters, digits or '-', not starting or ending with '-'; 253 characters at most
+// - Block-list entries are plain domains; a bare URL (`https://example.com/`) is reduced
+//   to its host, but URLs with a path are rejected
+// - Allow-list patterns are an exact domain, a wildcard suffix (`*.example.com`) or a
+//   regex written between slashes (`/^ads[0-9]*\.example\.com$/`)
+
+use crate::hosts;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PatternKind {
+    Exact,
+    WildcardSuffix,
+    Regex,
+}
+
+fn is_valid_label(label: &str) -> bool {
+    !label.is_empty()
+        && label.len() <= 63
+        && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        && !label.starts_with('-')
+        && !label.ends_with('-')
+}
+
+// Validate and normalize a domain name
+pub fn normalize_domain(input: &str) -> Result<String, String> {
+    let domain = input.trim().trim_end_matches('.');
+    let domain = if domain.is_ascii() {
+        domain.to_ascii_lowercase()
+    } else {
+        idna::domain_to_ascii(domain).map_err(|_| format!("Invalid internationalized domain: {}", input.trim()))?
+    };
+    if domain.is_empty() {
+        return Err("Domain is empty".to_string());
+    }
+    if hosts::is_valid_ip(&domain) {
+        return Err(format!("IP addresses are not domains: {}", input.trim()));
+    }
+    if domain.contains("://") || domain.contains('/') {
+        return Err(format!("URLs are not domains: {}", input.trim()));
+    }
+    if domain.len() > 253 {
+        return Err(format!("Domain is longer than 253 characters: {}", input.trim()));
+    }
+    if let Some(label) = domain.split('.').find(|label| !is_valid_label(label)) {
+        return Err(format!("Malformed label '{}' in {}", label, input.trim()));
+    }
+    Ok(domain)
+}
+
+// Validate and normalize a block-list domain
+pub fn normalize_block_domain(input: &str) -> Result<String, String> {
+    let trimmed = input.trim();
+    let host = match trimmed.split_once("://") {
+        Some((_, rest)) => {
+            let host = rest.strip_suffix('/').unwrap_or(rest);
+            if host.contains('/') || host.contains('?') || host.contains('#') {
+                return Err(format!("URLs with a path cannot be blocked, only whole domains: {}", trimmed));
+            }
+            host
+        }
+        None => trimmed,
+    };
+    normalize_domain(host)
+}
+
+// Validate and normalize an allow-list pattern
+pub fn normalize_allow_pattern(input: &str) -> Result<(String, PatternKind), String> {
+    let pattern = input.trim();
+    if pattern.len() >= 2 && pattern.starts_with('/') && pattern.ends_with('/') {
+        let body = &pattern[1..pattern.len() - 1];
+        if body.is_empty() {
+            return Err("Regex pattern is empty".to_string());
+        }
+        Regex::new(body).map_err(|e| format!("Invalid regex {}: {}", pattern, e))?;
+        return Ok((pattern.to_string(), PatternKind::Regex));
+    }
+    if let Some(suffix) = pattern.strip_prefix("*.") {
+        let suffix = normalize_domain(suffix)?;
+        return Ok((format!("*.{}", suffix), PatternKind::WildcardSuffix));
+    }
+    if pattern.contains('*') {
+        return Err(format!("Wildcards are only supported as a leading '*.': {}", pattern));
+    }
+    Ok((normalize_domain(pattern)?, PatternKind::Exact))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_case_whitespace_trailing_dots_and_idn() {
+        for (input, expected) in [
+            ("Example.COM", "example.com"),
+            ("  ads.example.com.  ", "ads.example.com"),
+            ("xn--bcher-kva.example", "xn--bcher-kva.example"),
+            ("bücher.example", "xn--bcher-kva.example"),
+            ("BÜCHER.Example.", "xn--bcher-kva.example"),
+            ("localhost", "localhost"),
+        ] {
+            assert_eq!(normalize_domain(input).as_deref(), Ok(expected), "{}", input);
+        }
+    }
+
+    #[test]
+    fn enforces_label_and_length_limits() {
+        let label = "a".repeat(63);
+        assert!(normalize_domain(&format!("{}.example", label)).is_ok());
+        assert!(normalize_domain(&format!("a{}.example", label)).is_err());
+
+        // 4 * 63 + 3 dots = 255
+        let long = [label.as_str(); 4].join(".");
+        assert!(normalize_domain(&long[..253]).is_ok());
+        assert!(normalize_domain(&long[..254]).is_err());
+
+        for input in ["", " . ", "a..b", "-ads.example", "ads-.example", "ads_1.example", "ads example.com", "*.example.com"] {
+            assert!(normalize_domain(input).is_err(), "{:?} was accepted", input);
+        }
+    }
+
+    #[test]
+    fn rejects_ips_urls_and_paths() {
+        for input in ["127.0.0.1", "::1", "2001:db8::1", "https://example.com", "example.com/ads", "/ads"] {
+            assert!(normalize_domain(input).is_err(), "{:?} was accepted", input);
+        }
+        assert!(normalize_domain("10.0.0.1").unwrap_err().starts_with("IP addresses"));
+    }
+
+    #[test]
+    fn block_domains_accept_bare_urls_only() {
+        assert_eq!(normalize_block_domain("https://Ads.Example.com/").as_deref(), Ok("ads.example.com"));
+        assert_eq!(normalize_block_domain("http://ads.example.com").as_deref(), Ok("ads.example.com"));
+        assert_eq!(normalize_block_domain(" tracker.example. ").as_deref(), Ok("tracker.example"));
+        for input in [
+            "https://example.com/ads",
+            "https://example.com?x=1",
+            "https://example.com#top",
+            "https://10.0.0.1/",
+            "https://",
+            "example.com/ads",
+        ] {
+            assert!(normalize_block_domain(input).is_err(), "{:?} was accepted", input);
+        }
+    }
+
+    #[test]
+    fn allow_patterns_accept_exact_domains_wildcard_suffixes_and_regexes() {
+        for (input, pattern, kind) in [
+            (" Example.com ", "example.com", PatternKind::Exact),
+            ("*.CDN.Example.com.", "*.cdn.example.com", PatternKind::WildcardSuffix),
+            ("*.bücher.example", "*.xn--bcher-kva.example", PatternKind::WildcardSuffix),
+            (r"/^ads[0-9]*\.example\.com$/", r"/^ads[0-9]*\.example\.com$/", PatternKind::Regex),
+            ("/Tracker/", "/Tracker/", PatternKind::Regex),
+        ] {
+            assert_eq!(normalize_allow_pattern(input), Ok((pattern.to_string(), kind)), "{}", input);
+        }
+    }
+
+    #[test]
+    fn allow_patterns_reject_other_wildcards_and_invalid_regexes() {
+        for input in ["a*b.example.com", "*example.com", "ads.*.example.com", "*", "*.", "*.*.example.com", "example.*"] {
+            assert!(normalize_allow_pattern(input).is_err(), "{:?} was accepted", input);
+        }
+        assert!(normalize_allow_pattern("a*b.example.com").unwrap_err().starts_with("Wildcards"));
+
+        for input in ["//", "/ads[/", "/(unclosed/", "/a{2,1}/"] {
+            assert!(normalize_allow_pattern(input).is_err(), "{:?} was accepted", input);
+        }
+        assert!(normalize_allow_pattern("/ads[/").unwrap_err().starts_with("Invalid regex"));
+        // A lone slash is not a regex
+        assert!(normalize_allow_pattern("/").is_err());
+    }
+}
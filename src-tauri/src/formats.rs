@@ -0,0 +1,537 @@
+// Block-list formats for HostDNI
+// - Normalizes the list formats found in the wild into a domain set:
+//   hosts (`0.0.0.0 ads.example.com`), one domain per line, AdBlock Plus (`||ads.example.com^`),
+//   dnsmasq (`address=/ads.example.com/0.0.0.0`), Unbound (`local-zone: "ads.example.com" always_nxdomain`)
+//   and RPZ zone files (`ads.example.com CNAME .`)
+// - The format is given by the caller or detected from the first lines of the content
+// - Comments and blank lines are ignored; every other line that yields no domain is reported
+//   with its line number and the reason
+// - Used by source refreshes (sources.rs) and the list import endpoints (api.rs)
+
+use crate::domains;
+use crate::hosts;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ListFormat {
+    #[default]
+    Auto,
+    Hosts,
+    Domains,
+    Adblock,
+    Dnsmasq,
+    Unbound,
+    Rpz,
+}
+
+impl ListFormat {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ListFormat::Auto => "auto",
+            ListFormat::Hosts => "hosts",
+            ListFormat::Domains => "domains",
+            ListFormat::Adblock => "adblock",
+            ListFormat::Dnsmasq => "dnsmasq",
+            ListFormat::Unbound => "unbound",
+            ListFormat::Rpz => "rpz",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<ListFormat> {
+        match value {
+            "auto" => Some(ListFormat::Auto),
+            "hosts" => Some(ListFormat::Hosts),
+            "domains" => Some(ListFormat::Domains),
+            "adblock" => Some(ListFormat::Adblock),
+            "dnsmasq" => Some(ListFormat::Dnsmasq),
+            "unbound" => Some(ListFormat::Unbound),
+            "rpz" => Some(ListFormat::Rpz),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SkippedLine {
+    // 1-based
+    pub line: usize,
+    pub content: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct ParsedList {
+    // The format actually used (never Auto)
+    pub format: ListFormat,
+    pub domains: BTreeSet<String>,
+    pub skipped: Vec<SkippedLine>,
+}
+
+// What a parse did, for API responses; `skipped` holds at most the first few lines
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ParseSummary {
+    pub format: ListFormat,
+    pub domain_count: usize,
+    pub skipped_count: usize,
+    pub skipped: Vec<SkippedLine>,
+}
+
+impl ParsedList {
+    pub fn summary(&self, max_skipped: usize) -> ParseSummary {
+        ParseSummary {
+            format: self.format,
+            domain_count: self.domains.len(),
+            skipped_count: self.skipped.len(),
+            skipped: self.skipped.iter().take(max_skipped).cloned().collect(),
+        }
+    }
+}
+
+// Names that hosts-format lists map for their own sake, not to block them
+const LOCAL_NAMES: &[&str] = &[
+    "localhost",
+    "localhost.localdomain",
+    "local",
+    "broadcasthost",
+    "ip6-localhost",
+    "ip6-loopback",
+    "ip6-localnet",
+    "ip6-mcastprefix",
+    "ip6-allnodes",
+    "ip6-allrouters",
+    "ip6-allhosts",
+];
+
+// Lines looked at when detecting the format
+const DETECT_LINES: usize = 200;
+
+// What one line yields: domains, a reason it was skipped, or nothing (comments, blanks, headers)
+enum LineResult {
+    Domains(Vec<String>),
+    Skipped(String),
+    Ignored,
+}
+
+// Guess the format of `content` from its first meaningful lines
+pub fn detect(content: &str) -> ListFormat {
+    let mut scores = [0usize; 6];
+    let formats = [
+        ListFormat::Adblock,
+        ListFormat::Dnsmasq,
+        ListFormat::Unbound,
+        ListFormat::Rpz,
+        ListFormat::Hosts,
+        ListFormat::Domains,
+    ];
+
+    let lines = content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#') && !line.starts_with(';'))
+        .take(DETECT_LINES);
+    for line in lines {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let index = if line.starts_with("||") || line.starts_with("@@") || line.starts_with('!') || line.starts_with("[Adblock") {
+            0
+        } else if line.starts_with("address=/") || line.starts_with("server=/") || line.starts_with("local=/") {
+            1
+        } else if line.starts_with("local-zone:") || line.starts_with("local-data:") || line == "server:" {
+            2
+        } else if line.starts_with("$TTL") || line.starts_with("$ORIGIN") || tokens.iter().any(|token| {
+            token.eq_ignore_ascii_case("CNAME") || token.eq_ignore_ascii_case("SOA")
+        }) {
+            3
+        } else if tokens.len() > 1 && hosts::is_valid_ip(tokens[0]) {
+            4
+        } else if tokens.len() == 1 {
+            5
+        } else {
+            continue;
+        };
+        scores[index] += 1;
+    }
+
+    // Ties go to the more specific format (earlier in the list)
+    let mut best = 5;
+    for index in (0..formats.len()).rev() {
+        if scores[index] >= scores[best] {
+            best = index;
+        }
+    }
+    formats[best]
+}
+
+// Parse `content` in `format` (detected when Auto) into a domain set
+pub fn parse(content: &str, format: ListFormat) -> ParsedList {
+    let format = match format {
+        ListFormat::Auto => detect(content),
+        format => format,
+    };
+
+    let mut domains = BTreeSet::new();
+    let mut skipped = Vec::new();
+    let mut rpz = RpzState::default();
+    for (index, raw) in content.lines().enumerate() {
+        let result = match format {
+            ListFormat::Hosts => parse_hosts_line(raw),
+            ListFormat::Domains | ListFormat::Auto => parse_domains_line(raw),
+            ListFormat::Adblock => parse_adblock_line(raw),
+            ListFormat::Dnsmasq => parse_dnsmasq_line(raw),
+            ListFormat::Unbound => parse_unbound_line(raw),
+            ListFormat::Rpz => rpz.parse_line(raw),
+        };
+        let reason = match result {
+            LineResult::Domains(names) => {
+                let mut reasons = Vec::new();
+                for name in names {
+                    match domains::normalize_block_domain(&name) {
+                        Ok(domain) if LOCAL_NAMES.contains(&domain.as_str()) => {
+                            reasons.push(format!("Local name {}", domain))
+                        }
+                        Ok(domain) => {
+                            domains.insert(domain);
+                        }
+                        Err(e) => reasons.push(e),
+                    }
+                }
+                if reasons.is_empty() {
+                    continue;
+                }
+                reasons.join("; ")
+            }
+            LineResult::Skipped(reason) => reason,
+            LineResult::Ignored => continue,
+        };
+        skipped.push(SkippedLine { line: index + 1, content: raw.trim().to_string(), reason });
+    }
+
+    ParsedList { format, domains, skipped }
+}
+
+// The line without its comment, trimmed
+fn strip_comment(line: &str, marker: char) -> &str {
+    line.split(marker).next().unwrap_or("").trim()
+}
+
+fn parse_hosts_line(raw: &str) -> LineResult {
+    let line = strip_comment(raw, '#');
+    let mut tokens = line.split_whitespace();
+    let ip = match tokens.next() {
+        Some(ip) => ip,
+        None => return LineResult::Ignored,
+    };
+    if !hosts::is_valid_ip(ip) {
+        return LineResult::Skipped(format!("Not a hosts line: {} is not an IP address", ip));
+    }
+    let names: Vec<String> = tokens.map(str::to_string).collect();
+    if names.is_empty() {
+        return LineResult::Skipped("No hostname after the IP address".to_string());
+    }
+    LineResult::Domains(names)
+}
+
+fn parse_domains_line(raw: &str) -> LineResult {
+    let line = strip_comment(raw, '#');
+    let tokens: Vec<&str> = line.split_whitespace().collect();
+    match tokens.as_slice() {
+        [] => LineResult::Ignored,
+        [domain] => LineResult::Domains(vec![domain.to_string()]),
+        _ => LineResult::Skipped("Expected one domain per line".to_string()),
+    }
+}
+
+fn parse_adblock_line(raw: &str) -> LineResult {
+    let line = raw.trim();
+    if line.is_empty() || line.starts_with('!') || line.starts_with('#') || line.starts_with('[') {
+        return LineResult::Ignored;
+    }
+    if line.starts_with("@@") {
+        return LineResult::Skipped("Exception rules are not supported".to_string());
+    }
+    if line.contains("##") || line.contains("#@#") || line.contains("#?#") || line.contains("#$#") {
+        return LineResult::Skipped("Cosmetic rules cannot be expressed in a hosts file".to_string());
+    }
+    let rule = match line.strip_prefix("||") {
+        Some(rule) => rule,
+        None => return LineResult::Skipped("Only ||domain^ rules are supported".to_string()),
+    };
+    let (rule, options) = match rule.split_once('$') {
+        Some((rule, options)) => (rule, Some(options)),
+        None => (rule, None),
+    };
+    if options.is_some_and(|options| !options.is_empty() && options != "important") {
+        return LineResult::Skipped("Rules with options cannot be expressed in a hosts file".to_string());
+    }
+    let domain = match rule.strip_suffix('^').or_else(|| rule.strip_suffix("^|")) {
+        Some(domain) => domain,
+        None if !rule.contains(['/', '*', '^']) => rule,
+        None => return LineResult::Skipped("Only whole-domain rules are supported".to_string()),
+    };
+    if domain.contains(['/', '*', '^']) {
+        return LineResult::Skipped("Only whole-domain rules are supported".to_string());
+    }
+    LineResult::Domains(vec![domain.to_string()])
+}
+
+fn parse_dnsmasq_line(raw: &str) -> LineResult {
+    let line = strip_comment(raw, '#');
+    if line.is_empty() {
+        return LineResult::Ignored;
+    }
+    let (directive, value) = match line.split_once('=') {
+        Some(pair) => pair,
+        None => return LineResult::Skipped("Not a dnsmasq directive".to_string()),
+    };
+    // address=/a.com/b.com/0.0.0.0, local=/a.com/ and server=/a.com/ (answered locally)
+    let target = match directive.trim() {
+        "address" | "local" | "server" => value.trim(),
+        other => return LineResult::Skipped(format!("Unsupported dnsmasq directive {}", other)),
+    };
+    let parts: Vec<&str> = match target.strip_prefix('/') {
+        Some(rest) => rest.split('/').collect(),
+        None => return LineResult::Skipped("Expected /domain/ after the directive".to_string()),
+    };
+    let (answer, names) = match parts.split_last() {
+        Some((answer, names)) if !names.is_empty() => (*answer, names),
+        _ => return LineResult::Skipped("No domain between the slashes".to_string()),
+    };
+    if directive.trim() == "server" && !answer.is_empty() {
+        return LineResult::Skipped("Forwarding rule, not a block".to_string());
+    }
+    if directive.trim() == "address" && !answer.is_empty() && !answer.starts_with('#') && !hosts::is_valid_ip(answer) {
+        return LineResult::Skipped(format!("Invalid address {}", answer));
+    }
+    LineResult::Domains(names.iter().filter(|name| !name.is_empty()).map(|name| name.to_string()).collect())
+}
+
+// local-zone types that stop a name from resolving
+const UNBOUND_BLOCKING_ZONES: &[&str] = &[
+    "static",
+    "refuse",
+    "deny",
+    "redirect",
+    "inform_deny",
+    "always_refuse",
+    "always_nxdomain",
+    "always_null",
+];
+
+fn parse_unbound_line(raw: &str) -> LineResult {
+    let line = strip_comment(raw, '#');
+    if line.is_empty() || line == "server:" {
+        return LineResult::Ignored;
+    }
+    if let Some(rest) = line.strip_prefix("local-zone:") {
+        let (name, zone_type) = match unquote_first(rest) {
+            Some(pair) => pair,
+            None => return LineResult::Skipped("Expected a quoted zone name".to_string()),
+        };
+        let zone_type = zone_type.trim();
+        if !UNBOUND_BLOCKING_ZONES.contains(&zone_type) {
+            return LineResult::Skipped(format!("Zone type {} does not block", zone_type));
+        }
+        return LineResult::Domains(vec![name.to_string()]);
+    }
+    if let Some(rest) = line.strip_prefix("local-data:") {
+        // local-data: "ads.example.com A 0.0.0.0"
+        let record = match unquote_first(rest) {
+            Some((record, _)) => record,
+            None => return LineResult::Skipped("Expected a quoted record".to_string()),
+        };
+        let tokens: Vec<&str> = record.split_whitespace().collect();
+        let record_type = tokens.iter().skip(1).find(|token| !token.eq_ignore_ascii_case("IN") && token.parse::<u32>().is_err());
+        return match (tokens.first(), record_type) {
+            (Some(name), Some(record_type)) if record_type.eq_ignore_ascii_case("A") || record_type.eq_ignore_ascii_case("AAAA") => {
+                LineResult::Domains(vec![name.to_string()])
+            }
+            _ => LineResult::Skipped("Only A and AAAA records are supported".to_string()),
+        };
+    }
+    LineResult::Skipped("Not a local-zone or local-data line".to_string())
+}
+
+// `"quoted" rest` -> (quoted, rest)
+fn unquote_first(value: &str) -> Option<(&str, &str)> {
+    let rest = value.trim().strip_prefix('"')?;
+    rest.split_once('"')
+}
+
+// RPZ parsing keeps track of $ORIGIN and multi-line records across lines
+#[derive(Default)]
+struct RpzState {
+    origin: Option<String>,
+    // Owner name of the last record; records that start with whitespace continue it
+    owner: Option<String>,
+    // Open parentheses of a multi-line record (usually the SOA)
+    depth: usize,
+}
+
+impl RpzState {
+    fn parse_line(&mut self, raw: &str) -> LineResult {
+        let line = strip_comment(raw, ';');
+        if self.depth > 0 {
+            self.track_parentheses(line);
+            return LineResult::Ignored;
+        }
+        if line.is_empty() {
+            return LineResult::Ignored;
+        }
+        if let Some(origin) = line.strip_prefix("$ORIGIN") {
+            self.origin = Some(origin.trim().trim_end_matches('.').to_ascii_lowercase());
+            return LineResult::Ignored;
+        }
+        if line.starts_with('$') {
+            return LineResult::Ignored;
+        }
+        self.track_parentheses(line);
+
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let (owner, fields) = if raw.starts_with(char::is_whitespace) {
+            (self.owner.clone(), &tokens[..])
+        } else {
+            self.owner = Some(tokens[0].to_string());
+            (self.owner.clone(), &tokens[1..])
+        };
+        // Skip the optional TTL and class to reach the record type
+        let mut rest = fields.iter().skip_while(|token| {
+            token.parse::<u32>().is_ok() || token.eq_ignore_ascii_case("IN")
+        });
+        let record_type = rest.next().map(|token| token.to_ascii_uppercase());
+        let data = rest.next().copied().unwrap_or("");
+        match record_type.as_deref() {
+            Some("SOA") | Some("NS") => return LineResult::Ignored,
+            Some("CNAME") if data == "." || data == "*." => {}
+            Some("CNAME") if data.starts_with("rpz-passthru") => {
+                return LineResult::Skipped("Passthru rule, not a block".to_string())
+            }
+            Some("CNAME") => return LineResult::Skipped(format!("Rewrite to {} is not a block", data)),
+            Some("A") | Some("AAAA") => {}
+            Some(other) => return LineResult::Skipped(format!("Unsupported record type {}", other)),
+            None => return LineResult::Skipped("Missing record type".to_string()),
+        }
+        let Some(owner) = owner else {
+            return LineResult::Skipped("Record without an owner name".to_string());
+        };
+        if owner == "@" {
+            return LineResult::Ignored;
+        }
+        if owner.starts_with("*.") {
+            return LineResult::Skipped("Wildcard triggers cannot be expressed in a hosts file".to_string());
+        }
+        LineResult::Domains(vec![self.relative_name(&owner)])
+    }
+
+    fn track_parentheses(&mut self, line: &str) {
+        let opened = line.matches('(').count();
+        let closed = line.matches(')').count();
+        self.depth = (self.depth + opened).saturating_sub(closed);
+    }
+
+    // Owner names are relative to the zone; absolute ones have the origin removed
+    fn relative_name(&self, owner: &str) -> String {
+        let Some(absolute) = owner.strip_suffix('.') else {
+            return owner.to_string();
+        };
+        let lowered = absolute.to_ascii_lowercase();
+        match &self.origin {
+            Some(origin) => lowered
+                .strip_suffix(origin.as_str())
+                .and_then(|name| name.strip_suffix('.'))
+                .map(str::to_string)
+                .unwrap_or(lowered),
+            None => lowered,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn domains(list: &ParsedList) -> Vec<&str> {
+        list.domains.iter().map(String::as_str).collect()
+    }
+
+    #[test]
+    fn parses_hosts_and_plain_lists() {
+        let list = parse(
+            "# header\n127.0.0.1 localhost\n0.0.0.0 Ads.Example.com tracker.example.net # inline\n0.0.0.0 plain.example.org\n\nnot a_domain\n",
+            ListFormat::Auto,
+        );
+        assert_eq!(list.format, ListFormat::Hosts);
+        assert_eq!(domains(&list), ["ads.example.com", "plain.example.org", "tracker.example.net"]);
+        let lines: Vec<usize> = list.skipped.iter().map(|skipped| skipped.line).collect();
+        assert_eq!(lines, [2, 6]);
+
+        let list = parse("ads.example.com\nhttps://tracker.example.net/\nnot a_domain\n", ListFormat::Auto);
+        assert_eq!(list.format, ListFormat::Domains);
+        assert_eq!(domains(&list), ["ads.example.com", "tracker.example.net"]);
+        assert_eq!(list.skipped[0].reason, "Expected one domain per line");
+    }
+
+    #[test]
+    fn parses_adblock_rules() {
+        let list = parse(
+            "[Adblock Plus 2.0]\n! comment\n||ads.example.com^\n||tracker.example.net^$important\n||cdn.example.org^$third-party\n@@||ok.example.com^\nexample.com##.banner\n||example.com/path^\n",
+            ListFormat::Auto,
+        );
+        assert_eq!(list.format, ListFormat::Adblock);
+        assert_eq!(domains(&list), ["ads.example.com", "tracker.example.net"]);
+        let lines: Vec<usize> = list.skipped.iter().map(|skipped| skipped.line).collect();
+        assert_eq!(lines, [5, 6, 7, 8]);
+    }
+
+    #[test]
+    fn parses_dnsmasq_and_unbound() {
+        let list = parse(
+            "address=/ads.example.com/0.0.0.0\naddress=/a.example.net/b.example.net/\nserver=/lan/192.168.1.1\n",
+            ListFormat::Auto,
+        );
+        assert_eq!(list.format, ListFormat::Dnsmasq);
+        assert_eq!(domains(&list), ["a.example.net", "ads.example.com", "b.example.net"]);
+        assert_eq!(list.skipped.len(), 1);
+
+        let list = parse(
+            "server:\nlocal-zone: \"ads.example.com\" always_nxdomain\nlocal-zone: \"lan\" transparent\nlocal-data: \"tracker.example.net A 0.0.0.0\"\n",
+            ListFormat::Auto,
+        );
+        assert_eq!(list.format, ListFormat::Unbound);
+        assert_eq!(domains(&list), ["ads.example.com", "tracker.example.net"]);
+        assert_eq!(list.skipped[0].reason, "Zone type transparent does not block");
+    }
+
+    #[test]
+    fn parses_rpz_zones() {
+        let list = parse(
+            "$TTL 300\n$ORIGIN rpz.example.\n@ IN SOA localhost. root.localhost. (\n  1 3600 600 86400 300 )\n  IN NS localhost.\n\tNS ns2.localhost.\nads.example.com CNAME .\n  300 IN AAAA ::\n*.ads.example.com CNAME .\ntracker.example.net.rpz.example. 300 IN CNAME *.\nlocal.example.net A 127.0.0.1\n  TXT \"note\"\nok.example.org CNAME rpz-passthru.\n",
+            ListFormat::Auto,
+        );
+        assert_eq!(list.format, ListFormat::Rpz);
+        assert_eq!(domains(&list), ["ads.example.com", "local.example.net", "tracker.example.net"]);
+        // Owner-less records continue the previous owner: the NS records belong to the apex
+        let reasons: Vec<&str> = list.skipped.iter().map(|skipped| skipped.reason.as_str()).collect();
+        assert_eq!(
+            reasons,
+            [
+                "Wildcard triggers cannot be expressed in a hosts file",
+                "Unsupported record type TXT",
+                "Passthru rule, not a block"
+            ]
+        );
+
+        let list = parse("  IN A 127.0.0.1\n  IN NS localhost.\n", ListFormat::Rpz);
+        assert!(list.domains.is_empty());
+        assert_eq!(list.skipped.len(), 1);
+        assert_eq!(list.skipped[0].reason, "Record without an owner name");
+    }
+
+    #[test]
+    fn explicit_format_overrides_detection() {
+        let list = parse("0.0.0.0 ads.example.com\n", ListFormat::Domains);
+        assert_eq!(list.format, ListFormat::Domains);
+        assert!(list.domains.is_empty());
+        assert_eq!(list.skipped.len(), 1);
+    }
+}
@@ -0,0 +1,189 @@
+// Write preflight for HostDNI
+// - Every hosts file write (REST API and Tauri commands) runs `preflight` first
+// - Protected entries (config `protected.entries`, `ip hostname [aliases]` lines) must be
+//   present and enabled; by default localhost, broadcasthost and ::1
+// - A missing entry is re-injected at the end of the file, or the write is refused, depending
+//   on `protected.on_missing`
+// - When HostDNI writes only its managed section, entries outside it count as present and
+//   missing ones are re-injected into the section
+// - The report names the guard that fired and the entries involved
+
+use crate::config::{ProtectedConfig, ProtectedMode};
+use crate::hosts::{self, Entry, HostsFile};
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+
+pub const PROTECTED_ENTRIES_GUARD: &str = "protected_entries";
+const REINJECTED_COMMENT: &str = "protected entry restored by HostDNI";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GuardAction {
+    Reinjected,
+    Refused,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GuardReport {
+    pub guard: String,
+    pub action: GuardAction,
+    // The protected entries that were missing, as `ip hostname` lines
+    pub entries: Vec<String>,
+}
+
+impl GuardReport {
+    pub fn message(&self) -> String {
+        let entries = self.entries.join(", ");
+        match self.action {
+            GuardAction::Reinjected => format!("Guard {} re-injected missing entries: {}", self.guard, entries),
+            GuardAction::Refused => format!("Guard {} refused the write, missing entries: {}", self.guard, entries),
+        }
+    }
+}
+
+// Parse the configured protected entries
+pub fn protected_entries(config: &ProtectedConfig) -> Result<Vec<Entry>, String> {
+    config
+        .entries
+        .iter()
+        .map(|line| {
+            let mut parts = line.split_whitespace();
+            let (Some(ip), Some(hostname)) = (parts.next(), parts.next()) else {
+                return Err(format!("Protected entry must be `ip hostname`: {}", line));
+            };
+            let aliases: Vec<String> = parts.map(str::to_string).collect();
+            if !hosts::is_valid_ip(ip) {
+                return Err(format!("Invalid IP address in protected entry: {}", line));
+            }
+            if let Some(name) = std::iter::once(hostname).chain(aliases.iter().map(String::as_str)).find(|name| !hosts::is_valid_hostname(name)) {
+                return Err(format!("Invalid hostname '{}' in protected entry: {}", name, line));
+            }
+            Ok(Entry {
+                ip: ip.to_string(),
+                hostname: hostname.to_string(),
+                aliases,
+                comment: None,
+                enabled: true,
+            })
+        })
+        .collect()
+}
+
+fn same_ip(a: &str, b: &str) -> bool {
+    match (a.parse::<IpAddr>(), b.parse::<IpAddr>()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a.eq_ignore_ascii_case(b),
+    }
+}
+
+// Whether an enabled entry in `files` maps every name of `protected` to its address
+fn is_present(files: &[&HostsFile], protected: &Entry) -> bool {
+    std::iter::once(&protected.hostname).chain(&protected.aliases).all(|name| {
+        files.iter().flat_map(|file| file.entries()).any(|entry| {
+            entry.enabled
+                && same_ip(&entry.ip, &protected.ip)
+                && std::iter::once(&entry.hostname).chain(&entry.aliases).any(|other| other.eq_ignore_ascii_case(name))
+        })
+    })
+}
+
+// Protected entries that `file` lacks
+pub fn missing_entries(file: &HostsFile, protected: &[Entry]) -> Vec<Entry> {
+    protected.iter().filter(|entry| !is_present(&[file], entry)).cloned().collect()
+}
+
+// Check `file` before it is written. Ok(None) when nothing was missing, Ok(Some) when missing
+// entries were re-injected into `file`, Err when the write must not happen.
+pub fn preflight(file: &mut HostsFile, config: &ProtectedConfig) -> Result<Option<GuardReport>, GuardReport> {
+    preflight_section(file, &HostsFile::default(), config)
+}
+
+// `preflight` for a managed section written into a file whose other lines are `outside`
+pub fn preflight_section(
+    section: &mut HostsFile,
+    outside: &HostsFile,
+    config: &ProtectedConfig,
+) -> Result<Option<GuardReport>, GuardReport> {
+    // Config validation rejects malformed entries, but never write unguarded if one slips through
+    let protected = protected_entries(config).map_err(|e| GuardReport {
+        guard: PROTECTED_ENTRIES_GUARD.to_string(),
+        action: GuardAction::Refused,
+        entries: vec![e],
+    })?;
+    let missing: Vec<Entry> = protected
+        .into_iter()
+        .filter(|entry| !is_present(&[&*section, outside], entry))
+        .collect();
+    if missing.is_empty() {
+        return Ok(None);
+    }
+
+    let mut report = GuardReport {
+        guard: PROTECTED_ENTRIES_GUARD.to_string(),
+        action: GuardAction::Refused,
+        entries: missing.iter().map(|entry| format!("{} {}", entry.ip, entry.hostname)).collect(),
+    };
+    if config.on_missing == ProtectedMode::Refuse {
+        return Err(report);
+    }
+
+    // Appended, so the line numbers and IDs of existing entries do not move
+    for mut entry in missing {
+        entry.comment = Some(REINJECTED_COMMENT.to_string());
+        section.push_entry(entry);
+    }
+    report.action = GuardAction::Reinjected;
+    Ok(Some(report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(mode: ProtectedMode) -> ProtectedConfig {
+        ProtectedConfig {
+            on_missing: mode,
+            ..ProtectedConfig::default()
+        }
+    }
+
+    #[test]
+    fn passes_when_every_protected_entry_is_present() {
+        let mut file = HostsFile::parse(
+            "127.0.0.1 localhost\n255.255.255.255 broadcasthost\n0:0:0:0:0:0:0:1 ip6-localhost localhost\n",
+        );
+        assert!(preflight(&mut file, &config(ProtectedMode::Refuse)).unwrap().is_none());
+    }
+
+    #[test]
+    fn reinjects_missing_and_disabled_entries() {
+        let content = "# 127.0.0.1 localhost\n255.255.255.255 broadcasthost\n10.0.0.5 dev.local";
+        let mut file = HostsFile::parse(content);
+        let report = preflight(&mut file, &config(ProtectedMode::Reinject)).unwrap().unwrap();
+        assert_eq!(report.action, GuardAction::Reinjected);
+        assert_eq!(report.entries, vec!["127.0.0.1 localhost", "::1 localhost"]);
+
+        let written = file.to_string();
+        assert!(written.starts_with(content));
+        assert!(written.contains("127.0.0.1\tlocalhost\t# protected entry restored by HostDNI"));
+        assert!(missing_entries(&file, &protected_entries(&ProtectedConfig::default()).unwrap()).is_empty());
+    }
+
+    #[test]
+    fn counts_entries_outside_the_managed_section() {
+        let outside = HostsFile::parse("127.0.0.1 localhost\n::1 localhost\n");
+        let mut section = HostsFile::parse("0.0.0.0 ads.example.com\n");
+        let report = preflight_section(&mut section, &outside, &config(ProtectedMode::Reinject)).unwrap().unwrap();
+        assert_eq!(report.entries, vec!["255.255.255.255 broadcasthost"]);
+        assert_eq!(section.entry_count(), 2);
+    }
+
+    #[test]
+    fn refuses_without_touching_the_file() {
+        let mut file = HostsFile::parse("10.0.0.5 dev.local\n");
+        let report = preflight(&mut file, &config(ProtectedMode::Refuse)).unwrap_err();
+        assert_eq!(report.guard, PROTECTED_ENTRIES_GUARD);
+        assert_eq!(report.entries.len(), 3);
+        assert_eq!(file.to_string(), "10.0.0.5 dev.local\n");
+    }
+}
@@ -0,0 +1,130 @@
+// Privileged helper daemon for HostDNI
+// - Optional root process, installed once, so routine writes stop prompting for a password
+// - Listens on a Unix socket owned by the installing user (mode 0600)
+// - Checks the peer's uid on every connection (installing user or root only)
+// - Accepts a narrow set of operations: write hosts, back up, restore, enable, disable
+// - One newline-delimited JSON request and response per connection
+// - Unix only, like the rest of the helper (lib.rs and privilege.rs gate it behind cfg(unix))
+// - Protocol and client side live here (see privilege::HelperExecutor);
+//   the server side is src/bin/hostdni-helper/daemon.rs
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+
+pub const DEFAULT_SOCKET_PATH: &str = "/var/run/hostdni-helper.sock";
+pub const INSTALL_PATH: &str = "/usr/local/libexec/hostdni-helper";
+
+static BACKUP_NAME_PATTERN: Lazy<Regex> = Lazy::new(|| Regex::new(r"^hosts_\d{8}_\d{6}\.backup$").unwrap());
+
+// Socket path, overridable with HOSTDNI_HELPER_SOCKET
+pub fn socket_path() -> PathBuf {
+    std::env::var_os("HOSTDNI_HELPER_SOCKET")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_SOCKET_PATH))
+}
+
+pub fn is_valid_backup_name(file_name: &str) -> bool {
+    BACKUP_NAME_PATTERN.is_match(file_name)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum HelperRequest {
+    // Replace /etc/hosts atomically (previous version kept in /etc/hosts.backup)
+    WriteHosts { content: String },
+    // Copy /etc/hosts into the backup directory as `file_name`
+    Backup { file_name: String },
+    // Replace /etc/hosts atomically with a file from the backup directory
+    Restore { file_name: String },
+    // Move /etc/hosts to /etc/hosts.disabled
+    Disable,
+    // Move /etc/hosts.disabled back to /etc/hosts
+    Enable,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HelperResponse {
+    pub success: bool,
+    pub error: Option<String>,
+}
+
+// Connect to the helper listening on `socket`; fails when none is running
+pub fn connect(socket: &Path) -> Result<UnixStream, String> {
+    UnixStream::connect(socket).map_err(|e| format!("Failed to connect to privileged helper: {}", e))
+}
+
+// Send one request over a new connection and wait for the helper's answer
+pub fn send_request(mut stream: UnixStream, request: &HelperRequest) -> Result<(), String> {
+    let mut payload = serde_json::to_vec(request)
+        .map_err(|e| format!("Failed to encode helper request: {}", e))?;
+    payload.push(b'\n');
+    stream.write_all(&payload)
+        .map_err(|e| format!("Failed to send helper request: {}", e))?;
+
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line)
+        .map_err(|e| format!("Failed to read helper response: {}", e))?;
+    let response: HelperResponse = serde_json::from_str(&line)
+        .map_err(|e| format!("Invalid helper response: {}", e))?;
+    if response.success {
+        Ok(())
+    } else {
+        Err(response.error.unwrap_or_else(|| "Privileged helper request failed".to_string()))
+    }
+}
+
+// Service definition that runs the installed helper at boot
+pub fn service_definition(allowed_uid: u32) -> (PathBuf, String) {
+    let args = [
+        INSTALL_PATH.to_string(),
+        "--socket".to_string(),
+        DEFAULT_SOCKET_PATH.to_string(),
+        "--allow-uid".to_string(),
+        allowed_uid.to_string(),
+    ];
+
+    if cfg!(target_os = "macos") {
+        let program_arguments: String = args
+            .iter()
+            .map(|arg| format!("        <string>{}</string>\n", arg))
+            .collect();
+        let plist = format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" \"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n\
+             <plist version=\"1.0\">\n\
+             <dict>\n\
+             \x20   <key>Label</key>\n\
+             \x20   <string>com.hostdni.helper</string>\n\
+             \x20   <key>ProgramArguments</key>\n\
+             \x20   <array>\n\
+             {}\
+             \x20   </array>\n\
+             \x20   <key>RunAtLoad</key>\n\
+             \x20   <true/>\n\
+             \x20   <key>KeepAlive</key>\n\
+             \x20   <true/>\n\
+             </dict>\n\
+             </plist>\n",
+            program_arguments
+        );
+        (PathBuf::from("/Library/LaunchDaemons/com.hostdni.helper.plist"), plist)
+    } else {
+        let unit = format!(
+            "[Unit]\n\
+             Description=HostDNI privileged helper\n\
+             \n\
+             [Service]\n\
+             ExecStart={}\n\
+             Restart=on-failure\n\
+             \n\
+             [Install]\n\
+             WantedBy=multi-user.target\n",
+            args.join(" ")
+        );
+        (PathBuf::from("/etc/systemd/system/hostdni-helper.service"), unit)
+    }
+}
@@ -0,0 +1,392 @@
+// Hosts file parser for HostDNI
+// - Single parser shared by every REST handler and Tauri command
+// - Produces a typed line list: blank lines, comments, entries, disabled entries
+// - Lossless: lines that are not modified serialize back to their original bytes
+// - Line endings and the presence of a trailing newline are preserved
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+static IPV4_PATTERN: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^(?:(?:25[0-5]|2[0-4][0-9]|[01]?[0-9][0-9]?)\.){3}(?:25[0-5]|2[0-4][0-9]|[01]?[0-9][0-9]?)$").unwrap()
+});
+
+static IPV6_PATTERN: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^(?:(?:[0-9a-fA-F]{1,4}:){7}[0-9a-fA-F]{1,4}|(?:[0-9a-fA-F]{1,4}:){1,7}:|(?:[0-9a-fA-F]{1,4}:){1,6}:[0-9a-fA-F]{1,4}|(?:[0-9a-fA-F]{1,4}:){1,5}(?::[0-9a-fA-F]{1,4}){1,2}|(?:[0-9a-fA-F]{1,4}:){1,4}(?::[0-9a-fA-F]{1,4}){1,3}|(?:[0-9a-fA-F]{1,4}:){1,3}(?::[0-9a-fA-F]{1,4}){1,4}|(?:[0-9a-fA-F]{1,4}:){1,2}(?::[0-9a-fA-F]{1,4}){1,5}|[0-9a-fA-F]{1,4}:(?:(?::[0-9a-fA-F]{1,4}){1,6})|:(?:(?::[0-9a-fA-F]{1,4}){1,7}|:)|fe80:(?::[0-9a-fA-F]{0,4}){0,4}%[0-9a-zA-Z]{1,}|::(?:ffff(?::0{1,4}){0,1}:){0,1}(?:(?:25[0-5]|(?:2[0-4]|1{0,1}[0-9]){0,1}[0-9])\.){3,3}(?:25[0-5]|(?:2[0-4]|1{0,1}[0-9]){0,1}[0-9])|(?:[0-9a-fA-F]{1,4}:){1,4}:(?:(?:25[0-5]|(?:2[0-4]|1{0,1}[0-9]){0,1}[0-9])\.){3,3}(?:25[0-5]|(?:2[0-4]|1{0,1}[0-9]){0,1}[0-9]))$").unwrap()
+});
+
+// Validate an IPv4 or IPv6 address as it may appear in a hosts file
+pub fn is_valid_ip(ip: &str) -> bool {
+    IPV4_PATTERN.is_match(ip) || IPV6_PATTERN.is_match(ip)
+}
+
+// Permissive hostname validation (letters, digits, '-', '.', '%')
+pub fn is_valid_hostname(hostname: &str) -> bool {
+    !hostname.is_empty()
+        && hostname.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '.' || c == '%')
+}
+
+// A single mapping line, enabled or commented out
+// e.g. `127.0.0.1  localhost localhost.localdomain  # loopback`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub ip: String,
+    // Canonical hostname (first name on the line)
+    pub hostname: String,
+    // Additional names mapped to the same address
+    pub aliases: Vec<String>,
+    // Inline `#` comment, without the leading '#'
+    pub comment: Option<String>,
+    pub enabled: bool,
+}
+
+impl Entry {
+    // Render the entry as a hosts file line (without line ending)
+    pub fn render(&self) -> String {
+        let mut line = String::new();
+        if !self.enabled {
+            line.push_str("# ");
+        }
+        line.push_str(&self.ip);
+        line.push('\t');
+        line.push_str(&self.hostname);
+        for alias in &self.aliases {
+            line.push(' ');
+            line.push_str(alias);
+        }
+        if let Some(ref comment) = self.comment {
+            line.push_str("\t# ");
+            line.push_str(comment);
+        }
+        line
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LineKind {
+    // Empty or whitespace-only line
+    Blank,
+    // Pure comment that does not describe an entry
+    Comment,
+    // Enabled or disabled (commented-out) entry
+    Entry(Entry),
+    // Non-comment line that is not a valid entry; kept verbatim
+    Invalid,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line {
+    pub kind: LineKind,
+    // Original text (without '\n'); None once the line has been modified or created
+    raw: Option<String>,
+}
+
+impl Line {
+    pub fn entry(entry: Entry) -> Line {
+        Line { kind: LineKind::Entry(entry), raw: None }
+    }
+
+    pub fn comment(text: &str) -> Line {
+        Line { kind: LineKind::Comment, raw: Some(format!("# {}", text)) }
+    }
+
+    pub fn blank() -> Line {
+        Line { kind: LineKind::Blank, raw: Some(String::new()) }
+    }
+
+    // Original text; None once the line has been modified or created as an entry
+    pub fn raw(&self) -> Option<&str> {
+        self.raw.as_deref()
+    }
+
+    pub fn as_entry(&self) -> Option<&Entry> {
+        match self.kind {
+            LineKind::Entry(ref entry) => Some(entry),
+            _ => None,
+        }
+    }
+
+    // Mutable access to the entry; the line will be re-rendered on serialization
+    pub fn as_entry_mut(&mut self) -> Option<&mut Entry> {
+        match self.kind {
+            LineKind::Entry(ref mut entry) => {
+                self.raw = None;
+                Some(entry)
+            }
+            _ => None,
+        }
+    }
+
+    fn render(&self, line_ending: &str) -> String {
+        match self.raw {
+            Some(ref raw) => raw.clone(),
+            None => match self.kind {
+                LineKind::Entry(ref entry) => {
+                    let mut line = entry.render();
+                    // Keep CRLF files consistent for newly written lines
+                    if line_ending == "\r\n" {
+                        line.push('\r');
+                    }
+                    line
+                }
+                _ => String::new(),
+            },
+        }
+    }
+}
+
+// Parsed hosts file, one item per physical line
+#[derive(Debug, Clone, PartialEq)]
+pub struct HostsFile {
+    pub lines: Vec<Line>,
+    line_ending: &'static str,
+    trailing_newline: bool,
+}
+
+impl Default for HostsFile {
+    fn default() -> Self {
+        HostsFile {
+            lines: Vec::new(),
+            line_ending: "\n",
+            trailing_newline: true,
+        }
+    }
+}
+
+impl HostsFile {
+    pub fn parse(content: &str) -> HostsFile {
+        let mut pieces: Vec<&str> = content.split('\n').collect();
+        let trailing_newline = content.ends_with('\n');
+        if trailing_newline || content.is_empty() {
+            pieces.pop();
+        }
+
+        let line_ending = if content.contains("\r\n") { "\r\n" } else { "\n" };
+
+        let lines = pieces
+            .into_iter()
+            .map(|raw| Line {
+                kind: classify_line(raw.strip_suffix('\r').unwrap_or(raw)),
+                raw: Some(raw.to_string()),
+            })
+            .collect();
+
+        HostsFile {
+            lines,
+            line_ending,
+            trailing_newline: trailing_newline || content.is_empty(),
+        }
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &Entry> {
+        self.lines.iter().filter_map(|line| line.as_entry())
+    }
+
+    pub fn entry_count(&self) -> usize {
+        self.entries().count()
+    }
+
+    // Entries paired with their stable IDs (see `entry_id`)
+    pub fn entries_with_ids(&self) -> Vec<(String, &Entry)> {
+        self.entry_ids_by_line()
+            .into_iter()
+            .filter_map(|(index, id)| self.lines[index].as_entry().map(|entry| (id, entry)))
+            .collect()
+    }
+
+    // Line index and stable ID of every entry, in file order
+    pub fn entry_ids_by_line(&self) -> Vec<(usize, String)> {
+        let mut occurrences: HashMap<(&str, &str), usize> = HashMap::new();
+        self.lines
+            .iter()
+            .enumerate()
+            .filter_map(|(index, line)| {
+                let entry = line.as_entry()?;
+                let occurrence = occurrences.entry((&entry.ip, &entry.hostname)).or_insert(0);
+                let id = entry_id(&entry.ip, &entry.hostname, *occurrence);
+                *occurrence += 1;
+                Some((index, id))
+            })
+            .collect()
+    }
+
+    pub fn push_entry(&mut self, entry: Entry) {
+        self.lines.push(Line::entry(entry));
+    }
+
+    pub fn push_line(&mut self, line: Line) {
+        self.lines.push(line);
+    }
+}
+
+impl fmt::Display for HostsFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let count = self.lines.len();
+        for (index, line) in self.lines.iter().enumerate() {
+            f.write_str(&line.render(self.line_ending))?;
+            if index + 1 < count || self.trailing_newline {
+                f.write_str("\n")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Stable, content-derived entry ID
+// Derived from the ip/hostname pair plus an occurrence counter for duplicates, so
+// it survives edits elsewhere in the file and toggling the entry on or off
+pub fn entry_id(ip: &str, hostname: &str, occurrence: usize) -> String {
+    // FNV-1a: stable across runs and Rust versions, unlike DefaultHasher
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in ip.bytes().chain(std::iter::once(0)).chain(hostname.bytes()) {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    format!("{:016x}-{}", hash, occurrence)
+}
+
+// Classify a single line (without line ending)
+fn classify_line(line: &str) -> LineKind {
+    let trimmed_line = line.trim();
+
+    if trimmed_line.is_empty() {
+        return LineKind::Blank;
+    }
+
+    // Check if line is commented out (disabled)
+    let is_enabled = !trimmed_line.starts_with('#');
+    let working_line = if is_enabled { trimmed_line } else { trimmed_line[1..].trim() };
+
+    // Pure comments (nothing after '#', or a '##' style header)
+    if working_line.is_empty() || working_line.starts_with('#') {
+        return LineKind::Comment;
+    }
+
+    // Split off the inline comment, if any
+    let (mapping, comment) = match working_line.find('#') {
+        Some(pos) => (&working_line[..pos], Some(working_line[pos + 1..].trim())),
+        None => (working_line, None),
+    };
+
+    let mut parts = mapping.split_whitespace();
+    let ip = parts.next().unwrap_or("");
+    let names: Vec<&str> = parts.collect();
+
+    if is_valid_ip(ip) && !names.is_empty() && names.iter().all(|name| is_valid_hostname(name)) {
+        return LineKind::Entry(Entry {
+            ip: ip.to_string(),
+            hostname: names[0].to_string(),
+            aliases: names[1..].iter().map(|name| name.to_string()).collect(),
+            comment: comment.filter(|c| !c.is_empty()).map(|c| c.to_string()),
+            enabled: is_enabled,
+        });
+    }
+
+    if is_enabled {
+        LineKind::Invalid
+    } else {
+        LineKind::Comment
+    }
+}
+
+// Read and parse a hosts file from disk
+pub fn read_hosts_file(path: impl AsRef<Path>) -> Result<HostsFile, String> {
+    let path = path.as_ref();
+    if !path.exists() {
+        return Err("Hosts file not found".to_string());
+    }
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read hosts file: {}", e))?;
+    Ok(HostsFile::parse(&content))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_round_trip(content: &str) {
+        assert_eq!(HostsFile::parse(content).to_string(), content);
+    }
+
+    #[test]
+    fn round_trips_byte_for_byte() {
+        assert_round_trip("");
+        assert_round_trip("\n");
+        assert_round_trip("127.0.0.1 localhost\n");
+        // No trailing newline
+        assert_round_trip("127.0.0.1 localhost\n::1 localhost");
+        // CRLF, including a CRLF file without a trailing newline
+        assert_round_trip("127.0.0.1 localhost\r\n\r\n# comment\r\n");
+        assert_round_trip("127.0.0.1 localhost\r\n10.0.0.1 nas.lan");
+        // Blank and whitespace-only lines, comments, disabled entries, invalid lines, inline comments
+        assert_round_trip(
+            "##\n# Host Database\n\n   \t\n#   10.0.0.2   printer.lan\n<<<not hosts>>>\n10.0.0.1  nas.lan nas   # storage  \n",
+        );
+    }
+
+    #[test]
+    fn classifies_lines() {
+        let file = HostsFile::parse("\n# comment\n# 10.0.0.2 printer.lan\n10.0.0.1 nas.lan nas # storage\nbogus line\n# bogus comment\n");
+        let kinds: Vec<&LineKind> = file.lines.iter().map(|line| &line.kind).collect();
+        assert_eq!(kinds[0], &LineKind::Blank);
+        assert_eq!(kinds[1], &LineKind::Comment);
+        assert_eq!(kinds[4], &LineKind::Invalid);
+        assert_eq!(kinds[5], &LineKind::Comment);
+
+        let disabled = file.lines[2].as_entry().unwrap();
+        assert_eq!((disabled.ip.as_str(), disabled.hostname.as_str(), disabled.enabled), ("10.0.0.2", "printer.lan", false));
+        let enabled = file.lines[3].as_entry().unwrap();
+        assert_eq!(enabled.aliases, vec!["nas"]);
+        assert_eq!(enabled.comment.as_deref(), Some("storage"));
+        assert!(enabled.enabled);
+    }
+
+    #[test]
+    fn modified_lines_are_re_rendered_with_the_file_line_ending() {
+        let mut file = HostsFile::parse("127.0.0.1 localhost\r\n10.0.0.1   nas.lan\r\n");
+        file.lines[1].as_entry_mut().unwrap().enabled = false;
+        file.push_entry(Entry {
+            ip: "10.0.0.3".to_string(),
+            hostname: "wiki.lan".to_string(),
+            aliases: Vec::new(),
+            comment: None,
+            enabled: true,
+        });
+        assert_eq!(file.to_string(), "127.0.0.1 localhost\r\n# 10.0.0.1\tnas.lan\r\n10.0.0.3\twiki.lan\r\n");
+    }
+
+    #[test]
+    fn renders_entries() {
+        let mut entry = Entry {
+            ip: "10.0.0.1".to_string(),
+            hostname: "nas.lan".to_string(),
+            aliases: vec!["nas".to_string(), "storage".to_string()],
+            comment: Some("basement".to_string()),
+            enabled: true,
+        };
+        assert_eq!(entry.render(), "10.0.0.1\tnas.lan nas storage\t# basement");
+        entry.enabled = false;
+        entry.comment = None;
+        assert_eq!(entry.render(), "# 10.0.0.1\tnas.lan nas storage");
+        // A rendered entry parses back to itself
+        assert_eq!(HostsFile::parse(&entry.render()).lines[0].as_entry(), Some(&entry));
+    }
+
+    #[test]
+    fn entry_ids_are_stable() {
+        let file = HostsFile::parse("127.0.0.1 localhost\n10.0.0.1 nas.lan\n10.0.0.1 nas.lan\n");
+        let ids: Vec<String> = file.entries_with_ids().into_iter().map(|(id, _)| id).collect();
+        assert_eq!(ids[1], entry_id("10.0.0.1", "nas.lan", 0));
+        assert_eq!(ids[2], entry_id("10.0.0.1", "nas.lan", 1));
+        assert_ne!(ids[1], ids[2]);
+
+        // Edits elsewhere in the file and toggling keep the ID
+        let edited = HostsFile::parse("# header\n::1 localhost\n# 10.0.0.1 nas.lan # off\n");
+        assert_eq!(edited.entries_with_ids()[1].0, ids[1]);
+        // Fixed value, so IDs survive restarts and Rust upgrades
+        assert_eq!(entry_id("127.0.0.1", "localhost", 0), "4517c80135b605eb-0");
+    }
+}
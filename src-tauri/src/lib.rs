@@ -0,0 +1,24 @@
+// HostDNI library
+// - Everything except the Tauri shell (main.rs), shared by the app and the privileged helper
+//   (src/bin/hostdni-helper)
+
+pub mod api;
+pub mod atomic;
+pub mod auth;
+pub mod backups;
+pub mod compiler;
+pub mod config;
+pub mod deploy;
+pub mod diff;
+pub mod domains;
+pub mod formats;
+pub mod guard;
+#[cfg(unix)]
+pub mod helper;
+pub mod hosts;
+pub mod managed;
+pub mod paths;
+pub mod privilege;
+pub mod retention;
+pub mod sources;
+pub mod store;
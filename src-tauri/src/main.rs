@@ -1,29 +1,19 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use std::fs::{self};
-use std::path::Path;
 use chrono::Local;
 use serde::{Serialize, Deserialize};
-use tokio;
 use std::sync::{Arc, Mutex};
 use std::time::{SystemTime, UNIX_EPOCH};
 use actix_web::{web, App, HttpServer, middleware};
 use actix_cors::Cors;
 use once_cell::sync::Lazy;
+use hosts::{HostsFile, Line};
 
 // Import our modules
-mod auth;
-mod api;
+use hostdni_lib::{api, auth, backups, config, deploy, guard, hosts, paths, privilege, store};
 
 // Data structures for Tauri commands
-#[derive(Debug, Serialize, Deserialize)]
-pub struct BackupFile {
-    pub filename: String,
-    pub backup_date: String,
-    pub file_path: String,
-}
-
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct NetworkLog {
     pub id: String,
@@ -56,171 +46,91 @@ fn get_monitoring_state() -> Arc<Mutex<bool>> {
 
 // Get list of backup files
 #[tauri::command]
-fn get_backup_files() -> Result<Vec<BackupFile>, String> {
-    // Use user's home directory for backups instead of /etc
-    let home_dir = std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string());
-    let backup_dir = format!("{}/.hostdni/backups", home_dir);
-    
-    // Create backup directory if it doesn't exist
-    if !Path::new(&backup_dir).exists() {
-        if let Err(e) = fs::create_dir_all(&backup_dir) {
-            return Err(format!("Failed to create backup directory: {}", e));
-        }
-    }
-    
-    let mut backup_files = Vec::new();
-    
-    if let Ok(entries) = fs::read_dir(&backup_dir) {
-            for entry in entries {
-                if let Ok(entry) = entry {
-                    let path = entry.path();
-                if path.is_file() && path.extension().and_then(|s| s.to_str()) == Some("backup") {
-                    if let Ok(metadata) = fs::metadata(&path) {
-                        if let Ok(_created) = metadata.created() {
-                            let _created_time = SystemTime::now()
-                                .duration_since(UNIX_EPOCH)
-                                .unwrap()
-                                .as_secs();
-                            
-                            let filename = path.file_name()
-                                .and_then(|n| n.to_str())
-                                .unwrap_or("unknown")
-                                .to_string();
-                            
-                            let backup_date = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
-                            
-                                    backup_files.push(BackupFile {
-                                filename,
-                                backup_date,
-                                        file_path: path.to_string_lossy().to_string(),
-                                    });
-                                }
-                            }
-                        }
-                    }
-                }
-            }
-            
-    // Sort by creation time (newest first)
-    backup_files.sort_by(|a, b| b.backup_date.cmp(&a.backup_date));
-            
-            Ok(backup_files)
+fn get_backup_files() -> Result<Vec<backups::BackupFile>, String> {
+    let backups = backups::repository().list()?;
+    Ok(backups.iter().map(backups::BackupFile::from).collect())
 }
 
-// Backup the current hosts file
+// Backup the current hosts file; `origin` defaults to manual
 #[tauri::command]
-async fn backup_current_hosts_file() -> Result<(), String> {
-    let hosts_path = "/etc/hosts";
-    let backup_dir = "/etc/hosts_backups";
-    
-    // Check if hosts file exists
-    if !Path::new(hosts_path).exists() {
-        return Err("No hosts file found to backup".to_string());
-    }
-    
-    // Create backup directory if it doesn't exist (requires elevated permissions)
-    if !Path::new(backup_dir).exists() {
-        let script = format!(
-            "do shell script \"mkdir -p {}\" with administrator privileges",
-            backup_dir
-        );
-        
-        let output = std::process::Command::new("osascript")
-            .args(&["-e", &script])
-            .output();
-        
-        match output {
-            Ok(result) => {
-                if !result.status.success() {
-                    let error_msg = String::from_utf8_lossy(&result.stderr);
-                    return Err(format!("Failed to create backup directory: {}", error_msg));
-                }
-            }
-            Err(e) => {
-                return Err(format!("Failed to execute directory creation command: {}", e));
-            }
-        }
-    }
-    
-    // Generate backup filename with timestamp
-    let timestamp = Local::now().format("%Y%m%d_%H%M%S").to_string();
-    let backup_filename = format!("hosts_{}.backup", timestamp);
-    let backup_path = format!("{}/{}", backup_dir, backup_filename);
-    
-    // Use osascript to show native macOS authentication dialog
-    let script = format!(
-        "do shell script \"cp {} {}\" with administrator privileges",
-        hosts_path, backup_path
-    );
+async fn backup_current_hosts_file(
+    origin: Option<backups::BackupOrigin>,
+    name: Option<String>,
+    description: Option<String>,
+) -> Result<backups::BackupFile, String> {
+    let label = backups::BackupLabel::new(origin.unwrap_or_default(), name, description);
+    let backup = backups::repository()
+        .create_from_hosts(label)
+        .map_err(|e| e.message().to_string())?;
     
-    let output = std::process::Command::new("osascript")
-        .args(&["-e", &script])
-        .output();
-    
-    match output {
-        Ok(result) => {
-            if result.status.success() {
-                println!("Hosts file backed up to: {}", backup_path);
-                Ok(())
-            } else {
-                let error_msg = String::from_utf8_lossy(&result.stderr);
-                Err(format!("Failed to create backup: {}", error_msg))
-            }
-        }
-        Err(e) => Err(format!("Failed to execute command: {}", e)),
-    }
+    println!("Hosts file backed up to: {}", backup.path.display());
+    Ok(backups::BackupFile::from(&backup))
 }
 
-// ===== BLOCK/ALLOW LIST FUNCTIONS =====
-
-
+// Restore a backup listed by get_backup_files over the hosts file
+#[tauri::command]
+async fn restore_backup(filename: String) -> Result<backups::RestoreResult, String> {
+    let result = backups::repository().restore(&filename).map_err(|e| e.message().to_string())?;
+    println!("Restored backup {}", result.backup);
+    Ok(result)
+}
 
 // Save hosts file
 #[tauri::command]
-async fn save_hosts_file(hosts_content: String) -> Result<(), String> {
+async fn save_hosts_file(hosts_content: String) -> Result<Option<guard::GuardReport>, String> {
     // Use the provided hosts content instead of creating a basic one
     let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
     
-    // Add timestamp header to the content
-    let final_content = format!("# Managed by HostDNI. Last updated at: {}\n\n{}", timestamp, hosts_content);
+    // Add timestamp header to the parsed content
+    let mut hosts_file = HostsFile::default();
+    hosts_file.push_line(Line::comment(&format!("Managed by HostDNI. Last updated at: {}", timestamp)));
+    hosts_file.push_line(Line::blank());
+    hosts_file.lines.extend(HostsFile::parse(&hosts_content).lines);
     
-    // Write to hosts file (this would require elevated permissions)
-    let hosts_path = "/etc/hosts";
+    // In managed-section mode the content only replaces HostDNI's section
+    let mode = config::current().deploy.mode;
+    let current = match mode {
+        config::DeployMode::WholeFile => String::new(),
+        config::DeployMode::ManagedSection => deploy::read_current()?,
+    };
+    let assembled = deploy::assemble(&current, &mut hosts_file, mode)?;
+    let final_content = assembled.content;
     
-    // Create a temporary file in a writable location
-    let temp_dir = std::env::temp_dir();
-    let temp_file = temp_dir.join("hostdni_hosts_temp");
-    
-    // Write content to temporary file
-    if let Err(e) = fs::write(&temp_file, final_content) {
-        return Err(format!("Failed to create temporary hosts file: {}", e));
+    // Keep the file being replaced in the backup repository
+    if let Some(snapshot) = backups::snapshot_before_deploy("Before deployment")? {
+        println!("Hosts file backed up to: {}", snapshot);
     }
     
-    // Use osascript to show native macOS authentication dialog
-    let script = format!(
-        "do shell script \"cp {} {}\" with administrator privileges",
-        temp_file.to_str().unwrap(), hosts_path
-    );
-    
-    let output = std::process::Command::new("osascript")
-        .args(&["-e", &script])
-        .output();
-    
-    // Clean up temporary file
-    let _ = fs::remove_file(&temp_file);
-    
-    match output {
-        Ok(result) => {
-            if result.status.success() {
-                println!("Hosts file updated successfully");
-                Ok(())
-            } else {
-                let error_msg = String::from_utf8_lossy(&result.stderr);
-                Err(format!("Failed to write hosts file: {}", error_msg))
-            }
-        }
-        Err(e) => Err(format!("Failed to execute command: {}", e)),
-    }
+    // Write to hosts file (requires elevated permissions; refused while it is disabled)
+    deploy::write(&final_content)?;
+    println!("Hosts file updated successfully");
+    Ok(assembled.guard)
+}
+
+// Install the privileged helper so routine hosts file writes stop prompting
+#[tauri::command]
+async fn install_privileged_helper() -> Result<(), String> {
+    privilege::install_helper()?;
+    println!("Privileged helper installed");
+    Ok(())
+}
+
+// Compile the hosts file and diff it against the current one without writing anything
+#[tauri::command]
+fn preview_hosts_file(sink_ip: Option<String>) -> Result<deploy::HostsPreview, String> {
+    deploy::preview(&deploy::sink_ip(sink_ip.as_deref())?)
+}
+
+// Get the configuration in effect
+#[tauri::command]
+fn get_config() -> config::Config {
+    config::current()
+}
+
+// Validate, save and apply a new configuration; returns settings that need a restart
+#[tauri::command]
+fn update_config(config: config::Config) -> Result<Vec<String>, String> {
+    config::update(config)
 }
 
 // ===== NETWORK MONITORING FUNCTIONS =====
@@ -336,11 +246,26 @@ async fn generate_sample_logs() -> Result<(), String> {
 }
 
 fn main() {
+    // Load the configuration before anything reads it
+    let bind_address = config::startup().bind_address();
+
     // Start the API token rotation
     auth::start_token_rotation();
 
+    println!("[HostDNI] Hosts file: {}", paths::hosts_paths().hosts.display());
+    println!("[HostDNI] Privilege backend: {}", privilege::executor().name());
+
+    // Open the database (and run pending migrations) before serving requests
+    store::store();
+    println!("[HostDNI] Database: {}", store::db_path().display());
+
+    // Bring backups from the earlier backup locations into the repository
+    let repository = backups::repository();
+    let imported = repository.import(&backups::legacy_dirs(&config::current()));
+    println!("[HostDNI] Backups: {} ({} imported)", repository.root().display(), imported);
+
     // Start the Actix REST API server in a background thread
-    std::thread::spawn(|| {
+    std::thread::spawn(move || {
         let sys = actix_rt::System::new();
         sys.block_on(async {
             let server = HttpServer::new(|| {
@@ -357,7 +282,7 @@ fn main() {
                         web::scope("/api/auth")
                             .route("/token", web::get().to(auth::get_token))
                     )
-                    // Host entries endpoints (streaming from /etc/hosts)
+                    // Host entries endpoints (streaming from the hosts file)
                     .service(
                         web::scope("/api/etc/hosts")
                             .route("", web::get().to(api::get_host_entries))
@@ -367,7 +292,13 @@ fn main() {
                             .route("/status", web::get().to(api::get_hosts_status))
                             .route("/disable", web::post().to(api::disable_hosts_file))
                             .route("/enable", web::post().to(api::enable_hosts_file))
+                            .route("/preview", web::post().to(api::preview_hosts_file))
                             .route("/build_and_save", web::post().to(api::build_and_save_hosts_file))
+                            .route("/bulk", web::post().to(api::bulk_host_operations))
+                            .route("/{id}", web::put().to(api::replace_host_entry))
+                            .route("/{id}", web::patch().to(api::update_host_entry))
+                            .route("/{id}", web::delete().to(api::delete_host_entry))
+                            .route("/{id}/toggle", web::post().to(api::toggle_host_entry))
                     )
 
                     // Backups endpoints
@@ -377,25 +308,62 @@ fn main() {
                             .route("/files", web::get().to(api::get_backup_files))
                             .route("", web::get().to(api::get_backups))
                             .route("", web::post().to(api::create_backup))
+                            .route("/prune", web::post().to(api::prune_backups))
+                            .route("/{id}/restore", web::post().to(api::restore_backup))
+                            .route("/{a}/diff/{b}", web::get().to(api::diff_backups))
+                            .route("/{id}/pin", web::post().to(api::pin_backup))
+                            .route("/{id}/unpin", web::post().to(api::unpin_backup))
                     )
                     // Allow lists endpoints
                     .service(
                         web::scope("/api/allow-lists")
                             .route("", web::get().to(api::get_allow_lists))
+                            .route("", web::post().to(api::create_allow_list_entry))
+                            .route("/import", web::post().to(api::import_allow_list_entries))
+                            .route("/{id}", web::get().to(api::get_allow_list_entry))
+                            .route("/{id}", web::put().to(api::replace_allow_list_entry))
+                            .route("/{id}", web::patch().to(api::update_allow_list_entry))
+                            .route("/{id}", web::delete().to(api::delete_allow_list_entry))
+                            .route("/{id}/enable", web::post().to(api::enable_allow_list_entry))
+                            .route("/{id}/disable", web::post().to(api::disable_allow_list_entry))
                     )
                     // Block lists endpoints
                     .service(
                         web::scope("/api/block-lists")
                             .route("", web::get().to(api::get_block_lists))
+                            .route("", web::post().to(api::create_block_list_entry))
+                            .route("/import", web::post().to(api::import_block_list_entries))
+                            .route("/{id}", web::get().to(api::get_block_list_entry))
+                            .route("/{id}", web::put().to(api::replace_block_list_entry))
+                            .route("/{id}", web::patch().to(api::update_block_list_entry))
+                            .route("/{id}", web::delete().to(api::delete_block_list_entry))
+                    )
+                    // Remote list sources endpoints
+                    .service(
+                        web::scope("/api/sources")
+                            .route("", web::get().to(api::get_sources))
+                            .route("", web::post().to(api::create_source_subscription))
+                            .route("/{id}", web::get().to(api::get_source))
+                            .route("/{id}", web::put().to(api::replace_source))
+                            .route("/{id}", web::patch().to(api::update_source_subscription))
+                            .route("/{id}", web::delete().to(api::delete_source_subscription))
+                            .route("/{id}/refresh", web::post().to(api::refresh_source))
+                    )
+
+                    // Configuration endpoints
+                    .service(
+                        web::scope("/api/config")
+                            .route("", web::get().to(api::get_config))
+                            .route("", web::put().to(api::update_config))
                     )
 
                     // System endpoints
                     .route("/api/health", web::get().to(api::health_check))
                     .route("/api/stats", web::get().to(api::get_stats))
             })
-            .bind("127.0.0.1:8080")
+            .bind(&bind_address)
             .unwrap();
-            println!("REST API server running on http://127.0.0.1:8080");
+            println!("REST API server running on http://{}", bind_address);
             server.run().await.unwrap();
         });
     });
@@ -405,7 +373,12 @@ fn main() {
         .invoke_handler(tauri::generate_handler![
             get_backup_files,
             backup_current_hosts_file,
+            restore_backup,
             save_hosts_file,
+            install_privileged_helper,
+            preview_hosts_file,
+            get_config,
+            update_config,
             get_network_logs_page,
             get_network_logs_count,
             clear_network_logs,
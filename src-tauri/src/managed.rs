@@ -0,0 +1,145 @@
+// Managed section of the hosts file
+// - In `managed_section` mode (config `deploy.mode`) HostDNI owns only the lines between
+//   `# BEGIN HostDNI` and `# END HostDNI`; everything outside them is kept byte for byte, so
+//   entries written by Docker, VPN clients or other tools survive deployments
+// - A file without markers gets the section appended at its end
+// - Unbalanced or repeated markers are an error: HostDNI never guesses where its section ends
+
+pub const BEGIN_MARKER: &str = "# BEGIN HostDNI";
+pub const END_MARKER: &str = "# END HostDNI";
+
+fn marker(line: &str) -> Option<&'static str> {
+    match line.trim() {
+        BEGIN_MARKER => Some(BEGIN_MARKER),
+        END_MARKER => Some(END_MARKER),
+        _ => None,
+    }
+}
+
+// A hosts file split around its managed section
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sections<'a> {
+    before: &'a str,
+    // Marker lines (with their line endings) and the text between them
+    section: Option<(&'a str, &'a str, &'a str)>,
+    after: &'a str,
+    line_ending: &'static str,
+}
+
+pub fn split(content: &str) -> Result<Sections<'_>, String> {
+    let mut begin: Option<(usize, usize)> = None;
+    let mut section = None;
+    let mut offset = 0;
+    for (index, line) in content.split_inclusive('\n').enumerate() {
+        let start = offset;
+        offset += line.len();
+        match (marker(line), begin) {
+            (Some(BEGIN_MARKER), None) if section.is_none() => begin = Some((start, offset)),
+            (Some(END_MARKER), Some((begin_start, begin_end))) => {
+                section = Some((begin_start, begin_end, start, offset));
+                begin = None;
+            }
+            (Some(found), _) => return Err(format!("Unexpected '{}' on line {} of the hosts file", found, index + 1)),
+            (None, _) => {}
+        }
+    }
+    if begin.is_some() {
+        return Err(format!("The hosts file has '{}' without '{}'", BEGIN_MARKER, END_MARKER));
+    }
+
+    let line_ending = if content.contains("\r\n") { "\r\n" } else { "\n" };
+    Ok(match section {
+        Some((begin_start, begin_end, end_start, end_end)) => Sections {
+            before: &content[..begin_start],
+            section: Some((
+                &content[begin_start..begin_end],
+                &content[begin_end..end_start],
+                &content[end_start..end_end],
+            )),
+            after: &content[end_end..],
+            line_ending,
+        },
+        None => Sections { before: content, section: None, after: "", line_ending },
+    })
+}
+
+impl Sections<'_> {
+    // Text between the markers; empty when there is no section yet
+    pub fn inside(&self) -> &str {
+        self.section.map(|(_, inside, _)| inside).unwrap_or("")
+    }
+
+    // Everything HostDNI does not own
+    pub fn outside(&self) -> String {
+        format!("{}{}", self.before, self.after)
+    }
+
+    // The file with the section replaced by `inside`, or appended when there was none
+    pub fn replace(&self, inside: &str) -> Result<String, String> {
+        if let Some(line) = inside.lines().find(|line| marker(line).is_some()) {
+            return Err(format!("Managed section content must not contain '{}'", line.trim()));
+        }
+        let mut inside = inside.to_string();
+        if !inside.is_empty() && !inside.ends_with('\n') {
+            inside.push_str(self.line_ending);
+        }
+
+        let mut content = String::from(self.before);
+        match self.section {
+            Some((begin, _, end)) => {
+                content.push_str(begin);
+                content.push_str(&inside);
+                content.push_str(end);
+                content.push_str(self.after);
+            }
+            None => {
+                if !content.is_empty() && !content.ends_with('\n') {
+                    content.push_str(self.line_ending);
+                }
+                content.push_str(BEGIN_MARKER);
+                content.push_str(self.line_ending);
+                content.push_str(&inside);
+                content.push_str(END_MARKER);
+                content.push_str(self.line_ending);
+            }
+        }
+        Ok(content)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn appends_a_section_to_a_file_without_markers() {
+        let current = "127.0.0.1 localhost\n172.17.0.2 db.docker";
+        let sections = split(current).unwrap();
+        assert_eq!(sections.inside(), "");
+
+        let written = sections.replace("0.0.0.0\tads.example.com\n").unwrap();
+        assert_eq!(written, format!("{}\n# BEGIN HostDNI\n0.0.0.0\tads.example.com\n# END HostDNI\n", current));
+    }
+
+    #[test]
+    fn replaces_only_the_section() {
+        let before = "127.0.0.1 localhost\r\n10.8.0.1  vpn.corp   # added by VPN\r\n";
+        let after = "\r\n172.17.0.2 db.docker";
+        let current = format!("{}# BEGIN HostDNI\r\n0.0.0.0 old.example.com\r\n# END HostDNI\r\n{}", before, after);
+        let sections = split(&current).unwrap();
+        assert_eq!(sections.inside(), "0.0.0.0 old.example.com\r\n");
+        assert_eq!(sections.outside(), format!("{}{}", before, after));
+
+        let written = sections.replace("0.0.0.0 new.example.com").unwrap();
+        assert_eq!(written, format!("{}# BEGIN HostDNI\r\n0.0.0.0 new.example.com\r\n# END HostDNI\r\n{}", before, after));
+        assert_eq!(split(&written).unwrap().replace("").unwrap(), format!("{}# BEGIN HostDNI\r\n# END HostDNI\r\n{}", before, after));
+    }
+
+    #[test]
+    fn rejects_unbalanced_markers() {
+        assert!(split("# BEGIN HostDNI\n0.0.0.0 a.example.com\n").is_err());
+        assert!(split("# END HostDNI\n").is_err());
+        assert!(split("# BEGIN HostDNI\n# END HostDNI\n# BEGIN HostDNI\n# END HostDNI\n").is_err());
+        assert!(split("").unwrap().replace("# END HostDNI\n").is_err());
+    }
+}
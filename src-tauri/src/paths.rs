@@ -0,0 +1,83 @@
+// Hosts file locations for HostDNI
+// - Single source for the hosts file and its sibling paths (rollback copy, disabled copy, backups)
+// - HOSTDNI_HOSTS_FILE points HostDNI at another hosts file (e.g. a container's)
+// - HOSTDNI_TARGET_DIR re-roots every path under a directory (a chroot, or a scratch
+//   directory for dry runs and integration tests)
+
+use once_cell::sync::Lazy;
+use std::path::{Path, PathBuf};
+
+pub const SYSTEM_HOSTS_PATH: &str = "/etc/hosts";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HostsPaths {
+    // The managed hosts file
+    pub hosts: PathBuf,
+    // Previous version, kept for rollback on every write
+    pub backup: PathBuf,
+    // Where the hosts file is moved while disabled
+    pub disabled: PathBuf,
+    // Timestamped backups directory
+    pub backups_dir: PathBuf,
+}
+
+impl HostsPaths {
+    // Paths for a hosts file; siblings live next to it (hosts.backup, hosts.disabled, hosts_backups/)
+    pub fn for_hosts_file(hosts: impl Into<PathBuf>) -> HostsPaths {
+        let hosts = hosts.into();
+        let name = hosts.file_name().and_then(|n| n.to_str()).unwrap_or("hosts").to_string();
+        let sibling = |suffix: &str| hosts.with_file_name(format!("{}{}", name, suffix));
+        HostsPaths {
+            backup: sibling(".backup"),
+            disabled: sibling(".disabled"),
+            backups_dir: sibling("_backups"),
+            hosts,
+        }
+    }
+
+    // The real system hosts file
+    pub fn system() -> HostsPaths {
+        HostsPaths::for_hosts_file(SYSTEM_HOSTS_PATH)
+    }
+
+    // Same layout, re-rooted under `target_dir` (e.g. /srv/chroot/etc/hosts)
+    pub fn under(&self, target_dir: &Path) -> HostsPaths {
+        let reroot = |path: &Path| target_dir.join(path.strip_prefix("/").unwrap_or(path));
+        HostsPaths {
+            hosts: reroot(&self.hosts),
+            backup: reroot(&self.backup),
+            disabled: reroot(&self.disabled),
+            backups_dir: reroot(&self.backups_dir),
+        }
+    }
+
+    // Resolve from HOSTDNI_HOSTS_FILE and HOSTDNI_TARGET_DIR
+    pub fn from_env() -> HostsPaths {
+        let paths = match std::env::var_os("HOSTDNI_HOSTS_FILE") {
+            Some(hosts) if !hosts.is_empty() => HostsPaths::for_hosts_file(hosts),
+            _ => HostsPaths::system(),
+        };
+        match std::env::var_os("HOSTDNI_TARGET_DIR") {
+            Some(target_dir) if !target_dir.is_empty() => paths.under(Path::new(&target_dir)),
+            _ => paths,
+        }
+    }
+
+    // Whether these are the real system paths rather than an override
+    pub fn is_system(&self) -> bool {
+        *self == HostsPaths::system()
+    }
+
+    // Disabled: moved aside to the disabled path and not recreated
+    pub fn is_disabled(&self) -> bool {
+        self.disabled.exists() && !self.hosts.exists()
+    }
+}
+
+static PATHS: Lazy<HostsPaths> = Lazy::new(HostsPaths::from_env);
+
+// Paths used by every handler and command in this process
+pub fn hosts_paths() -> &'static HostsPaths {
+    &PATHS
+}
+
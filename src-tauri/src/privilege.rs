@@ -0,0 +1,636 @@
+// Privilege escalation for HostDNI
+// - Every operation that needs root goes through a PrivilegedExecutor
+// - Operations are typed actions (copy, move, mkdir, write-bytes), never raw shell strings
+// - Backends: osascript (macOS), pkexec (polkit), sudo -n, and "already root"
+// - When the privileged helper daemon is running, supported actions go to it
+//   instead, without a password prompt (see helper.rs; Unix only)
+// - Shell-based backends receive one script per batch, passed as an argument (never
+//   spliced into AppleScript source), with every path single-quoted
+// - The backend is chosen at runtime (see `detect`), or forced with
+//   HOSTDNI_PRIVILEGE_BACKEND=osascript|pkexec|sudo|root|fake
+// - FakeExecutor records actions instead of running them, for tests
+
+use crate::atomic;
+#[cfg(unix)]
+use crate::helper::{self, HelperRequest};
+use crate::paths;
+use once_cell::sync::Lazy;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PrivilegedAction {
+    Copy { from: PathBuf, to: PathBuf },
+    Move { from: PathBuf, to: PathBuf },
+    Mkdir { path: PathBuf },
+    // Atomically replace `path` with `contents`, keeping its owner and mode.
+    // The previous version is copied to `rollback` first and restored if the
+    // written file does not match.
+    WriteBytes { path: PathBuf, contents: Vec<u8>, rollback: Option<PathBuf> },
+    // Register and start a launchd plist or systemd unit
+    LoadService { path: PathBuf },
+}
+
+pub trait PrivilegedExecutor: Send + Sync {
+    // Short backend name, for logs and status endpoints
+    fn name(&self) -> &'static str;
+
+    // Run a batch of actions with elevated privileges (one prompt per batch)
+    fn execute(&self, actions: &[PrivilegedAction]) -> Result<(), String>;
+}
+
+// Quote an argument for /bin/sh: wrap in single quotes, escaping embedded ones
+pub fn shell_quote(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
+
+fn quote_path(path: &Path) -> Result<String, String> {
+    let path = path
+        .to_str()
+        .ok_or_else(|| format!("Path is not valid UTF-8: {}", path.display()))?;
+    if path.contains('\0') {
+        return Err("Path contains a NUL byte".to_string());
+    }
+    Ok(shell_quote(path))
+}
+
+// Shell script for a batch of actions. File contents are staged in private
+// temporary files, which are removed when the script is dropped.
+pub struct PreparedScript {
+    pub script: String,
+    staged: Vec<PathBuf>,
+}
+
+impl Drop for PreparedScript {
+    fn drop(&mut self) {
+        for path in &self.staged {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+fn stage_contents(contents: &[u8]) -> Result<PathBuf, String> {
+    let path = std::env::temp_dir().join(format!("hostdni-{}.staged", uuid::Uuid::new_v4().simple()));
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path)
+        .map_err(|e| format!("Failed to create staging file: {}", e))?;
+    file.write_all(contents)
+        .and_then(|_| file.sync_all())
+        .map_err(|e| {
+            let _ = fs::remove_file(&path);
+            format!("Failed to write staging file: {}", e)
+        })?;
+    Ok(path)
+}
+
+// Owner and mode arguments for `install`, taken from the file being replaced
+#[cfg(unix)]
+fn install_attributes(path: &Path) -> String {
+    use std::os::unix::fs::MetadataExt;
+
+    match fs::metadata(path) {
+        Ok(metadata) => format!(
+            "-m {:o} -o {} -g {}",
+            metadata.mode() & 0o7777,
+            metadata.uid(),
+            metadata.gid()
+        ),
+        Err(_) => "-m 644".to_string(),
+    }
+}
+
+#[cfg(not(unix))]
+fn install_attributes(_path: &Path) -> String {
+    "-m 644".to_string()
+}
+
+pub fn prepare_script(actions: &[PrivilegedAction]) -> Result<PreparedScript, String> {
+    let mut prepared = PreparedScript {
+        script: String::from("set -e\n"),
+        staged: Vec::new(),
+    };
+
+    for action in actions {
+        let commands = match action {
+            PrivilegedAction::Copy { from, to } => {
+                format!("cp -- {} {}", quote_path(from)?, quote_path(to)?)
+            }
+            PrivilegedAction::Move { from, to } => {
+                format!("mv -- {} {}", quote_path(from)?, quote_path(to)?)
+            }
+            PrivilegedAction::Mkdir { path } => format!("mkdir -p -- {}", quote_path(path)?),
+            PrivilegedAction::WriteBytes { path, contents, rollback } => {
+                let staged_path = stage_contents(contents)?;
+                prepared.staged.push(staged_path.clone());
+                let staged = quote_path(&staged_path)?;
+                let target = quote_path(path)?;
+                let temp = quote_path(&atomic::temp_path_for(path)?)?;
+                let rollback = match rollback {
+                    Some(rollback) => Some(quote_path(rollback)?),
+                    None => None,
+                };
+
+                let mut commands = Vec::new();
+                if let Some(ref rollback) = rollback {
+                    commands.push(format!("if [ -e {} ]; then cp -p -- {} {}; fi", target, target, rollback));
+                }
+                commands.push(format!("install {} -- {} {}", install_attributes(path), staged, temp));
+                commands.push(format!("mv -f -- {} {}", temp, target));
+                let restore = rollback
+                    .map(|rollback| format!("cp -p -- {} {}; ", rollback, target))
+                    .unwrap_or_default();
+                commands.push(format!(
+                    "if ! cmp -s -- {} {}; then {}echo 'Verification failed after write' >&2; exit 1; fi",
+                    staged, target, restore
+                ));
+                commands.join("\n")
+            }
+            PrivilegedAction::LoadService { path } => {
+                if cfg!(target_os = "macos") {
+                    format!("launchctl load -w {}", quote_path(path)?)
+                } else {
+                    let unit = path.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+                    format!("systemctl daemon-reload\nsystemctl enable --now {}", shell_quote(unit))
+                }
+            }
+        };
+        prepared.script.push_str(&commands);
+        prepared.script.push('\n');
+    }
+
+    Ok(prepared)
+}
+
+// Run a prepared command, mapping failure to its stderr
+fn run_command(mut command: Command) -> Result<(), String> {
+    let output = command
+        .output()
+        .map_err(|e| format!("Failed to execute command: {}", e))?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).trim().to_string())
+    }
+}
+
+// Arguments for osascript: the script is passed as argv, never embedded in AppleScript source
+pub fn osascript_args(script: &str) -> Vec<String> {
+    vec![
+        "-e".to_string(),
+        "on run argv".to_string(),
+        "-e".to_string(),
+        "do shell script (item 1 of argv) with administrator privileges".to_string(),
+        "-e".to_string(),
+        "end run".to_string(),
+        script.to_string(),
+    ]
+}
+
+// macOS: native authentication dialog via AppleScript
+pub struct OsascriptExecutor;
+
+impl PrivilegedExecutor for OsascriptExecutor {
+    fn name(&self) -> &'static str {
+        "osascript"
+    }
+
+    fn execute(&self, actions: &[PrivilegedAction]) -> Result<(), String> {
+        let prepared = prepare_script(actions)?;
+        let mut osascript = Command::new("osascript");
+        osascript.args(osascript_args(&prepared.script));
+        run_command(osascript)
+    }
+}
+
+// Linux desktops: polkit authentication agent
+pub struct PkexecExecutor;
+
+impl PrivilegedExecutor for PkexecExecutor {
+    fn name(&self) -> &'static str {
+        "pkexec"
+    }
+
+    fn execute(&self, actions: &[PrivilegedAction]) -> Result<(), String> {
+        let prepared = prepare_script(actions)?;
+        let mut pkexec = Command::new("pkexec");
+        pkexec.args(["/bin/sh", "-c", &prepared.script]);
+        run_command(pkexec)
+    }
+}
+
+// Non-interactive sudo; only succeeds with cached credentials or NOPASSWD rules
+pub struct SudoExecutor;
+
+impl PrivilegedExecutor for SudoExecutor {
+    fn name(&self) -> &'static str {
+        "sudo"
+    }
+
+    fn execute(&self, actions: &[PrivilegedAction]) -> Result<(), String> {
+        let prepared = prepare_script(actions)?;
+        let mut sudo = Command::new("sudo");
+        sudo.args(["-n", "/bin/sh", "-c", &prepared.script]);
+        run_command(sudo)
+    }
+}
+
+// Process already runs as root; actions run in-process, without a shell
+pub struct RootExecutor;
+
+impl PrivilegedExecutor for RootExecutor {
+    fn name(&self) -> &'static str {
+        "root"
+    }
+
+    fn execute(&self, actions: &[PrivilegedAction]) -> Result<(), String> {
+        for action in actions {
+            match action {
+                PrivilegedAction::Copy { from, to } => {
+                    fs::copy(from, to)
+                        .map_err(|e| format!("Failed to copy {} to {}: {}", from.display(), to.display(), e))?;
+                }
+                PrivilegedAction::Move { from, to } => {
+                    fs::rename(from, to)
+                        .map_err(|e| format!("Failed to move {} to {}: {}", from.display(), to.display(), e))?;
+                }
+                PrivilegedAction::Mkdir { path } => {
+                    fs::create_dir_all(path)
+                        .map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+                }
+                PrivilegedAction::WriteBytes { path, contents, rollback } => {
+                    atomic::write_atomic(path, contents, rollback.as_deref())?;
+                }
+                PrivilegedAction::LoadService { path } => {
+                    if cfg!(target_os = "macos") {
+                        let mut launchctl = Command::new("launchctl");
+                        launchctl.arg("load").arg("-w").arg(path);
+                        run_command(launchctl)?;
+                    } else {
+                        let unit = path.file_name().unwrap_or_default();
+                        let mut reload = Command::new("systemctl");
+                        reload.arg("daemon-reload");
+                        run_command(reload)?;
+                        let mut enable = Command::new("systemctl");
+                        enable.arg("enable").arg("--now").arg(unit);
+                        run_command(enable)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// Records actions without running them; optionally fails every call
+#[derive(Default)]
+pub struct FakeExecutor {
+    pub actions: Mutex<Vec<PrivilegedAction>>,
+    pub fail_with: Option<String>,
+}
+
+impl PrivilegedExecutor for FakeExecutor {
+    fn name(&self) -> &'static str {
+        "fake"
+    }
+
+    fn execute(&self, actions: &[PrivilegedAction]) -> Result<(), String> {
+        self.actions.lock().unwrap().extend_from_slice(actions);
+        match self.fail_with {
+            Some(ref error) => Err(error.clone()),
+            None => Ok(()),
+        }
+    }
+}
+
+// Sends supported actions to the privileged helper when it is running,
+// and everything else (or everything, without a helper) to `fallback`
+#[cfg(unix)]
+pub struct HelperExecutor {
+    pub fallback: Arc<dyn PrivilegedExecutor>,
+    // Helper socket; a stale socket file left by a stopped helper counts as no helper
+    pub socket: PathBuf,
+}
+
+#[cfg(unix)]
+impl HelperExecutor {
+    // Map actions onto the helper's narrow protocol; None if any action is unsupported
+    fn to_requests(actions: &[PrivilegedAction]) -> Option<Vec<HelperRequest>> {
+        // The helper only ever touches the system hosts file
+        let system = paths::HostsPaths::system();
+        let hosts = system.hosts.as_path();
+        let disabled = system.disabled.as_path();
+        let backup_dir = system.backups_dir.as_path();
+        let backup_name = |path: &Path| -> Option<String> {
+            let name = path.file_name()?.to_str()?;
+            (path.parent() == Some(backup_dir) && helper::is_valid_backup_name(name)).then(|| name.to_string())
+        };
+
+        let mut requests = Vec::new();
+        for action in actions {
+            let request = match action {
+                PrivilegedAction::WriteBytes { path, contents, .. } if path == hosts => {
+                    HelperRequest::WriteHosts { content: String::from_utf8(contents.clone()).ok()? }
+                }
+                PrivilegedAction::Move { from, to } if from == hosts && to == disabled => HelperRequest::Disable,
+                PrivilegedAction::Move { from, to } if from == disabled && to == hosts => HelperRequest::Enable,
+                PrivilegedAction::Copy { from, to } if from == hosts => {
+                    HelperRequest::Backup { file_name: backup_name(to)? }
+                }
+                PrivilegedAction::Copy { from, to } if to == hosts => {
+                    HelperRequest::Restore { file_name: backup_name(from)? }
+                }
+                // The helper creates its backup directory itself
+                PrivilegedAction::Mkdir { path } if path == backup_dir => continue,
+                _ => return None,
+            };
+            requests.push(request);
+        }
+        Some(requests)
+    }
+}
+
+#[cfg(unix)]
+impl PrivilegedExecutor for HelperExecutor {
+    fn name(&self) -> &'static str {
+        if self.socket.exists() {
+            "helper"
+        } else {
+            self.fallback.name()
+        }
+    }
+
+    fn execute(&self, actions: &[PrivilegedAction]) -> Result<(), String> {
+        if let Some(requests) = HelperExecutor::to_requests(actions) {
+            for (index, request) in requests.iter().enumerate() {
+                match helper::connect(&self.socket) {
+                    Ok(stream) => helper::send_request(stream, request)?,
+                    // Nothing sent yet, so the whole batch can still go to the fallback
+                    Err(_) if index == 0 => return self.fallback.execute(actions),
+                    Err(e) => return Err(e),
+                }
+            }
+            return Ok(());
+        }
+        self.fallback.execute(actions)
+    }
+}
+
+// Whether this process may replace `path` itself (writable file and directory)
+#[cfg(unix)]
+fn is_writable(path: &Path) -> bool {
+    use std::os::unix::ffi::OsStrExt;
+
+    let writable = |path: &Path| {
+        std::ffi::CString::new(path.as_os_str().as_bytes())
+            .map(|c_path| unsafe { libc::access(c_path.as_ptr(), libc::W_OK) } == 0)
+            .unwrap_or(false)
+    };
+    let dir_writable = path.parent().map(writable).unwrap_or(false);
+    dir_writable && (!path.exists() || writable(path))
+}
+
+#[cfg(not(unix))]
+fn is_writable(path: &Path) -> bool {
+    let writable = |path: &Path| fs::metadata(path).map(|metadata| !metadata.permissions().readonly()).unwrap_or(false);
+    path.parent().is_some_and(Path::is_dir) && (!path.exists() || writable(path))
+}
+
+#[cfg(unix)]
+fn is_root() -> bool {
+    unsafe { libc::geteuid() == 0 }
+}
+
+#[cfg(not(unix))]
+fn is_root() -> bool {
+    false
+}
+
+fn in_path(program: &str) -> bool {
+    std::env::var_os("PATH")
+        .map(|paths| std::env::split_paths(&paths).any(|dir| dir.join(program).is_file()))
+        .unwrap_or(false)
+}
+
+pub fn executor_by_name(name: &str) -> Option<Arc<dyn PrivilegedExecutor>> {
+    match name {
+        "osascript" => Some(Arc::new(OsascriptExecutor)),
+        "pkexec" => Some(Arc::new(PkexecExecutor)),
+        "sudo" => Some(Arc::new(SudoExecutor)),
+        "root" => Some(Arc::new(RootExecutor)),
+        "fake" => Some(Arc::new(FakeExecutor::default())),
+        _ => None,
+    }
+}
+
+// Pick the backend for this process and platform
+pub fn detect() -> Arc<dyn PrivilegedExecutor> {
+    if let Ok(name) = std::env::var("HOSTDNI_PRIVILEGE_BACKEND") {
+        match executor_by_name(&name) {
+            Some(executor) => return executor,
+            None => println!("[HostDNI] Unknown privilege backend '{}', detecting instead", name),
+        }
+    }
+
+    // Redirected hosts files in a directory we can write (e.g. a scratch
+    // directory) need no elevation
+    let paths = paths::hosts_paths();
+    if is_root() || (!paths.is_system() && is_writable(&paths.hosts)) {
+        return Arc::new(RootExecutor);
+    }
+
+    let fallback: Arc<dyn PrivilegedExecutor> = if cfg!(target_os = "macos") {
+        Arc::new(OsascriptExecutor)
+    } else if (std::env::var_os("DISPLAY").is_some() || std::env::var_os("WAYLAND_DISPLAY").is_some())
+        && in_path("pkexec")
+    {
+        Arc::new(PkexecExecutor)
+    } else {
+        Arc::new(SudoExecutor)
+    };
+    with_helper(fallback)
+}
+
+#[cfg(unix)]
+fn with_helper(fallback: Arc<dyn PrivilegedExecutor>) -> Arc<dyn PrivilegedExecutor> {
+    Arc::new(HelperExecutor { fallback, socket: helper::socket_path() })
+}
+
+#[cfg(not(unix))]
+fn with_helper(fallback: Arc<dyn PrivilegedExecutor>) -> Arc<dyn PrivilegedExecutor> {
+    fallback
+}
+
+static EXECUTOR: Lazy<Arc<dyn PrivilegedExecutor>> = Lazy::new(detect);
+
+// Backend used by all privileged operations
+pub fn executor() -> Arc<dyn PrivilegedExecutor> {
+    EXECUTOR.clone()
+}
+
+// Install the helper binary shipped next to the app and start it as a
+// system service for the current user (one final password prompt)
+#[cfg(unix)]
+pub fn install_helper() -> Result<(), String> {
+    let source = std::env::current_exe()
+        .map_err(|e| format!("Failed to locate application binary: {}", e))?
+        .with_file_name("hostdni-helper");
+    if !source.is_file() {
+        return Err(format!("Helper binary not found at {}", source.display()));
+    }
+
+    let install_path = PathBuf::from(helper::INSTALL_PATH);
+    let install_dir = install_path.parent().map(Path::to_path_buf).unwrap_or_default();
+    let (service_path, definition) = helper::service_definition(unsafe { libc::getuid() });
+
+    executor().execute(&[
+        PrivilegedAction::Mkdir { path: install_dir },
+        PrivilegedAction::Copy { from: source, to: install_path },
+        PrivilegedAction::WriteBytes { path: service_path.clone(), contents: definition.into_bytes(), rollback: None },
+        PrivilegedAction::LoadService { path: service_path },
+    ])
+}
+
+#[cfg(not(unix))]
+pub fn install_helper() -> Result<(), String> {
+    Err("The privileged helper is only available on macOS and Linux".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOSTILE_NAMES: &[&str] = &[
+        "with space",
+        "single'quote",
+        "double\"quote",
+        "semi;colon",
+        "$(touch pwned)",
+        "`touch pwned`",
+        "back\\slash",
+        "new\nline",
+        "-leading-dash",
+        "glob*?[a]",
+    ];
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("hostdni-test-{}-{}", name, uuid::Uuid::new_v4().simple()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn run_script(script: &str, dir: &Path) -> Result<(), String> {
+        let mut sh = Command::new("/bin/sh");
+        sh.args(["-c", script]).current_dir(dir);
+        run_command(sh)
+    }
+
+    #[test]
+    fn shell_quote_round_trips_hostile_strings() {
+        for name in HOSTILE_NAMES {
+            let output = Command::new("/bin/sh")
+                .args(["-c", &format!("printf %s {}", shell_quote(name))])
+                .output()
+                .unwrap();
+            assert_eq!(String::from_utf8_lossy(&output.stdout), *name);
+        }
+    }
+
+    #[test]
+    fn script_handles_hostile_paths_without_injection() {
+        for name in HOSTILE_NAMES {
+            let dir = scratch_dir("paths");
+            let source = dir.join(format!("src {}", name));
+            let nested = dir.join(name).join("nested");
+            let copied = nested.join(name);
+            let moved = dir.join(format!("moved {}", name));
+            let written = dir.join(format!("written {}", name));
+            fs::write(&source, "127.0.0.1 localhost\n").unwrap();
+
+            let prepared = prepare_script(&[
+                PrivilegedAction::Mkdir { path: nested.clone() },
+                PrivilegedAction::Copy { from: source.clone(), to: copied.clone() },
+                PrivilegedAction::Move { from: copied.clone(), to: moved.clone() },
+                PrivilegedAction::WriteBytes {
+                    path: written.clone(),
+                    contents: b"::1 localhost\n".to_vec(),
+                    rollback: None,
+                },
+            ])
+            .unwrap();
+            run_script(&prepared.script, &dir).unwrap();
+
+            assert!(nested.is_dir(), "mkdir failed for {:?}", name);
+            assert!(!copied.exists(), "move left source behind for {:?}", name);
+            assert_eq!(fs::read_to_string(&moved).unwrap(), "127.0.0.1 localhost\n");
+            assert_eq!(fs::read_to_string(&written).unwrap(), "::1 localhost\n");
+            assert!(!dir.join("pwned").exists(), "command injected via {:?}", name);
+            fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn write_bytes_keeps_rollback_copy() {
+        let dir = scratch_dir("rollback");
+        let target = dir.join("hosts; touch pwned");
+        let rollback = dir.join("hosts.backup 'old'");
+        fs::write(&target, "old\n").unwrap();
+
+        let prepared = prepare_script(&[PrivilegedAction::WriteBytes {
+            path: target.clone(),
+            contents: b"new\n".to_vec(),
+            rollback: Some(rollback.clone()),
+        }])
+        .unwrap();
+        run_script(&prepared.script, &dir).unwrap();
+
+        assert_eq!(fs::read_to_string(&target).unwrap(), "new\n");
+        assert_eq!(fs::read_to_string(&rollback).unwrap(), "old\n");
+        assert!(!dir.join("pwned").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn osascript_receives_script_as_argument() {
+        let script = "cp -- '/tmp/a\" & do shell script \"rm -rf ~' '/etc/hosts'";
+        let args = osascript_args(script);
+        assert_eq!(args.last().map(String::as_str), Some(script));
+        assert!(args[..args.len() - 1].iter().all(|arg| !arg.contains("/etc/hosts")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn helper_executor_falls_back_when_no_helper_is_listening() {
+        let dir = scratch_dir("stale-socket");
+        let socket = dir.join("helper.sock");
+        // Socket file left behind by a helper that is no longer running
+        drop(std::os::unix::net::UnixListener::bind(&socket).unwrap());
+        assert!(socket.exists());
+
+        let fallback = Arc::new(FakeExecutor::default());
+        let executor = HelperExecutor { fallback: fallback.clone(), socket };
+        let system = paths::HostsPaths::system();
+        let actions = [PrivilegedAction::Move { from: system.hosts, to: system.disabled }];
+        executor.execute(&actions).unwrap();
+        assert_eq!(*fallback.actions.lock().unwrap(), actions);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn staged_files_are_removed_on_drop() {
+        let prepared = prepare_script(&[PrivilegedAction::WriteBytes {
+            path: std::env::temp_dir().join("hostdni-never-written"),
+            contents: b"x".to_vec(),
+            rollback: None,
+        }])
+        .unwrap();
+        let staged = prepared.staged.clone();
+        assert!(staged.iter().all(|path| path.exists()));
+        drop(prepared);
+        assert!(staged.iter().all(|path| !path.exists()));
+    }
+}
@@ -0,0 +1,145 @@
+// Backup retention for HostDNI
+// - Decides which backups in the repository to keep (config `backups.retention`): pinned ones,
+//   the newest `keep_last`, and the newest of each of the last `keep_daily_days` days and of
+//   each of the last `keep_weekly_weeks` weeks
+// - Days are local calendar days and weeks run Monday to Sunday
+// - Everything else is pruned; `plan` only decides, `BackupRepository::prune` deletes
+
+use crate::backups::Backup;
+use crate::config::RetentionConfig;
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeepReason {
+    Pinned,
+    Last,
+    Daily,
+    Weekly,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeptBackup {
+    pub id: String,
+    pub reasons: Vec<KeepReason>,
+}
+
+// Both lists newest first
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RetentionPlan {
+    pub kept: Vec<KeptBackup>,
+    pub removed: Vec<String>,
+}
+
+pub fn plan(backups: &[Backup], config: &RetentionConfig, now: DateTime<Utc>) -> RetentionPlan {
+    plan_in(backups, config, now, &Local)
+}
+
+fn week_start(day: NaiveDate) -> NaiveDate {
+    day - Duration::days(day.weekday().num_days_from_monday() as i64)
+}
+
+fn plan_in<Tz: TimeZone>(backups: &[Backup], config: &RetentionConfig, now: DateTime<Utc>, tz: &Tz) -> RetentionPlan {
+    let mut sorted: Vec<&Backup> = backups.iter().collect();
+    sorted.sort_by(|a, b| b.meta.created_at.cmp(&a.meta.created_at).then_with(|| b.id.cmp(&a.id)));
+
+    let today = now.with_timezone(tz).date_naive();
+    let this_week = week_start(today);
+    let (mut days, mut weeks) = (HashSet::new(), HashSet::new());
+    let mut plan = RetentionPlan::default();
+    for (index, backup) in sorted.into_iter().enumerate() {
+        let day = backup.meta.created_at.with_timezone(tz).date_naive();
+        let week = week_start(day);
+        let mut reasons = Vec::new();
+        if backup.meta.pinned {
+            reasons.push(KeepReason::Pinned);
+        }
+        if index < config.keep_last {
+            reasons.push(KeepReason::Last);
+        }
+        // Newest first, so the first backup seen for a day or week is its newest
+        if (today - day).num_days() < config.keep_daily_days as i64 && days.insert(day) {
+            reasons.push(KeepReason::Daily);
+        }
+        if (this_week - week).num_days() < 7 * config.keep_weekly_weeks as i64 && weeks.insert(week) {
+            reasons.push(KeepReason::Weekly);
+        }
+
+        if reasons.is_empty() {
+            plan.removed.push(backup.id.clone());
+        } else {
+            plan.kept.push(KeptBackup { id: backup.id.clone(), reasons });
+        }
+    }
+    plan
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backups::{BackupMeta, BackupOrigin};
+    use std::path::PathBuf;
+
+    fn backup(id: &str, created_at: &str, pinned: bool) -> Backup {
+        Backup {
+            id: id.to_string(),
+            path: PathBuf::from(id),
+            meta: BackupMeta {
+                created_at: created_at.parse().unwrap(),
+                size_bytes: 0,
+                sha256: String::new(),
+                entries_count: 0,
+                origin: BackupOrigin::Manual,
+                name: None,
+                description: None,
+                pinned,
+            },
+        }
+    }
+
+    fn config(keep_last: usize, keep_daily_days: u32, keep_weekly_weeks: u32) -> RetentionConfig {
+        RetentionConfig { keep_last, keep_daily_days, keep_weekly_weeks, auto_prune: true }
+    }
+
+    fn reasons(plan: &RetentionPlan, id: &str) -> Vec<KeepReason> {
+        plan.kept.iter().find(|kept| kept.id == id).map(|kept| kept.reasons.clone()).unwrap_or_default()
+    }
+
+    // Wednesday 2024-03-13, noon
+    fn now() -> DateTime<Utc> {
+        "2024-03-13T12:00:00Z".parse().unwrap()
+    }
+
+    #[test]
+    fn keeps_the_newest_of_each_day_and_week() {
+        let backups = vec![
+            backup("wed-2", "2024-03-13T11:00:00Z", false),
+            backup("wed-1", "2024-03-13T09:00:00Z", false),
+            backup("mon", "2024-03-11T09:00:00Z", false),
+            backup("sun", "2024-03-10T09:00:00Z", false),
+            backup("sat", "2024-03-09T09:00:00Z", false),
+            backup("prev-week", "2024-03-05T09:00:00Z", false),
+            backup("old", "2024-02-01T09:00:00Z", false),
+        ];
+        let plan = plan_in(&backups, &config(1, 3, 2), now(), &Utc);
+        assert_eq!(reasons(&plan, "wed-2"), vec![KeepReason::Last, KeepReason::Daily, KeepReason::Weekly]);
+        assert_eq!(reasons(&plan, "mon"), vec![KeepReason::Daily]);
+        assert_eq!(reasons(&plan, "sun"), vec![KeepReason::Weekly]);
+        assert_eq!(reasons(&plan, "prev-week"), Vec::<KeepReason>::new());
+        assert_eq!(plan.removed, vec!["wed-1", "sat", "prev-week", "old"]);
+    }
+
+    #[test]
+    fn never_removes_pinned_backups() {
+        let backups = vec![
+            backup("new", "2024-03-13T11:00:00Z", false),
+            backup("pinned", "2020-01-01T00:00:00Z", true),
+            backup("old", "2021-01-01T00:00:00Z", false),
+        ];
+        let plan = plan_in(&backups, &config(1, 0, 0), now(), &Utc);
+        assert_eq!(reasons(&plan, "pinned"), vec![KeepReason::Pinned]);
+        assert_eq!(plan.removed, vec!["old"]);
+    }
+}
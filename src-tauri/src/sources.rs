@@ -0,0 +1,493 @@
+// Remote list subscriptions for HostDNI
+// - A source is a named URL whose contents feed the block list or the allow list
+// - Downloads are parsed by formats.rs, in the source's format or a detected one
+// - Subscriptions, their parsed domain sets and their freshness are kept in the store (see store.rs)
+// - The raw body of the last good download is cached on disk with its ETag and Last-Modified
+//   validators; refreshes send a conditional request and a 304 keeps the stored domains, unless
+//   the source's format changed since they were parsed, then the cached copy is parsed again
+// - When a download fails, the cached copy is parsed again so the last good list stays in effect
+// - Functions take the store, cache and URL explicitly, so tests can use an in-memory store,
+//   a scratch cache directory and a local HTTP stand-in
+use crate::atomic;
+use crate::config;
+use crate::formats::{self, ListFormat, ParseSummary};
+use crate::store::Store;
+use chrono::{DateTime, Utc};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SourceKind {
+    Block,
+    Allow,
+}
+
+impl SourceKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SourceKind::Block => "block",
+            SourceKind::Allow => "allow",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<SourceKind> {
+        match value {
+            "block" => Some(SourceKind::Block),
+            "allow" => Some(SourceKind::Allow),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Source {
+    pub id: String,
+    pub name: String,
+    pub url: String,
+    pub enabled: bool,
+    pub kind: SourceKind,
+    // List format; detected from the content on every refresh when Auto
+    pub format: ListFormat,
+    pub created_at: DateTime<Utc>,
+    // Last refresh, successful or not
+    pub last_attempt_at: Option<DateTime<Utc>>,
+    // Last refresh that reached the server (a download or a 304)
+    pub last_success_at: Option<DateTime<Utc>>,
+    // Why the last refresh failed; cleared by the next success
+    pub last_error: Option<String>,
+    // Size of the list currently in effect
+    pub size_bytes: u64,
+    // Domains in the stored set
+    pub domain_count: usize,
+}
+
+// Only plain http(s) URLs can be subscribed to
+pub fn validate_url(url: &str) -> Result<String, String> {
+    let parsed = reqwest::Url::parse(url.trim()).map_err(|e| format!("Invalid URL {}: {}", url.trim(), e))?;
+    if parsed.scheme() != "http" && parsed.scheme() != "https" {
+        return Err(format!("Only http and https sources are supported: {}", url.trim()));
+    }
+    if parsed.host_str().is_none() {
+        return Err(format!("URL has no host: {}", url.trim()));
+    }
+    Ok(parsed.to_string())
+}
+
+// Skipped lines included in a refresh result
+const MAX_REPORTED_SKIPPED: usize = 100;
+
+// Largest list body accepted from a source
+const MAX_DOWNLOAD_BYTES: usize = 64 * 1024 * 1024;
+
+// Raw bodies and validators of the last good downloads, one pair of files per source
+pub struct SourceCache {
+    dir: PathBuf,
+}
+
+// Sidecar saved next to a cached body
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedMeta {
+    // The URL the body was downloaded from; a cache entry for another URL is ignored
+    pub url: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub fetched_at: DateTime<Utc>,
+    pub size_bytes: u64,
+    // The source format the body was parsed with
+    pub format: ListFormat,
+}
+
+impl SourceCache {
+    pub fn new(dir: impl Into<PathBuf>) -> SourceCache {
+        SourceCache { dir: dir.into() }
+    }
+
+    fn body_path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.body", id))
+    }
+
+    fn meta_path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", id))
+    }
+
+    // Validators of the cached copy of `source`, if its body is still on disk
+    pub fn meta(&self, source: &Source) -> Option<CachedMeta> {
+        let content = fs::read_to_string(self.meta_path(&source.id)).ok()?;
+        let meta: CachedMeta = serde_json::from_str(&content).ok()?;
+        if meta.url != source.url || !self.body_path(&source.id).exists() {
+            return None;
+        }
+        Some(meta)
+    }
+
+    pub fn body(&self, source: &Source) -> Result<Vec<u8>, String> {
+        let path = self.body_path(&source.id);
+        fs::read(&path).map_err(|e| format!("Failed to read cached copy {}: {}", path.display(), e))
+    }
+
+    // Save a downloaded body and its validators; the body is written first so a
+    // sidecar never points at a missing or partial body
+    pub fn save(&self, id: &str, body: &[u8], meta: &CachedMeta) -> Result<(), String> {
+        fs::create_dir_all(&self.dir)
+            .map_err(|e| format!("Failed to create {}: {}", self.dir.display(), e))?;
+        atomic::write_atomic(&self.body_path(id), body, None)?;
+        let meta = serde_json::to_vec_pretty(meta).map_err(|e| format!("Failed to encode cache metadata: {}", e))?;
+        atomic::write_atomic(&self.meta_path(id), &meta, None)
+    }
+
+    pub fn remove(&self, id: &str) {
+        let _ = fs::remove_file(self.meta_path(id));
+        let _ = fs::remove_file(self.body_path(id));
+    }
+}
+
+// Cache in the configured directory
+pub fn cache() -> SourceCache {
+    SourceCache::new(config::current().sources_cache_dir())
+}
+
+static CLIENT: Lazy<reqwest::Client> = Lazy::new(|| {
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(60))
+        .user_agent(concat!("HostDNI/", env!("CARGO_PKG_VERSION")))
+        .build()
+        .expect("failed to build HTTP client")
+});
+
+pub enum FetchResult {
+    // 304: the cached copy is still current
+    NotModified,
+    Fetched {
+        body: Vec<u8>,
+        etag: Option<String>,
+        last_modified: Option<String>,
+    },
+}
+
+// Download a source body, conditionally when validators from a cached copy are given.
+// Fails when the body is larger than `max_bytes`.
+pub async fn fetch(url: &str, cached: Option<&CachedMeta>, max_bytes: usize) -> Result<FetchResult, String> {
+    let mut request = CLIENT.get(url);
+    if let Some(cached) = cached {
+        if let Some(etag) = &cached.etag {
+            request = request.header(reqwest::header::IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &cached.last_modified {
+            request = request.header(reqwest::header::IF_MODIFIED_SINCE, last_modified);
+        }
+    }
+
+    let mut response = request
+        .send()
+        .await
+        .map_err(|e| format!("Failed to fetch {}: {}", url, e))?;
+    if response.status() == reqwest::StatusCode::NOT_MODIFIED {
+        return Ok(FetchResult::NotModified);
+    }
+    if !response.status().is_success() {
+        return Err(format!("Failed to fetch {}: HTTP {}", url, response.status()));
+    }
+
+    let header = |name: reqwest::header::HeaderName| {
+        response.headers().get(name).and_then(|value| value.to_str().ok()).map(str::to_string)
+    };
+    let etag = header(reqwest::header::ETAG);
+    let last_modified = header(reqwest::header::LAST_MODIFIED);
+    let too_large = || format!("Failed to fetch {}: the list is larger than {} bytes", url, max_bytes);
+    if response.content_length().is_some_and(|length| length > max_bytes as u64) {
+        return Err(too_large());
+    }
+
+    // Read in chunks so a server that sends no length (or lies about it) is cut off too
+    let mut body = Vec::new();
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|e| format!("Failed to read {}: {}", url, e))?
+    {
+        if body.len() + chunk.len() > max_bytes {
+            return Err(too_large());
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(FetchResult::Fetched { body, etag, last_modified })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RefreshOutcome {
+    // A new copy was downloaded and parsed
+    Updated,
+    // The server answered 304; the stored domains are current (re-parsed if the format changed)
+    NotModified,
+    // The download failed; the last good copy stays in effect
+    CachedCopy,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RefreshResult {
+    pub source: Source,
+    pub outcome: RefreshOutcome,
+    // How the list was parsed; None when nothing was parsed (a 304 with an unchanged format)
+    pub parse: Option<ParseSummary>,
+}
+
+// Fetch a source and bring its stored domain set and freshness up to date.
+// Fails only when the download fails and there is no cached copy to fall back on.
+pub async fn refresh(store: &Store, cache: &SourceCache, source: &Source) -> Result<RefreshResult, String> {
+    let attempted_at = Utc::now();
+    let cached = cache.meta(source);
+
+    let mut parse = None;
+    let outcome = match fetch(&source.url, cached.as_ref(), MAX_DOWNLOAD_BYTES).await {
+        Ok(FetchResult::Fetched { body, etag, last_modified }) => {
+            let meta = CachedMeta {
+                url: source.url.clone(),
+                etag,
+                last_modified,
+                fetched_at: attempted_at,
+                size_bytes: body.len() as u64,
+                format: source.format,
+            };
+            let parsed = formats::parse(&String::from_utf8_lossy(&body), source.format);
+            // Without a cached copy the next refresh just downloads in full again
+            if let Err(e) = cache.save(&source.id, &body, &meta) {
+                println!("[HostDNI] {}", e);
+            }
+            store.replace_source_domains(&source.id, &parsed.domains, meta.size_bytes)?;
+            store.record_source_success(&source.id, attempted_at)?;
+            parse = Some(parsed.summary(MAX_REPORTED_SKIPPED));
+            RefreshOutcome::Updated
+        }
+        Ok(FetchResult::NotModified) => {
+            // The stored domains were parsed in a format the source no longer has
+            if let Some(meta) = cached.as_ref().filter(|meta| meta.format != source.format) {
+                let body = cache.body(source)?;
+                let parsed = formats::parse(&String::from_utf8_lossy(&body), source.format);
+                let meta = CachedMeta { format: source.format, ..meta.clone() };
+                if let Err(e) = cache.save(&source.id, &body, &meta) {
+                    println!("[HostDNI] {}", e);
+                }
+                store.replace_source_domains(&source.id, &parsed.domains, body.len() as u64)?;
+                parse = Some(parsed.summary(MAX_REPORTED_SKIPPED));
+            }
+            store.record_source_success(&source.id, attempted_at)?;
+            RefreshOutcome::NotModified
+        }
+        Err(e) => {
+            store.record_source_failure(&source.id, attempted_at, &e)?;
+            if cached.is_none() {
+                return Err(e);
+            }
+            let body = cache.body(source)?;
+            let parsed = formats::parse(&String::from_utf8_lossy(&body), source.format);
+            store.replace_source_domains(&source.id, &parsed.domains, body.len() as u64)?;
+            parse = Some(parsed.summary(MAX_REPORTED_SKIPPED));
+            RefreshOutcome::CachedCopy
+        }
+    };
+
+    let source = store
+        .get_source(&source.id)?
+        .ok_or_else(|| format!("Source {} was removed during refresh", source.id))?;
+    Ok(RefreshResult { source, outcome, parse })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+
+    // Serve canned HTTP responses (status, extra headers, body) on a local port, one
+    // per connection. Returns the URL and a receiver for the requests the server saw.
+    fn serve(responses: &'static [(&'static str, &'static str, &'static str)]) -> (String, mpsc::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/list.txt", listener.local_addr().unwrap());
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            for (status, headers, body) in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = [0u8; 4096];
+                let read = stream.read(&mut request).unwrap_or(0);
+                let _ = sender.send(String::from_utf8_lossy(&request[..read]).to_string());
+                let response = format!(
+                    "HTTP/1.1 {}\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    headers,
+                    body.len(),
+                    body
+                );
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        (url, receiver)
+    }
+
+    // A URL whose server closes every connection without answering. The listener stays
+    // open for the whole run, so no other test can be handed its port.
+    fn unreachable_url() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/list.txt", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                drop(stream);
+            }
+        });
+        url
+    }
+
+    fn scratch_cache() -> SourceCache {
+        SourceCache::new(std::env::temp_dir().join(format!("hostdni-test-sources-{}", uuid::Uuid::new_v4().simple())))
+    }
+
+    // Remove the scratch cache directory, which only exists once something was cached
+    fn remove_cache(cache: &SourceCache) {
+        if cache.dir.exists() {
+            fs::remove_dir_all(&cache.dir).unwrap();
+        }
+    }
+
+    fn source(url: String) -> Source {
+        Source {
+            id: "source-1".to_string(),
+            name: "Test list".to_string(),
+            url,
+            enabled: true,
+            kind: SourceKind::Block,
+            format: ListFormat::Auto,
+            created_at: Utc::now(),
+            last_attempt_at: None,
+            last_success_at: None,
+            last_error: None,
+            size_bytes: 0,
+            domain_count: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn refresh_stores_parsed_domains_and_freshness() {
+        let store = Store::open_in_memory().unwrap();
+        let cache = scratch_cache();
+        const BODY: &str = "0.0.0.0 ads.example.com\n0.0.0.0 tracker.example.net\n";
+        let (url, _) = serve(&[("200 OK", "ETag: \"v1\"\r\n", BODY)]);
+        let source = source(url);
+        store.insert_source(&source).unwrap();
+
+        let result = refresh(&store, &cache, &source).await.unwrap();
+        assert_eq!(result.outcome, RefreshOutcome::Updated);
+        assert_eq!(result.parse.unwrap().format, ListFormat::Hosts);
+        assert_eq!(result.source.domain_count, 2);
+        assert_eq!(result.source.size_bytes, BODY.len() as u64);
+        assert!(result.source.last_success_at.is_some());
+        assert_eq!(cache.meta(&source).unwrap().etag.as_deref(), Some("\"v1\""));
+        remove_cache(&cache);
+    }
+
+    #[tokio::test]
+    async fn refresh_sends_validators_and_accepts_not_modified() {
+        let store = Store::open_in_memory().unwrap();
+        let cache = scratch_cache();
+        let (url, requests) = serve(&[
+            ("200 OK", "ETag: \"v1\"\r\nLast-Modified: Wed, 21 Oct 2015 07:28:00 GMT\r\n", "ads.example.com\n"),
+            ("304 Not Modified", "", ""),
+        ]);
+        let source = source(url);
+        store.insert_source(&source).unwrap();
+        refresh(&store, &cache, &source).await.unwrap();
+
+        let result = refresh(&store, &cache, &source).await.unwrap();
+        assert!(!requests.recv().unwrap().to_ascii_lowercase().contains("if-none-match"));
+        let request = requests.recv().unwrap().to_ascii_lowercase();
+        assert!(request.contains("if-none-match: \"v1\""));
+        assert!(request.contains("if-modified-since: wed, 21 oct 2015 07:28:00 gmt"));
+        assert_eq!(result.outcome, RefreshOutcome::NotModified);
+        assert_eq!(result.source.domain_count, 1);
+        assert!(result.source.last_error.is_none());
+        assert!(result.parse.is_none());
+        remove_cache(&cache);
+    }
+
+    #[tokio::test]
+    async fn failed_refresh_falls_back_to_cached_copy() {
+        let cache = scratch_cache();
+        let (url, _) = serve(&[
+            ("200 OK", "", "ads.example.com\ntracker.example.net\n"),
+            ("500 Internal Server Error", "", ""),
+        ]);
+        let source = source(url);
+        let first = Store::open_in_memory().unwrap();
+        first.insert_source(&source).unwrap();
+        refresh(&first, &cache, &source).await.unwrap();
+
+        // A fresh database and a failing server: the cached body is used
+        let store = Store::open_in_memory().unwrap();
+        store.insert_source(&source).unwrap();
+        let result = refresh(&store, &cache, &source).await.unwrap();
+        assert_eq!(result.outcome, RefreshOutcome::CachedCopy);
+        assert_eq!(result.source.domain_count, 2);
+        assert_eq!(result.source.size_bytes, cache.meta(&source).unwrap().size_bytes);
+        assert!(result.source.last_error.unwrap().contains("500"));
+        assert!(result.source.last_success_at.is_none());
+        remove_cache(&cache);
+    }
+
+    #[tokio::test]
+    async fn fetch_refuses_bodies_over_the_size_limit() {
+        let (url, _) = serve(&[("200 OK", "", "ads.example.com\n"), ("200 OK", "", "ads.example.com\n")]);
+        let error = fetch(&url, None, 8).await.err().unwrap();
+        assert!(error.contains("larger than 8 bytes"));
+        assert!(matches!(fetch(&url, None, 16).await.unwrap(), FetchResult::Fetched { body, .. } if body.len() == 16));
+    }
+
+    #[tokio::test]
+    async fn failed_refresh_without_cache_keeps_previous_domains() {
+        let store = Store::open_in_memory().unwrap();
+        let (url, _) = serve(&[("200 OK", "", "ads.example.com\n")]);
+        let source = source(url);
+        store.insert_source(&source).unwrap();
+        let cache = scratch_cache();
+        refresh(&store, &cache, &source).await.unwrap();
+
+        let broken = Source { url: unreachable_url(), ..source.clone() };
+        let empty = scratch_cache();
+        assert!(refresh(&store, &empty, &broken).await.is_err());
+        let stored = store.get_source(&source.id).unwrap().unwrap();
+        assert_eq!(stored.domain_count, 1);
+        assert!(stored.last_error.is_some());
+        remove_cache(&cache);
+        remove_cache(&empty);
+    }
+
+    #[tokio::test]
+    async fn not_modified_reparses_the_cached_copy_after_a_format_change() {
+        let store = Store::open_in_memory().unwrap();
+        let cache = scratch_cache();
+        let (url, _) = serve(&[
+            ("200 OK", "ETag: \"v1\"\r\n", "0.0.0.0 ads.example.com\n0.0.0.0 tracker.example.net\n"),
+            ("304 Not Modified", "", ""),
+            ("304 Not Modified", "", ""),
+        ]);
+        let source = source(url);
+        store.insert_source(&source).unwrap();
+        assert_eq!(refresh(&store, &cache, &source).await.unwrap().source.domain_count, 2);
+
+        // As domains, every hosts line is malformed
+        let changed = Source { format: ListFormat::Domains, ..source.clone() };
+        store.update_source(&changed).unwrap();
+        let result = refresh(&store, &cache, &changed).await.unwrap();
+        assert_eq!(result.outcome, RefreshOutcome::NotModified);
+        assert_eq!(result.parse.unwrap().format, ListFormat::Domains);
+        assert_eq!(result.source.domain_count, 0);
+        assert_eq!(cache.meta(&changed).unwrap().format, ListFormat::Domains);
+
+        // Parsed once; the next 304 keeps the stored domains
+        assert!(refresh(&store, &cache, &changed).await.unwrap().parse.is_none());
+        remove_cache(&cache);
+    }
+}
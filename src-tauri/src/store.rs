@@ -0,0 +1,577 @@
+// Embedded storage for HostDNI
+// - SQLite database at ~/.hostdni/hostdni.db (HOSTDNI_DB_PATH overrides)
+// - Owns allow-list entries, block-list entries and source subscriptions
+// - Schema is versioned with PRAGMA user_version; pending MIGRATIONS run in order at open
+// - One connection behind a mutex, shared by the REST API and the Tauri commands
+
+use crate::config;
+use crate::formats::ListFormat;
+use crate::sources::{Source, SourceKind};
+use chrono::{DateTime, Utc};
+use once_cell::sync::Lazy;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+// Schema migrations; entry N upgrades the database from user_version N to N + 1.
+// Append only: never edit a migration that has shipped.
+const MIGRATIONS: &[&str] = &[
+    // 1: initial schema
+    "CREATE TABLE allow_lists (
+        id TEXT PRIMARY KEY,
+        pattern TEXT NOT NULL UNIQUE,
+        description TEXT,
+        enabled INTEGER NOT NULL DEFAULT 1,
+        created_at TEXT NOT NULL
+    );
+    CREATE TABLE block_lists (
+        id TEXT PRIMARY KEY,
+        pattern TEXT NOT NULL UNIQUE,
+        description TEXT,
+        enabled INTEGER NOT NULL DEFAULT 1,
+        created_at TEXT NOT NULL
+    );
+    CREATE TABLE sources (
+        id TEXT PRIMARY KEY,
+        name TEXT NOT NULL,
+        url TEXT NOT NULL UNIQUE,
+        kind TEXT NOT NULL,
+        format TEXT NOT NULL DEFAULT 'auto',
+        enabled INTEGER NOT NULL DEFAULT 1,
+        created_at TEXT NOT NULL,
+        last_success_at TEXT,
+        last_attempt_at TEXT,
+        last_error TEXT,
+        domain_count INTEGER NOT NULL DEFAULT 0,
+        size_bytes INTEGER NOT NULL DEFAULT 0
+    );
+    CREATE TABLE source_domains (
+        source_id TEXT NOT NULL REFERENCES sources (id) ON DELETE CASCADE,
+        domain TEXT NOT NULL,
+        PRIMARY KEY (source_id, domain)
+    ) WITHOUT ROWID;",
+];
+
+// Allow- and block-list entries share one shape
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ListEntry {
+    pub id: String,
+    pub pattern: String,
+    pub description: Option<String>,
+    pub enabled: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+// Filters and page for `Store::query_entries`
+#[derive(Debug, Clone, Default)]
+pub struct ListQuery {
+    // Case-insensitive substring of the pattern or description
+    pub search: Option<String>,
+    pub enabled: Option<bool>,
+    pub offset: usize,
+    pub limit: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ListKind {
+    Allow,
+    Block,
+}
+
+impl ListKind {
+    fn table(self) -> &'static str {
+        match self {
+            ListKind::Allow => "allow_lists",
+            ListKind::Block => "block_lists",
+        }
+    }
+}
+
+fn list_entry_from_row(row: &Row) -> rusqlite::Result<ListEntry> {
+    Ok(ListEntry {
+        id: row.get("id")?,
+        pattern: row.get("pattern")?,
+        description: row.get("description")?,
+        enabled: row.get("enabled")?,
+        created_at: row.get("created_at")?,
+    })
+}
+
+fn source_from_row(row: &Row) -> rusqlite::Result<Source> {
+    let kind: String = row.get("kind")?;
+    let format: String = row.get("format")?;
+    Ok(Source {
+        id: row.get("id")?,
+        name: row.get("name")?,
+        url: row.get("url")?,
+        enabled: row.get("enabled")?,
+        kind: SourceKind::parse(&kind).unwrap_or(SourceKind::Block),
+        format: ListFormat::parse(&format).unwrap_or_default(),
+        created_at: row.get("created_at")?,
+        last_attempt_at: row.get("last_attempt_at")?,
+        last_success_at: row.get("last_success_at")?,
+        last_error: row.get("last_error")?,
+        size_bytes: row.get::<_, i64>("size_bytes")? as u64,
+        domain_count: row.get::<_, i64>("domain_count")? as usize,
+    })
+}
+
+fn db_error(e: rusqlite::Error) -> String {
+    format!("Database error: {}", e)
+}
+
+pub struct Store {
+    conn: Mutex<Connection>,
+}
+
+impl Store {
+    pub fn open(path: &Path) -> Result<Store, String> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        }
+        let conn = Connection::open(path)
+            .map_err(|e| format!("Failed to open database {}: {}", path.display(), e))?;
+        Store::with_connection(conn)
+    }
+
+    pub fn open_in_memory() -> Result<Store, String> {
+        Store::with_connection(Connection::open_in_memory().map_err(db_error)?)
+    }
+
+    fn with_connection(mut conn: Connection) -> Result<Store, String> {
+        conn.pragma_update(None, "journal_mode", "WAL").map_err(db_error)?;
+        conn.pragma_update(None, "foreign_keys", true).map_err(db_error)?;
+        migrate(&mut conn)?;
+        Ok(Store { conn: Mutex::new(conn) })
+    }
+
+    // ----- Allow and block lists -----
+
+    // One page of entries matching `query`, plus the total number of matches
+    pub fn query_entries(&self, kind: ListKind, query: &ListQuery) -> Result<(Vec<ListEntry>, usize), String> {
+        let search = query.search.as_deref().filter(|search| !search.is_empty()).map(|search| {
+            let escaped = search.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+            format!("%{}%", escaped)
+        });
+        let filter = "(?1 IS NULL OR pattern LIKE ?1 ESCAPE '\\' OR description LIKE ?1 ESCAPE '\\')
+            AND (?2 IS NULL OR enabled = ?2)";
+
+        let conn = self.conn.lock().unwrap();
+        let total = conn
+            .query_row(
+                &format!("SELECT COUNT(*) FROM {} WHERE {}", kind.table(), filter),
+                params![search, query.enabled],
+                |row| row.get::<_, i64>(0),
+            )
+            .map_err(db_error)? as usize;
+        let mut statement = conn
+            .prepare(&format!(
+                "SELECT * FROM {} WHERE {} ORDER BY created_at, id LIMIT ?3 OFFSET ?4",
+                kind.table(),
+                filter
+            ))
+            .map_err(db_error)?;
+        let entries = statement
+            .query_map(
+                params![search, query.enabled, query.limit as i64, query.offset as i64],
+                list_entry_from_row,
+            )
+            .and_then(|rows| rows.collect::<rusqlite::Result<Vec<_>>>())
+            .map_err(db_error)?;
+        Ok((entries, total))
+    }
+
+    pub fn get_entry(&self, kind: ListKind, id: &str) -> Result<Option<ListEntry>, String> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            &format!("SELECT * FROM {} WHERE id = ?1", kind.table()),
+            params![id],
+            list_entry_from_row,
+        )
+        .optional()
+        .map_err(db_error)
+    }
+
+    // Whether another entry (other than `except_id`) already has `pattern`
+    pub fn pattern_exists(&self, kind: ListKind, pattern: &str, except_id: Option<&str>) -> Result<bool, String> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            &format!("SELECT EXISTS (SELECT 1 FROM {} WHERE pattern = ?1 AND id IS NOT ?2)", kind.table()),
+            params![pattern, except_id],
+            |row| row.get(0),
+        )
+        .map_err(db_error)
+    }
+
+    // Insert entries in one transaction; all or nothing
+    pub fn insert_entries(&self, kind: ListKind, entries: &[ListEntry]) -> Result<(), String> {
+        let mut conn = self.conn.lock().unwrap();
+        let transaction = conn.transaction().map_err(db_error)?;
+        {
+            let mut statement = transaction
+                .prepare(&format!(
+                    "INSERT INTO {} (id, pattern, description, enabled, created_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+                    kind.table()
+                ))
+                .map_err(db_error)?;
+            for entry in entries {
+                statement
+                    .execute(params![entry.id, entry.pattern, entry.description, entry.enabled, entry.created_at])
+                    .map_err(db_error)?;
+            }
+        }
+        transaction.commit().map_err(db_error)
+    }
+
+    // Save changes to an existing entry; false if it does not exist
+    pub fn update_entry(&self, kind: ListKind, entry: &ListEntry) -> Result<bool, String> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            &format!("UPDATE {} SET pattern = ?2, description = ?3, enabled = ?4 WHERE id = ?1", kind.table()),
+            params![entry.id, entry.pattern, entry.description, entry.enabled],
+        )
+        .map(|changed| changed > 0)
+        .map_err(db_error)
+    }
+
+    // Remove an entry; false if it does not exist
+    pub fn delete_entry(&self, kind: ListKind, id: &str) -> Result<bool, String> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(&format!("DELETE FROM {} WHERE id = ?1", kind.table()), params![id])
+            .map(|changed| changed > 0)
+            .map_err(db_error)
+    }
+
+    pub fn count_entries(&self, kind: ListKind) -> Result<usize, String> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(&format!("SELECT COUNT(*) FROM {}", kind.table()), [], |row| row.get::<_, i64>(0))
+            .map(|count| count as usize)
+            .map_err(db_error)
+    }
+
+    // Patterns of every enabled entry, in insertion order
+    pub fn enabled_patterns(&self, kind: ListKind) -> Result<Vec<String>, String> {
+        let conn = self.conn.lock().unwrap();
+        let mut statement = conn
+            .prepare(&format!("SELECT pattern FROM {} WHERE enabled = 1 ORDER BY created_at, id", kind.table()))
+            .map_err(db_error)?;
+        let patterns = statement
+            .query_map([], |row| row.get(0))
+            .and_then(|rows| rows.collect::<rusqlite::Result<Vec<String>>>())
+            .map_err(db_error)?;
+        Ok(patterns)
+    }
+}
+
+impl Store {
+    // ----- Sources -----
+
+    pub fn list_sources(&self) -> Result<Vec<Source>, String> {
+        let conn = self.conn.lock().unwrap();
+        let mut statement = conn
+            .prepare("SELECT * FROM sources ORDER BY created_at, id")
+            .map_err(db_error)?;
+        let sources = statement
+            .query_map([], source_from_row)
+            .and_then(|rows| rows.collect::<rusqlite::Result<Vec<_>>>())
+            .map_err(db_error)?;
+        Ok(sources)
+    }
+
+    pub fn get_source(&self, id: &str) -> Result<Option<Source>, String> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row("SELECT * FROM sources WHERE id = ?1", params![id], source_from_row)
+            .optional()
+            .map_err(db_error)
+    }
+
+    // Whether another source (other than `except_id`) already uses `url`
+    pub fn source_url_exists(&self, url: &str, except_id: Option<&str>) -> Result<bool, String> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM sources WHERE url = ?1 AND id IS NOT ?2)",
+            params![url, except_id],
+            |row| row.get(0),
+        )
+        .map_err(db_error)
+    }
+
+    pub fn insert_source(&self, source: &Source) -> Result<(), String> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO sources (id, name, url, kind, format, enabled, created_at, last_attempt_at, last_success_at,
+                                  last_error, size_bytes, domain_count)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+            params![
+                source.id,
+                source.name,
+                source.url,
+                source.kind.as_str(),
+                source.format.as_str(),
+                source.enabled,
+                source.created_at,
+                source.last_attempt_at,
+                source.last_success_at,
+                source.last_error,
+                source.size_bytes as i64,
+                source.domain_count as i64
+            ],
+        )
+        .map(|_| ())
+        .map_err(db_error)
+    }
+
+    // Save the editable fields of a source; false if it does not exist
+    pub fn update_source(&self, source: &Source) -> Result<bool, String> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE sources SET name = ?2, url = ?3, kind = ?4, format = ?5, enabled = ?6 WHERE id = ?1",
+            params![
+                source.id,
+                source.name,
+                source.url,
+                source.kind.as_str(),
+                source.format.as_str(),
+                source.enabled
+            ],
+        )
+        .map(|changed| changed > 0)
+        .map_err(db_error)
+    }
+
+    // Remove a source and its domains; false if it does not exist
+    pub fn delete_source(&self, id: &str) -> Result<bool, String> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM sources WHERE id = ?1", params![id])
+            .map(|changed| changed > 0)
+            .map_err(db_error)
+    }
+
+    pub fn source_domains(&self, id: &str) -> Result<BTreeSet<String>, String> {
+        let conn = self.conn.lock().unwrap();
+        let mut statement = conn
+            .prepare("SELECT domain FROM source_domains WHERE source_id = ?1")
+            .map_err(db_error)?;
+        let domains = statement
+            .query_map(params![id], |row| row.get(0))
+            .and_then(|rows| rows.collect::<rusqlite::Result<BTreeSet<String>>>())
+            .map_err(db_error)?;
+        Ok(domains)
+    }
+
+    // Replace the stored domain set of a source; `size_bytes` is the size of the list it was parsed from
+    pub fn replace_source_domains(&self, id: &str, domains: &BTreeSet<String>, size_bytes: u64) -> Result<(), String> {
+        let mut conn = self.conn.lock().unwrap();
+        let transaction = conn.transaction().map_err(db_error)?;
+        transaction
+            .execute("DELETE FROM source_domains WHERE source_id = ?1", params![id])
+            .map_err(db_error)?;
+        {
+            let mut statement = transaction
+                .prepare("INSERT INTO source_domains (source_id, domain) VALUES (?1, ?2)")
+                .map_err(db_error)?;
+            for domain in domains {
+                statement.execute(params![id, domain]).map_err(db_error)?;
+            }
+        }
+        transaction
+            .execute(
+                "UPDATE sources SET domain_count = ?2, size_bytes = ?3 WHERE id = ?1",
+                params![id, domains.len() as i64, size_bytes as i64],
+            )
+            .map_err(db_error)?;
+        transaction.commit().map_err(db_error)
+    }
+
+    // A refresh reached the server (a new download or a 304)
+    pub fn record_source_success(&self, id: &str, at: DateTime<Utc>) -> Result<(), String> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE sources SET last_attempt_at = ?2, last_success_at = ?2, last_error = NULL WHERE id = ?1",
+            params![id, at],
+        )
+        .map(|_| ())
+        .map_err(db_error)
+    }
+
+    pub fn record_source_failure(&self, id: &str, at: DateTime<Utc>, error: &str) -> Result<(), String> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE sources SET last_attempt_at = ?2, last_error = ?3 WHERE id = ?1",
+            params![id, at, error],
+        )
+        .map(|_| ())
+        .map_err(db_error)
+    }
+}
+
+// Bring the schema up to date, one transaction per migration
+fn migrate(conn: &mut Connection) -> Result<(), String> {
+    let version: usize = conn
+        .pragma_query_value(None, "user_version", |row| row.get::<_, i64>(0))
+        .map_err(db_error)? as usize;
+    if version > MIGRATIONS.len() {
+        return Err(format!(
+            "Database schema version {} is newer than this version of HostDNI supports ({})",
+            version,
+            MIGRATIONS.len()
+        ));
+    }
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let transaction = conn.transaction().map_err(db_error)?;
+        transaction.execute_batch(migration).map_err(db_error)?;
+        transaction
+            .pragma_update(None, "user_version", (index + 1) as i64)
+            .map_err(db_error)?;
+        transaction.commit().map_err(db_error)?;
+        println!("[HostDNI] Database migrated to schema version {}", index + 1);
+    }
+    Ok(())
+}
+
+pub fn db_path() -> PathBuf {
+    std::env::var_os("HOSTDNI_DB_PATH")
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| config::expand_home("~/.hostdni/hostdni.db"))
+}
+
+static STORE: Lazy<Store> = Lazy::new(|| {
+    let path = db_path();
+    Store::open(&path).unwrap_or_else(|e| {
+        println!("[HostDNI] {}; falling back to an in-memory database", e);
+        Store::open_in_memory().expect("failed to open in-memory database")
+    })
+});
+
+// The process-wide store
+pub fn store() -> &'static Store {
+    &STORE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user_version(conn: &Connection) -> usize {
+        conn.pragma_query_value(None, "user_version", |row| row.get::<_, i64>(0)).unwrap() as usize
+    }
+
+    fn entry(pattern: &str, description: Option<&str>, enabled: bool, second: u32) -> ListEntry {
+        ListEntry {
+            id: format!("id-{}", second),
+            pattern: pattern.to_string(),
+            description: description.map(str::to_string),
+            enabled,
+            created_at: format!("2024-01-01T00:00:{:02}Z", second).parse().unwrap(),
+        }
+    }
+
+    fn patterns(store: &Store, query: ListQuery) -> (Vec<String>, usize) {
+        let (entries, total) = store.query_entries(ListKind::Block, &query).unwrap();
+        (entries.into_iter().map(|entry| entry.pattern).collect(), total)
+    }
+
+    fn search(store: &Store, search: &str) -> Vec<String> {
+        patterns(store, ListQuery { search: Some(search.to_string()), limit: 100, ..ListQuery::default() }).0
+    }
+
+    #[test]
+    fn migrates_an_empty_database_to_the_latest_schema() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        assert_eq!(user_version(&conn), 0);
+        migrate(&mut conn).unwrap();
+        assert_eq!(user_version(&conn), MIGRATIONS.len());
+
+        let tables: Vec<String> = conn
+            .prepare("SELECT name FROM sqlite_master WHERE type = 'table' ORDER BY name")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(tables, vec!["allow_lists", "block_lists", "source_domains", "sources"]);
+        conn.execute(
+            "INSERT INTO sources (id, name, url, kind, created_at) VALUES ('s', 'S', 'https://x', 'block', '')",
+            [],
+        )
+        .unwrap();
+        let (format, error): (String, Option<String>) = conn
+            .query_row("SELECT format, last_error FROM sources", [], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap();
+        assert_eq!((format.as_str(), error), ("auto", None));
+
+        // Idempotent once up to date
+        migrate(&mut conn).unwrap();
+        assert_eq!(user_version(&conn), MIGRATIONS.len());
+    }
+
+    #[test]
+    fn keeps_rows_across_reopens_and_rejects_duplicate_patterns() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn).unwrap();
+        conn.execute(
+            "INSERT INTO allow_lists (id, pattern, created_at) VALUES ('a', 'example.com', '2024-01-01T00:00:00Z')",
+            [],
+        )
+        .unwrap();
+
+        let store = Store::with_connection(conn).unwrap();
+        assert_eq!(store.get_entry(ListKind::Allow, "a").unwrap().unwrap().pattern, "example.com");
+        assert!(store.pattern_exists(ListKind::Allow, "example.com", None).unwrap());
+        let duplicate = entry("example.com", None, true, 1);
+        assert!(store.insert_entries(ListKind::Allow, std::slice::from_ref(&duplicate)).is_err());
+        store.insert_entries(ListKind::Block, std::slice::from_ref(&duplicate)).unwrap();
+        assert!(store.insert_entries(ListKind::Block, &[ListEntry { id: "b".to_string(), ..duplicate }]).is_err());
+    }
+
+    #[test]
+    fn refuses_databases_from_a_newer_version() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.pragma_update(None, "user_version", MIGRATIONS.len() as i64 + 1).unwrap();
+        assert!(migrate(&mut conn).unwrap_err().contains("newer"));
+    }
+
+    #[test]
+    fn search_matches_wildcard_characters_literally() {
+        let store = Store::open_in_memory().unwrap();
+        store
+            .insert_entries(
+                ListKind::Block,
+                &[
+                    entry("100percent.example", None, true, 1),
+                    entry("ads_tracker.example", None, true, 2),
+                    entry("adsxtracker.example", Some("50% off"), true, 3),
+                    entry("back.example", Some("C:\\path"), true, 4),
+                    entry("ADS.Example", None, true, 5),
+                ],
+            )
+            .unwrap();
+
+        assert_eq!(search(&store, "%"), vec!["adsxtracker.example"]);
+        assert_eq!(search(&store, "_"), vec!["ads_tracker.example"]);
+        assert_eq!(search(&store, "ads_t"), vec!["ads_tracker.example"]);
+        assert_eq!(search(&store, "\\"), vec!["back.example"]);
+        assert_eq!(search(&store, "ads.example"), vec!["ADS.Example"]);
+        assert_eq!(search(&store, "").len(), 5);
+    }
+
+    #[test]
+    fn pages_filtered_results_in_creation_order() {
+        let store = Store::open_in_memory().unwrap();
+        let entries: Vec<ListEntry> =
+            (0..7).map(|i| entry(&format!("host{}.example", i), None, i % 3 != 0, i)).collect();
+        store.insert_entries(ListKind::Block, &entries).unwrap();
+
+        let page = |offset, limit, enabled| patterns(&store, ListQuery { search: None, enabled, offset, limit });
+        assert_eq!(page(0, 3, None), (vec!["host0.example".into(), "host1.example".into(), "host2.example".into()], 7));
+        assert_eq!(page(6, 3, None), (vec!["host6.example".into()], 7));
+        assert_eq!(page(2, 2, Some(true)), (vec!["host4.example".into(), "host5.example".into()], 4));
+        assert_eq!(page(0, 10, Some(false)).1, 3);
+        assert_eq!(page(10, 10, None), (vec![], 7));
+    }
+}